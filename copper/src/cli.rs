@@ -0,0 +1,4 @@
+pub mod dns;
+pub mod http;
+
+pub type CliResult<T> = Result<T, String>;
@@ -0,0 +1,270 @@
+use super::CliResult;
+use copper::dns::CLOUDFLARE_DNS;
+use copper::dns::DnsPacket;
+use copper::dns::DnsTransport;
+use copper::dns::HttpsTransport;
+use copper::dns::QClass;
+use copper::dns::QType;
+use copper::dns::Resolver;
+use copper::dns::TcpTransport;
+use copper::dns::TlsTransport;
+use copper::dns::UdpTransport;
+use copper::dns::get_host_addr;
+use copper::dns::reverse_name;
+use copper::net::TcpConnector;
+use copper::net::TlsConnector;
+use copper::url::Url;
+use std::io;
+use std::io::Read;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+const DEFAULT_TLS_NAME: &str = "cloudflare-dns.com";
+const DEFAULT_DOH_URL: &str = "https://cloudflare-dns.com/dns-query";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Protocol {
+    Udp,
+    Tcp,
+    Tls(String),
+    Https(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    server: Option<IpAddr>,
+    port: Option<u16>,
+    name: Option<String>,
+    qtype: Option<QType>,
+    qclass: QClass,
+    protocol: Protocol,
+    short: bool,
+    hex: Option<String>,
+}
+
+impl Options {
+    /// Parses `dig`-style arguments. Like `dig`, bare words are taken as a
+    /// type or a class when they parse as one, and as the name otherwise.
+    fn parse(args: &[String]) -> CliResult<Self> {
+        let mut options = Self {
+            server: None,
+            port: None,
+            name: None,
+            qtype: None,
+            qclass: QClass::In,
+            protocol: Protocol::Udp,
+            short: false,
+            hex: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if let Some(server) = arg.strip_prefix('@') {
+                let (server, port) = parse_server(server)?;
+                options.server = Some(server);
+                options.port = port;
+            } else if let Some(option) = arg.strip_prefix('+') {
+                match option.split_once('=') {
+                    None if option == "tcp" => options.protocol = Protocol::Tcp,
+                    None if option == "tls" => {
+                        options.protocol = Protocol::Tls(DEFAULT_TLS_NAME.into())
+                    }
+                    Some(("tls", name)) => options.protocol = Protocol::Tls(name.into()),
+                    None if option == "https" => {
+                        options.protocol = Protocol::Https(DEFAULT_DOH_URL.into())
+                    }
+                    Some(("https", url)) => options.protocol = Protocol::Https(url.into()),
+                    None if option == "short" => options.short = true,
+                    _ => return Err(format!("unknown option +{}", option)),
+                }
+            } else if arg == "-x" {
+                let addr = args.next().ok_or("-x requires an address")?;
+                let addr: IpAddr = addr
+                    .parse()
+                    .map_err(|_| format!("invalid address {}", addr))?;
+                options.name = Some(reverse_name(addr));
+                options.qtype = Some(QType::Ptr);
+            } else if arg == "--hex" {
+                options.hex = Some(args.next().ok_or("--hex requires a packet")?.clone());
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option {}", arg));
+            } else if let (None, Ok(qtype)) = (options.qtype, arg.parse()) {
+                options.qtype = Some(qtype);
+            } else if let Ok(qclass) = arg.parse() {
+                options.qclass = qclass;
+            } else if options.name.is_none() {
+                options.name = Some(arg.clone());
+            } else {
+                return Err(format!("unexpected argument {}", arg));
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Parses `@server`, keeping the port unset when none is given so that each
+/// protocol can use its own default.
+fn parse_server(server: &str) -> CliResult<(IpAddr, Option<u16>)> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok((addr.ip(), Some(addr.port())));
+    }
+    let ip = server
+        .parse()
+        .map_err(|_| format!("invalid server {}", server))?;
+    Ok((ip, None))
+}
+
+/// Decodes a hex dump, ignoring whitespace and `:` separators.
+fn decode_hex(hex: &str) -> CliResult<Vec<u8>> {
+    let digits: Vec<u8> = hex
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace() && *byte != b':')
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".into());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| "invalid hex digit".to_string())
+        })
+        .collect()
+}
+
+pub fn run(args: &[String]) -> CliResult<()> {
+    let options = Options::parse(args)?;
+
+    if let Some(hex) = &options.hex {
+        let hex = if hex == "-" {
+            let mut hex = String::new();
+            io::stdin()
+                .read_to_string(&mut hex)
+                .map_err(|e| e.to_string())?;
+            hex
+        } else {
+            hex.clone()
+        };
+        let bytes = decode_hex(&hex)?;
+        let (packet, len) = DnsPacket::from_bytes(&bytes)?;
+        print_packet(&packet, &options);
+        if len < bytes.len() {
+            println!(";; {} trailing bytes ignored", bytes.len() - len);
+        }
+        return Ok(());
+    }
+
+    let ip = options.server.unwrap_or(CLOUDFLARE_DNS);
+    let server = SocketAddr::new(ip, options.port.unwrap_or(UdpTransport::PORT));
+    match &options.protocol {
+        Protocol::Udp => query(UdpTransport::with_addr(server), server, &options),
+        Protocol::Tcp => {
+            let transport =
+                TcpTransport::with_connector(server, &server.ip().to_string(), TcpConnector);
+            query(transport, server, &options)
+        }
+        Protocol::Tls(name) => {
+            let server = SocketAddr::new(ip, options.port.unwrap_or(TlsTransport::TLS_PORT));
+            let transport = TlsTransport::with_connector(server, name, TlsConnector::new());
+            query(transport, server, &options)
+        }
+        Protocol::Https(url) => {
+            // Without an explicit server, connect to the host of the URL
+            // rather than to the default UDP server.
+            let ip = match options.server {
+                Some(ip) => ip,
+                None => {
+                    let url = Url::new(url).map_err(|_| "invalid url")?;
+                    match url.hostname().parse() {
+                        Ok(ip) => ip,
+                        Err(_) => get_host_addr(url.hostname(), CLOUDFLARE_DNS)?,
+                    }
+                }
+            };
+            let transport = HttpsTransport::new(url, ip)?;
+            let server = transport.server();
+            query(transport, server, &options)
+        }
+    }
+}
+
+fn query<T: DnsTransport>(transport: T, server: SocketAddr, options: &Options) -> CliResult<()> {
+    let name = options.name.as_deref().unwrap_or("");
+    let qtype = match (&options.name, options.qtype) {
+        (_, Some(qtype)) => qtype,
+        (Some(_), None) => QType::A,
+        (None, None) => QType::Ns,
+    };
+
+    let resolver = Resolver::new(transport);
+    let start = Instant::now();
+    let response = resolver.query_with_class(name, qtype, options.qclass)?;
+    let elapsed = start.elapsed();
+
+    print_packet(&response, options);
+    if !options.short {
+        println!();
+        println!(";; Query time: {} msec", elapsed.as_millis());
+        println!(";; SERVER: {}", server);
+    }
+    Ok(())
+}
+
+fn print_packet(packet: &DnsPacket, options: &Options) {
+    if options.short {
+        for answer in packet.answers() {
+            println!("{}", answer.rdata());
+        }
+    } else {
+        print!("{}", packet);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use copper::dns::Class;
+
+    fn parse(args: &str) -> CliResult<Options> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse("@192.0.2.53 example.com mx ch +tcp +short").unwrap();
+        assert_eq!(options.server, Some("192.0.2.53".parse().unwrap()));
+        assert_eq!(options.port, None);
+        assert_eq!(options.name.as_deref(), Some("example.com"));
+        assert_eq!(options.qtype, Some(QType::Mx));
+        assert_eq!(options.qclass, Class::Ch);
+        assert_eq!(options.protocol, Protocol::Tcp);
+        assert!(options.short);
+
+        let options = parse("-x 192.0.2.1 @[2001:db8::53]:5353").unwrap();
+        assert_eq!(options.name.as_deref(), Some("1.2.0.192.in-addr.arpa"));
+        assert_eq!(options.qtype, Some(QType::Ptr));
+        assert_eq!(options.server, Some("2001:db8::53".parse().unwrap()));
+        assert_eq!(options.port, Some(5353));
+
+        let options = parse("@192.0.2.53:53 +tls").unwrap();
+        assert_eq!(options.port, Some(53));
+
+        let options = parse("example.com type65535 +tls=dns.example").unwrap();
+        assert_eq!(options.qtype, Some(QType::Unknown(65535)));
+        assert_eq!(options.protocol, Protocol::Tls("dns.example".into()));
+        assert!(parse("a.example b.example").is_err());
+        assert!(parse("+unknown").is_err());
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("0a ff:10\n").unwrap(), vec![0x0a, 0xff, 0x10]);
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+}
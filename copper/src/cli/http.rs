@@ -0,0 +1,285 @@
+use super::CliResult;
+use copper::dns::CLOUDFLARE_DNS;
+use copper::http::HttpRequest;
+use copper::http::HttpResponse;
+use copper::http::MAX_REDIRECTS;
+use copper::http::fetch_blocking;
+use copper::url::Url;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    url: String,
+    method: Option<String>,
+    headers: Vec<(String, String)>,
+    data: Option<Vec<u8>>,
+    include: bool,
+    location: bool,
+    output: Option<String>,
+    verbose: bool,
+    dns: IpAddr,
+}
+
+impl Options {
+    /// Options that take a value.
+    const VALUE_OPTIONS: [&str; 9] = [
+        "-X",
+        "--request",
+        "-H",
+        "--header",
+        "-d",
+        "--data",
+        "-o",
+        "--output",
+        "--dns",
+    ];
+
+    /// Parses curl-style arguments. Short options can be clustered, as in
+    /// `-iL`, and the last one may take a value, as in `-Lo file`.
+    fn parse(args: &[String]) -> CliResult<Self> {
+        let mut url = None;
+        let mut options = Self {
+            url: String::new(),
+            method: None,
+            headers: Vec::new(),
+            data: None,
+            include: false,
+            location: false,
+            output: None,
+            verbose: false,
+            dns: CLOUDFLARE_DNS,
+        };
+
+        let args = split_short_options(args);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |option: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} requires a value", option))
+            };
+            match arg.as_str() {
+                "-X" | "--request" => options.method = Some(value(arg)?),
+                "-H" | "--header" => {
+                    let header = value(arg)?;
+                    let (name, value) = header
+                        .split_once(':')
+                        .ok_or_else(|| format!("invalid header {}", header))?;
+                    options
+                        .headers
+                        .push((name.trim().into(), value.trim().into()));
+                }
+                "-d" | "--data" => options.data = Some(read_data(&value(arg)?)?),
+                "-i" | "--include" => options.include = true,
+                "-L" | "--location" => options.location = true,
+                "-o" | "--output" => options.output = Some(value(arg)?),
+                "-v" | "--verbose" => options.verbose = true,
+                "--dns" => {
+                    let dns = value(arg)?;
+                    options.dns = dns
+                        .parse()
+                        .map_err(|_| format!("invalid dns server {}", dns))?;
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+                _ if url.is_none() => url = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+
+        options.url = url.ok_or("no url given")?;
+        Ok(options)
+    }
+
+    fn request(&self) -> CliResult<HttpRequest> {
+        let url = Url::new(&self.url)?;
+        let method = match (&self.method, &self.data) {
+            (Some(method), _) => method.as_str(),
+            (None, Some(_)) => "POST",
+            (None, None) => "GET",
+        };
+
+        let mut request = HttpRequest::new(method, url);
+        if let Some(data) = &self.data {
+            request.push_header(
+                "Content-Type".into(),
+                "application/x-www-form-urlencoded".into(),
+            );
+            request.set_content(data);
+        }
+        for (name, value) in &self.headers {
+            request.push_header(name.clone(), value.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Splits clusters of short options into separate arguments. The rest of a
+/// cluster after an option that takes a value is that value, as in `-ofile`.
+fn split_short_options(args: &[String]) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(cluster) = arg
+            .strip_prefix('-')
+            .filter(|cluster| 1 < cluster.len() && !cluster.starts_with('-') && cluster.is_ascii())
+        else {
+            split.push(arg.clone());
+            if Options::VALUE_OPTIONS.contains(&arg.as_str())
+                && let Some(value) = args.next()
+            {
+                split.push(value.clone());
+            }
+            continue;
+        };
+        for (i, c) in cluster.char_indices() {
+            let option = format!("-{}", c);
+            let takes_value = Options::VALUE_OPTIONS.contains(&option.as_str());
+            split.push(option);
+            if takes_value {
+                match &cluster[i + 1..] {
+                    "" => split.extend(args.next().cloned()),
+                    value => split.push(value.into()),
+                }
+                break;
+            }
+        }
+    }
+    split
+}
+
+/// Reads `-d` data like curl: `@file` reads a file, `@-` reads stdin.
+fn read_data(data: &str) -> CliResult<Vec<u8>> {
+    match data.strip_prefix('@') {
+        Some("-") => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| e.to_string())?;
+            Ok(bytes)
+        }
+        Some(path) => fs::read(path).map_err(|e| format!("{}: {}", path, e)),
+        None => Ok(data.as_bytes().to_vec()),
+    }
+}
+
+fn status_line(response: &HttpResponse) -> String {
+    format!(
+        "{} {} {}",
+        response.version(),
+        response.statue().as_u16(),
+        response.note()
+    )
+}
+
+pub fn run(args: &[String]) -> CliResult<()> {
+    let options = Options::parse(args)?;
+    let mut request = options.request()?;
+
+    let mut output = Vec::new();
+    let mut redirects = 0;
+    let response = loop {
+        if options.verbose {
+            for line in request.header_as_string().lines() {
+                eprintln!("> {}", line);
+            }
+            eprintln!(">");
+        }
+
+        let response = fetch_blocking(&request, options.dns)?;
+
+        if options.verbose {
+            eprintln!("< {}", status_line(&response));
+            for (name, value) in response.headers() {
+                eprintln!("< {}: {}", name, value);
+            }
+            eprintln!("<");
+        }
+
+        match request.redirect(&response)? {
+            Some(redirected) if options.location => {
+                if MAX_REDIRECTS <= redirects {
+                    return Err(format!("maximum ({}) redirects followed", MAX_REDIRECTS));
+                }
+                redirects += 1;
+                if options.include {
+                    output.extend_from_slice(head(&response).as_bytes());
+                }
+                if options.verbose {
+                    eprintln!("* following redirect to {}", redirected.url());
+                }
+                request = redirected;
+            }
+            _ => break response,
+        }
+    };
+
+    if options.include {
+        output.extend_from_slice(head(&response).as_bytes());
+    }
+    output.extend_from_slice(response.content());
+    match &options.output {
+        Some(path) => fs::write(path, output).map_err(|e| format!("{}: {}", path, e)),
+        None => io::stdout().write_all(&output).map_err(|e| e.to_string()),
+    }
+}
+
+/// The status line and headers, as printed by `-i`.
+fn head(response: &HttpResponse) -> String {
+    let mut head = status_line(response) + "\r\n";
+    for (name, value) in response.headers() {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head + "\r\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> CliResult<Options> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse(&[
+            "-L",
+            "-H",
+            "Accept: text/html",
+            "-d",
+            "a=1",
+            "http://example.com/",
+            "-o",
+            "out.html",
+        ])
+        .unwrap();
+        assert!(options.location);
+        assert_eq!(options.output.as_deref(), Some("out.html"));
+        let request = options.request().unwrap();
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.header().get("Accept").unwrap(), "text/html");
+        assert_eq!(request.header().get("Content-Length").unwrap(), "3");
+
+        let options = parse(&["-X", "HEAD", "-i", "-v", "http://example.com/"]).unwrap();
+        assert_eq!(options.request().unwrap().method(), "HEAD");
+        assert!(options.include && options.verbose);
+        assert_eq!(
+            parse(&["-XHEAD", "-iv", "http://example.com/"]).unwrap(),
+            options
+        );
+
+        let options = parse(&["-iLo", "out.html", "-d", "-v", "http://example.com/"]).unwrap();
+        assert!(options.include && options.location && !options.verbose);
+        assert_eq!(options.output.as_deref(), Some("out.html"));
+        assert_eq!(options.data.as_deref(), Some(b"-v".as_slice()));
+        assert!(parse(&["-iz", "http://example.com/"]).is_err());
+
+        assert!(parse(&["-H", "invalid", "http://example.com/"]).is_err());
+        assert!(parse(&[]).is_err());
+    }
+}
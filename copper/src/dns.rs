@@ -1,34 +1,156 @@
+mod transport;
+
+pub use transport::*;
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Write;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
-use std::net::UdpSocket;
+use std::str::FromStr;
 
 pub type DnsResult<T> = Result<T, &'static str>;
 
 pub const CLOUDFLARE_DNS: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
 
+pub const MAX_CNAME_DEPTH: usize = 8;
+
 pub fn get_host_addr(host: &str, dns: IpAddr) -> DnsResult<IpAddr> {
-    let mut question_packet = DnsPacket::question();
-    question_packet.push_question(host.into());
-    let bytes = question_packet.to_bytes()?;
-
-    let udp_socket = UdpSocket::bind("0.0.0.0:0").map_err(|_| "failed to bind udp socket")?;
-    udp_socket
-        .connect((dns, 53))
-        .map_err(|_| "failed to connect to dns server")?;
-    udp_socket
-        .send(&bytes)
-        .map_err(|_| "failed to send packet")?;
-
-    let mut response_buff = [0u8; 512];
-    let response_len = udp_socket
-        .recv(&mut response_buff)
-        .map_err(|_| "failed to receive packet")?;
-    let response = &response_buff[..response_len];
-    let response_packet: DnsPacket = response.try_into()?;
-
-    response_packet
-        .get_ipaddr(host)
-        .ok_or("entry not found in response")
+    Resolver::new(UdpTransport::new(dns)).lookup_host(host)
+}
+
+pub async fn get_host_addr_async(host: &str, dns: IpAddr) -> DnsResult<IpAddr> {
+    Resolver::new(UdpTransport::new(dns))
+        .lookup_host_async(host)
+        .await
+}
+
+pub fn reverse_lookup(addr: IpAddr) -> DnsResult<Vec<String>> {
+    reverse_lookup_with(addr, UdpTransport::new(CLOUDFLARE_DNS))
+}
+
+/// Like `reverse_lookup`, but sends the query over `transport`.
+pub fn reverse_lookup_with<T: DnsTransport>(addr: IpAddr, transport: T) -> DnsResult<Vec<String>> {
+    Resolver::new(transport).reverse_lookup(addr)
+}
+
+/// Builds the `in-addr.arpa` (RFC 1035) or `ip6.arpa` (RFC 3596) name of an address.
+pub fn reverse_name(addr: IpAddr) -> String {
+    let mut name = String::new();
+    match addr {
+        IpAddr::V4(ipv4) => {
+            for octet in ipv4.octets().iter().rev() {
+                write!(name, "{}.", octet).unwrap();
+            }
+            name.push_str("in-addr.arpa");
+        }
+        IpAddr::V6(ipv6) => {
+            for octet in ipv6.octets().iter().rev() {
+                write!(name, "{:x}.{:x}.", octet & 0x0f, octet >> 4).unwrap();
+            }
+            name.push_str("ip6.arpa");
+        }
+    }
+    name
+}
+
+#[derive(Clone, Debug)]
+pub struct Resolver<T: DnsTransport> {
+    transport: T,
+}
+
+impl<T: DnsTransport> Resolver<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Builds a query with a random transaction ID and a 0x20-encoded name
+    /// (draft-vixie-dnsext-dns0x20). Transports discard or reject responses
+    /// that don't echo both back.
+    fn question(name: &str, qtype: QType, qclass: QClass) -> DnsResult<DnsPacket> {
+        let mut question_packet = DnsPacket::question();
+        question_packet.set_id(u16::from_be_bytes(random_bytes()?));
+        question_packet.push_classed_question(randomize_case(name)?, qtype, qclass);
+        Ok(question_packet)
+    }
+
+    fn ptr_names(name: &str, response: DnsPacket) -> DnsResult<Vec<String>> {
+        if response.rcode() != RCode::Success {
+            return Err("reverse lookup failed");
+        }
+        Ok(response.get_ptr_names(name))
+    }
+
+    pub fn query(&self, name: &str, qtype: QType) -> DnsResult<DnsPacket> {
+        self.query_with_class(name, qtype, QClass::In)
+    }
+
+    pub fn query_with_class(
+        &self,
+        name: &str,
+        qtype: QType,
+        qclass: QClass,
+    ) -> DnsResult<DnsPacket> {
+        self.transport
+            .exchange(&Self::question(name, qtype, qclass)?)
+    }
+
+    pub fn lookup_host(&self, host: &str) -> DnsResult<IpAddr> {
+        self.query(host, QType::A)?
+            .get_ipaddr(host)
+            .ok_or("entry not found in response")
+    }
+
+    pub fn reverse_lookup(&self, addr: IpAddr) -> DnsResult<Vec<String>> {
+        let name = reverse_name(addr);
+        Self::ptr_names(&name, self.query(&name, QType::Ptr)?)
+    }
+}
+
+impl<T: DnsTransport + AsyncDnsTransport> Resolver<T> {
+    pub async fn query_async(&self, name: &str, qtype: QType) -> DnsResult<DnsPacket> {
+        self.transport
+            .exchange_async(&Self::question(name, qtype, QClass::In)?)
+            .await
+    }
+
+    pub async fn lookup_host_async(&self, host: &str) -> DnsResult<IpAddr> {
+        self.query_async(host, QType::A)
+            .await?
+            .get_ipaddr(host)
+            .ok_or("entry not found in response")
+    }
+
+    pub async fn reverse_lookup_async(&self, addr: IpAddr) -> DnsResult<Vec<String>> {
+        let name = reverse_name(addr);
+        Self::ptr_names(&name, self.query_async(&name, QType::Ptr).await?)
+    }
+}
+
+pub(crate) fn random_bytes<const N: usize>() -> DnsResult<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).map_err(|_| "failed to get random bytes")?;
+    Ok(bytes)
+}
+
+fn randomize_case(name: &str) -> DnsResult<String> {
+    let mut bits = vec![0u8; name.len().div_ceil(8)];
+    getrandom::fill(&mut bits).map_err(|_| "failed to get random bytes")?;
+    Ok(name
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if bits[i / 8] & (1 << (i % 8)) != 0 {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect())
 }
 
 #[derive(Clone, Debug)]
@@ -36,6 +158,8 @@ pub struct DnsPacket {
     header: Header,
     questions: Vec<Question>,
     answers: Vec<Answer>,
+    authorities: Vec<ResourceRecord>,
+    additionals: Vec<ResourceRecord>,
 }
 
 impl TryFrom<&[u8]> for DnsPacket {
@@ -51,6 +175,8 @@ impl DnsPacket {
         let (header, mut index) = Header::from_bytes(bytes, 0)?;
         let mut questions = Vec::new();
         let mut answers = Vec::new();
+        let mut authorities = Vec::new();
+        let mut additionals = Vec::new();
 
         for _ in 0..header.qdcount {
             let (question, i) = Question::from_bytes(bytes, index)?;
@@ -64,11 +190,25 @@ impl DnsPacket {
             answers.push(answer);
         }
 
+        for _ in 0..header.nscount {
+            let (authority, i) = ResourceRecord::from_bytes(bytes, index)?;
+            index = i;
+            authorities.push(authority);
+        }
+
+        for _ in 0..header.arcount {
+            let (additional, i) = ResourceRecord::from_bytes(bytes, index)?;
+            index = i;
+            additionals.push(additional);
+        }
+
         Ok((
             Self {
                 header,
                 questions,
                 answers,
+                authorities,
+                additionals,
             },
             index,
         ))
@@ -79,43 +219,152 @@ impl DnsPacket {
             header: Header::question(),
             questions: Vec::new(),
             answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+        }
+    }
+
+    pub fn response(question: &DnsPacket) -> Self {
+        let mut header = question.header;
+        header.qr = true;
+        header.ra = true;
+        header.ancount = 0;
+        header.nscount = 0;
+        header.arcount = 0;
+        Self {
+            header,
+            questions: question.questions.clone(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
         }
     }
 
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn id(&self) -> u16 {
+        self.header.id()
+    }
+
+    pub fn set_id(&mut self, id: u16) {
+        self.header.id = id.to_be_bytes();
+    }
+
+    /// Checks the ID, opcode and question section against `query`. Question
+    /// names are compared case-sensitively to verify the 0x20 encoding.
+    pub fn is_response_to(&self, query: &DnsPacket) -> bool {
+        self.header.qr
+            && self.header.id == query.header.id
+            && self.header.opcode == query.header.opcode
+            && self.questions.len() == query.questions.len()
+            && self
+                .questions
+                .iter()
+                .zip(&query.questions)
+                .all(|(lhs, rhs)| {
+                    lhs.qname == rhs.qname && lhs.qtype == rhs.qtype && lhs.qclass == rhs.qclass
+                })
+    }
+
+    pub fn rcode(&self) -> RCode {
+        self.header.rcode()
+    }
+
+    pub fn questions(&self) -> &[Question] {
+        &self.questions
+    }
+
+    pub fn answers(&self) -> &[Answer] {
+        &self.answers
+    }
+
+    pub fn authorities(&self) -> &[ResourceRecord] {
+        &self.authorities
+    }
+
+    pub fn additionals(&self) -> &[ResourceRecord] {
+        &self.additionals
+    }
+
+    /// Resolves `host` against the answer section, following at most
+    /// `MAX_CNAME_DEPTH` CNAMEs.
     pub fn get_ipaddr(&self, host: &str) -> Option<IpAddr> {
-        self.get_ipaddr_recursion(host, 16)
-    }
-
-    fn get_ipaddr_recursion(&self, host: &str, depth: usize) -> Option<IpAddr> {
-        for answer in &self.answers {
-            if &answer.name == host {
-                return match answer.rdata {
-                    RData::IpAddr(addr) => Some(addr),
-                    RData::CName(ref name) => {
-                        if 0 < depth {
-                            self.get_ipaddr_recursion(name, depth - 1)
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                };
+        let mut owner = host;
+        for _ in 0..=MAX_CNAME_DEPTH {
+            let answer = self
+                .answers
+                .iter()
+                .find(|answer| answer.name.eq_ignore_ascii_case(owner))?;
+            match answer.rdata {
+                RData::IpAddr(addr) => return Some(addr),
+                RData::CName(ref name) => owner = name,
+                _ => return None,
             }
         }
 
         None
     }
 
+    /// Collects the PTR targets of `name`, following CNAMEs such as the ones
+    /// used for classless in-addr.arpa delegation (RFC 2317).
+    pub fn get_ptr_names(&self, name: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut owner = name;
+        for _ in 0..=MAX_CNAME_DEPTH {
+            let mut alias = None;
+            for answer in &self.answers {
+                if !answer.name.eq_ignore_ascii_case(owner) {
+                    continue;
+                }
+                match answer.rdata {
+                    RData::Ptr(ref target) => names.push(target.clone()),
+                    RData::CName(ref target) => alias = Some(target.as_str()),
+                    _ => (),
+                }
+            }
+            match alias {
+                Some(target) if names.is_empty() => owner = target,
+                _ => break,
+            }
+        }
+        names
+    }
+
     pub fn push_question(&mut self, name: String) {
+        self.push_typed_question(name, QType::A);
+    }
+
+    pub fn push_typed_question(&mut self, name: String, qtype: QType) {
+        self.push_classed_question(name, qtype, QClass::In);
+    }
+
+    pub fn push_classed_question(&mut self, name: String, qtype: QType, qclass: QClass) {
         let question = Question {
             qname: name,
-            qtype: QType::A,
-            qclass: QClass::In,
+            qtype,
+            qclass,
         };
         self.questions.push(question);
         self.header.qdcount += 1;
     }
 
+    pub fn push_answer(&mut self, answer: Answer) {
+        self.answers.push(answer);
+        self.header.ancount += 1;
+    }
+
+    pub fn push_authority(&mut self, authority: ResourceRecord) {
+        self.authorities.push(authority);
+        self.header.nscount += 1;
+    }
+
+    pub fn push_additional(&mut self, additional: ResourceRecord) {
+        self.additionals.push(additional);
+        self.header.arcount += 1;
+    }
+
     pub fn to_bytes(&self) -> DnsResult<Vec<u8>> {
         let mut bytes = Vec::new();
 
@@ -125,20 +374,75 @@ impl DnsPacket {
             bytes.extend_from_slice(&question.to_bytes()?);
         }
 
-        for answer in &self.answers {
-            bytes.extend_from_slice(&answer.to_bytes()?);
-        }
-
-        if 512 <= bytes.len() {
-            return Err("unsupported packet size");
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            bytes.extend_from_slice(&record.to_bytes()?);
         }
 
         Ok(bytes)
     }
 }
 
+/// Formats the packet the way `dig` does: header, flags and one block per
+/// non-empty section.
+impl Display for DnsPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            header.opcode(),
+            header.rcode(),
+            header.id()
+        )?;
+        let flags: Vec<_> = [
+            ("qr", header.qr()),
+            ("aa", header.aa()),
+            ("tc", header.tc()),
+            ("rd", header.rd()),
+            ("ra", header.ra()),
+        ]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect();
+        writeln!(
+            f,
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            flags.join(" "),
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len()
+        )?;
+
+        if !self.questions.is_empty() {
+            write!(f, "\n;; QUESTION SECTION:\n")?;
+            for question in &self.questions {
+                writeln!(f, ";{}", question)?;
+            }
+        }
+        for (section, records) in [
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.additionals),
+        ] {
+            if !records.is_empty() {
+                write!(f, "\n;; {} SECTION:\n", section)?;
+                for record in records {
+                    writeln!(f, "{}", record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
-struct Header {
+pub struct Header {
     id: [u8; 2],
     qr: bool,
     opcode: Opcode,
@@ -154,6 +458,34 @@ struct Header {
 }
 
 impl Header {
+    pub fn id(&self) -> u16 {
+        u16::from_be_bytes(self.id)
+    }
+
+    pub fn qr(&self) -> bool {
+        self.qr
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn aa(&self) -> bool {
+        self.aa
+    }
+
+    pub fn tc(&self) -> bool {
+        self.tc
+    }
+
+    pub fn rd(&self) -> bool {
+        self.rd
+    }
+
+    pub fn ra(&self) -> bool {
+        self.ra
+    }
+
     pub fn rcode(&self) -> RCode {
         self.rcode
     }
@@ -287,6 +619,12 @@ impl Question {
     }
 }
 
+impl Display for Question {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.\t\t\t{}\t{}", self.qname, self.qclass, self.qtype)
+    }
+}
+
 pub type Answer = ResourceRecord;
 
 #[derive(Clone, Debug)]
@@ -298,6 +636,15 @@ pub struct ResourceRecord {
 }
 
 impl ResourceRecord {
+    pub fn new(name: String, class: Class, ttl: u32, rdata: RData) -> Self {
+        Self {
+            name,
+            class,
+            ttl,
+            rdata,
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -310,13 +657,17 @@ impl ResourceRecord {
         self.ttl
     }
 
+    pub fn rtype(&self) -> RType {
+        self.rdata.rtype()
+    }
+
     pub fn rdata(&self) -> &RData {
         &self.rdata
     }
 
     pub fn from_bytes(bytes: &[u8], index: usize) -> DnsResult<(Self, usize)> {
         let (name, mut index) = get_label(bytes, index)?;
-        if bytes.len() <= index + 10 {
+        if bytes.len() < index + 10 {
             return Err("invalid resource record");
         }
         let rtype = u16::from_be_bytes(bytes[index..index + 2].try_into().unwrap()).into();
@@ -347,8 +698,43 @@ impl ResourceRecord {
                     .unwrap();
                 RData::IpAddr(IpAddr::V6(ipv6.into()))
             }
+            RType::Ns => RData::Ns(get_label(bytes, index)?.0),
             RType::CName => RData::CName(get_label(bytes, index)?.0),
-            _ => RData::Unknown,
+            RType::Soa => {
+                let (mname, i) = get_label(bytes, index)?;
+                let (rname, i) = get_label(bytes, i)?;
+                let numbers = bytes.get(i..i + 20).ok_or("invalid rdata")?;
+                let number =
+                    |n: usize| u32::from_be_bytes(numbers[n * 4..n * 4 + 4].try_into().unwrap());
+                RData::Soa {
+                    mname,
+                    rname,
+                    serial: number(0),
+                    refresh: number(1),
+                    retry: number(2),
+                    expire: number(3),
+                    minimum: number(4),
+                }
+            }
+            RType::Ptr => RData::Ptr(get_label(bytes, index)?.0),
+            RType::Mx => {
+                let preference = rdata_raw.get(..2).ok_or("invalid rdata")?;
+                RData::Mx {
+                    preference: u16::from_be_bytes(preference.try_into().unwrap()),
+                    exchange: get_label(bytes, index + 2)?.0,
+                }
+            }
+            RType::Txt => {
+                let mut strings = Vec::new();
+                let mut rest = rdata_raw;
+                while let Some((&len, tail)) = rest.split_first() {
+                    let string = tail.get(..len as usize).ok_or("invalid rdata")?;
+                    strings.push(string.to_vec());
+                    rest = &tail[len as usize..];
+                }
+                RData::Txt(strings)
+            }
+            _ => RData::Unknown(rtype, rdata_raw.to_vec()),
         };
         index += rdlength as usize;
 
@@ -364,21 +750,99 @@ impl ResourceRecord {
     }
 
     pub fn to_bytes(&self) -> DnsResult<Vec<u8>> {
-        unimplemented!()
+        let rdata = match self.rdata {
+            RData::IpAddr(IpAddr::V4(ipv4)) => ipv4.octets().to_vec(),
+            RData::IpAddr(IpAddr::V6(ipv6)) => ipv6.octets().to_vec(),
+            RData::Ns(ref name) | RData::CName(ref name) | RData::Ptr(ref name) => as_label(name)?,
+            RData::Soa {
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                let mut bytes = as_label(mname)?;
+                bytes.extend_from_slice(&as_label(rname)?);
+                for number in [serial, refresh, retry, expire, minimum] {
+                    bytes.extend_from_slice(&number.to_be_bytes());
+                }
+                bytes
+            }
+            RData::Mx {
+                preference,
+                ref exchange,
+            } => {
+                let mut bytes = preference.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&as_label(exchange)?);
+                bytes
+            }
+            RData::Txt(ref strings) => {
+                let mut bytes = Vec::new();
+                for string in strings {
+                    let len = u8::try_from(string.len()).map_err(|_| "too long txt string")?;
+                    bytes.push(len);
+                    bytes.extend_from_slice(string);
+                }
+                bytes
+            }
+            RData::Unknown(_, ref rdata) => rdata.clone(),
+        };
+        let rtype = self.rtype();
+
+        let mut bytes = as_label(&self.name)?;
+        bytes.extend_from_slice(&rtype.as_u16().to_be_bytes());
+        bytes.extend_from_slice(&self.class.as_u16().to_be_bytes());
+        bytes.extend_from_slice(&self.ttl.to_be_bytes());
+        bytes.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&rdata);
+
+        Ok(bytes)
+    }
+}
+
+impl Display for ResourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.\t\t{}\t{}\t{}\t{}",
+            self.name,
+            self.ttl,
+            self.class,
+            self.rtype(),
+            self.rdata
+        )
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Opcode {
-    Query = 0,
-    IQuery = 1,
-    Status = 2,
-    Unknown,
+    Query,
+    IQuery,
+    Status,
+    Unknown(u8),
 }
 
 impl Opcode {
     pub fn as_u8(self) -> u8 {
-        self as u8
+        match self {
+            Self::Query => 0,
+            Self::IQuery => 1,
+            Self::Status => 2,
+            Self::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query => write!(f, "QUERY"),
+            Self::IQuery => write!(f, "IQUERY"),
+            Self::Status => write!(f, "STATUS"),
+            Self::Unknown(value) => write!(f, "OPCODE{}", value),
+        }
     }
 }
 
@@ -388,25 +852,47 @@ impl From<u8> for Opcode {
             0 => Self::Query,
             1 => Self::IQuery,
             2 => Self::Status,
-            _ => Self::Unknown,
+            _ => Self::Unknown(value),
         }
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RCode {
-    Success = 0,
-    FormatError = 1,
-    ServerFailer = 2,
-    NoexistedDomain = 3,
-    Unimplemented = 4,
-    Rejected = 5,
-    Unknown,
+    Success,
+    FormatError,
+    ServerFailer,
+    NoexistedDomain,
+    Unimplemented,
+    Rejected,
+    Unknown(u8),
 }
 
 impl RCode {
     pub fn as_u8(self) -> u8 {
-        self as u8
+        match self {
+            Self::Success => 0,
+            Self::FormatError => 1,
+            Self::ServerFailer => 2,
+            Self::NoexistedDomain => 3,
+            Self::Unimplemented => 4,
+            Self::Rejected => 5,
+            Self::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for RCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Success => write!(f, "NOERROR"),
+            Self::FormatError => write!(f, "FORMERR"),
+            Self::ServerFailer => write!(f, "SERVFAIL"),
+            Self::NoexistedDomain => write!(f, "NXDOMAIN"),
+            Self::Unimplemented => write!(f, "NOTIMP"),
+            Self::Rejected => write!(f, "REFUSED"),
+            Self::Unknown(value) => write!(f, "RCODE{}", value),
+        }
     }
 }
 
@@ -419,7 +905,7 @@ impl From<u8> for RCode {
             3 => Self::NoexistedDomain,
             4 => Self::Unimplemented,
             5 => Self::Rejected,
-            _ => Self::Unknown,
+            _ => Self::Unknown(value),
         }
     }
 }
@@ -428,15 +914,54 @@ pub type QClass = Class;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Class {
-    In = 1,
-    Ch = 3,
-    Hs = 4,
-    Unknown,
+    In,
+    Ch,
+    Hs,
+    Any,
+    Unknown(u16),
 }
 
 impl Class {
     pub fn as_u16(self) -> u16 {
-        self as u16
+        match self {
+            Self::In => 1,
+            Self::Ch => 3,
+            Self::Hs => 4,
+            Self::Any => 255,
+            Self::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::In => write!(f, "IN"),
+            Self::Ch => write!(f, "CH"),
+            Self::Hs => write!(f, "HS"),
+            Self::Any => write!(f, "ANY"),
+            Self::Unknown(value) => write!(f, "CLASS{}", value),
+        }
+    }
+}
+
+/// Accepts mnemonics case-insensitively, plus the generic `CLASS<n>` form of
+/// RFC 3597.
+impl FromStr for Class {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "IN" => Ok(Self::In),
+            "CH" => Ok(Self::Ch),
+            "HS" => Ok(Self::Hs),
+            "ANY" => Ok(Self::Any),
+            upper => upper
+                .strip_prefix("CLASS")
+                .and_then(|value| value.parse::<u16>().ok())
+                .map(Self::from)
+                .ok_or("unknown class"),
+        }
     }
 }
 
@@ -446,7 +971,8 @@ impl From<u16> for Class {
             1 => Self::In,
             3 => Self::Ch,
             4 => Self::Hs,
-            _ => Self::Unknown,
+            255 => Self::Any,
+            _ => Self::Unknown(value),
         }
     }
 }
@@ -455,15 +981,74 @@ pub type QType = RType;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RType {
-    A = 1,
-    AAAA = 28,
-    CName = 5,
-    Unknown,
+    A,
+    Ns,
+    CName,
+    Soa,
+    Ptr,
+    Mx,
+    Txt,
+    AAAA,
+    Any,
+    Unknown(u16),
 }
 
 impl RType {
     pub fn as_u16(self) -> u16 {
-        self as u16
+        match self {
+            Self::A => 1,
+            Self::Ns => 2,
+            Self::CName => 5,
+            Self::Soa => 6,
+            Self::Ptr => 12,
+            Self::Mx => 15,
+            Self::Txt => 16,
+            Self::AAAA => 28,
+            Self::Any => 255,
+            Self::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for RType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A => write!(f, "A"),
+            Self::Ns => write!(f, "NS"),
+            Self::CName => write!(f, "CNAME"),
+            Self::Soa => write!(f, "SOA"),
+            Self::Ptr => write!(f, "PTR"),
+            Self::Mx => write!(f, "MX"),
+            Self::Txt => write!(f, "TXT"),
+            Self::AAAA => write!(f, "AAAA"),
+            Self::Any => write!(f, "ANY"),
+            Self::Unknown(value) => write!(f, "TYPE{}", value),
+        }
+    }
+}
+
+/// Accepts mnemonics case-insensitively, plus the generic `TYPE<n>` form of
+/// RFC 3597.
+impl FromStr for RType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(Self::A),
+            "NS" => Ok(Self::Ns),
+            "CNAME" => Ok(Self::CName),
+            "SOA" => Ok(Self::Soa),
+            "PTR" => Ok(Self::Ptr),
+            "MX" => Ok(Self::Mx),
+            "TXT" => Ok(Self::Txt),
+            "AAAA" => Ok(Self::AAAA),
+            "ANY" => Ok(Self::Any),
+            upper => upper
+                .strip_prefix("TYPE")
+                .and_then(|value| value.parse::<u16>().ok())
+                .map(Self::from)
+                .ok_or("unknown type"),
+        }
     }
 }
 
@@ -471,9 +1056,15 @@ impl From<u16> for RType {
     fn from(value: u16) -> Self {
         match value {
             1 => Self::A,
-            28 => Self::AAAA,
+            2 => Self::Ns,
             5 => Self::CName,
-            _ => Self::Unknown,
+            6 => Self::Soa,
+            12 => Self::Ptr,
+            15 => Self::Mx,
+            16 => Self::Txt,
+            28 => Self::AAAA,
+            255 => Self::Any,
+            _ => Self::Unknown(value),
         }
     }
 }
@@ -481,8 +1072,95 @@ impl From<u16> for RType {
 #[derive(Clone, Debug)]
 pub enum RData {
     IpAddr(IpAddr),
+    Ns(String),
     CName(String),
-    Unknown,
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    Ptr(String),
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    Txt(Vec<Vec<u8>>),
+    Unknown(RType, Vec<u8>),
+}
+
+impl RData {
+    pub fn rtype(&self) -> RType {
+        match self {
+            Self::IpAddr(IpAddr::V4(_)) => RType::A,
+            Self::IpAddr(IpAddr::V6(_)) => RType::AAAA,
+            Self::Ns(_) => RType::Ns,
+            Self::CName(_) => RType::CName,
+            Self::Soa { .. } => RType::Soa,
+            Self::Ptr(_) => RType::Ptr,
+            Self::Mx { .. } => RType::Mx,
+            Self::Txt(_) => RType::Txt,
+            Self::Unknown(rtype, _) => *rtype,
+        }
+    }
+}
+
+/// Formats the record data in zone file presentation format. Records of
+/// unknown types use the generic `\# <length> <hex>` form of RFC 3597.
+impl Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IpAddr(addr) => write!(f, "{}", addr),
+            Self::Ns(name) | Self::CName(name) | Self::Ptr(name) => write!(f, "{}.", name),
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{}. {}. {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            Self::Mx {
+                preference,
+                exchange,
+            } => write!(f, "{} {}.", preference, exchange),
+            Self::Txt(strings) => {
+                for (i, string) in strings.iter().enumerate() {
+                    if 0 < i {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "\"")?;
+                    for &byte in string {
+                        match byte {
+                            b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+                            0x20..0x7f => write!(f, "{}", byte as char)?,
+                            _ => write!(f, "\\{:03}", byte)?,
+                        }
+                    }
+                    write!(f, "\"")?;
+                }
+                Ok(())
+            }
+            Self::Unknown(_, rdata) => {
+                write!(f, "\\# {}", rdata.len())?;
+                if !rdata.is_empty() {
+                    write!(f, " ")?;
+                    for byte in rdata {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 fn as_label(s: &str) -> DnsResult<Vec<u8>> {
@@ -492,7 +1170,7 @@ fn as_label(s: &str) -> DnsResult<Vec<u8>> {
         if 0x7f <= part.len() {
             return Err("too large qname part found");
         }
-        if part.len() == 0 {
+        if part.is_empty() {
             break;
         }
         bytes.push(part.len() as u8);
@@ -539,7 +1217,7 @@ fn get_label(bytes: &[u8], index: usize) -> DnsResult<(String, usize)> {
                 let len = len_u8 as usize;
                 index += 1;
                 string.push_str(
-                    str::from_utf8(&bytes[index..index + len])
+                    str::from_utf8(bytes.get(index..index + len).ok_or("invalid label")?)
                         .map_err(|_| "invalid utf8 codepoint found")?,
                 );
                 index += len;
@@ -554,3 +1232,224 @@ fn get_label(bytes: &[u8], index: usize) -> DnsResult<(String, usize)> {
     let len = append_label(bytes, index, &mut string, 16)?;
     Ok((string, len))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+    use std::net::Ipv6Addr;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    #[test]
+    fn test_reverse_name() {
+        assert_eq!(
+            reverse_name(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+            "1.2.0.192.in-addr.arpa"
+        );
+        assert_eq!(
+            reverse_name(IpAddr::V6(Ipv6Addr::new(
+                0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x0567
+            ))),
+            "7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn test_reverse_lookup() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buff = [0u8; 512];
+            let (len, peer) = server.recv_from(&mut buff).unwrap();
+            let query = DnsPacket::try_from(&buff[..len]).unwrap();
+            assert_eq!(query.questions()[0].qtype(), QType::Ptr);
+            let name = query.questions()[0].qname().to_string();
+            assert!(name.eq_ignore_ascii_case("1.2.0.192.in-addr.arpa"));
+
+            let alias = "1.0/25.2.0.192.in-addr.arpa".to_string();
+            let mut response = DnsPacket::response(&query);
+            response.push_answer(Answer::new(
+                name.to_ascii_lowercase(),
+                Class::In,
+                60,
+                RData::CName(alias.clone()),
+            ));
+            for target in ["host.example.com", "alias.example.com"] {
+                response.push_answer(Answer::new(
+                    alias.clone(),
+                    Class::In,
+                    60,
+                    RData::Ptr(target.into()),
+                ));
+            }
+            server.send_to(&response.to_bytes().unwrap(), peer).unwrap();
+        });
+
+        assert_eq!(
+            reverse_lookup_with(
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                UdpTransport::with_addr(addr)
+            )
+            .unwrap(),
+            vec![
+                "host.example.com".to_string(),
+                "alias.example.com".to_string()
+            ]
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_query_randomization() {
+        const QUERIES: usize = 16;
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut queries = Vec::new();
+            for _ in 0..QUERIES {
+                let mut buff = [0u8; 512];
+                let (len, peer) = server.recv_from(&mut buff).unwrap();
+                let query = DnsPacket::try_from(&buff[..len]).unwrap();
+                let response = DnsPacket::response(&query);
+                server.send_to(&response.to_bytes().unwrap(), peer).unwrap();
+                queries.push((query, peer.port()));
+            }
+            queries
+        });
+
+        let resolver = Resolver::new(UdpTransport::with_addr(addr));
+        let name = "abcdefghijklmnopqrstuvwxyz.example.com";
+        for _ in 0..QUERIES {
+            resolver.query(name, QType::A).unwrap();
+        }
+        let queries = handle.join().unwrap();
+
+        let ids: HashSet<_> = queries.iter().map(|(query, _)| query.id()).collect();
+        let ports: HashSet<_> = queries.iter().map(|(_, port)| *port).collect();
+        assert!(ids.len() > 1);
+        assert!(ports.len() > 1);
+        for (query, port) in &queries {
+            assert!(*port >= 1024);
+            assert!(query.questions()[0].qname().eq_ignore_ascii_case(name));
+        }
+        assert!(
+            queries
+                .iter()
+                .any(|(query, _)| query.questions()[0].qname() != name)
+        );
+    }
+
+    #[test]
+    fn test_cname_depth() {
+        let mut packet = DnsPacket::question();
+        for i in 0..MAX_CNAME_DEPTH + 1 {
+            packet.push_answer(Answer::new(
+                format!("{}.example.com", i),
+                Class::In,
+                60,
+                RData::CName(format!("{}.example.com", i + 1)),
+            ));
+        }
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        packet.push_answer(Answer::new(
+            format!("{}.example.com", MAX_CNAME_DEPTH + 1),
+            Class::In,
+            60,
+            RData::IpAddr(addr),
+        ));
+        assert_eq!(packet.get_ipaddr("1.Example.com"), Some(addr));
+        assert_eq!(packet.get_ipaddr("0.example.com"), None);
+
+        let mut packet = DnsPacket::question();
+        for (name, target) in [
+            ("a.example.com", "b.example.com"),
+            ("b.example.com", "a.example.com"),
+        ] {
+            packet.push_answer(Answer::new(
+                name.into(),
+                Class::In,
+                60,
+                RData::CName(target.into()),
+            ));
+        }
+        assert_eq!(packet.get_ipaddr("a.example.com"), None);
+    }
+
+    #[test]
+    fn test_sections_and_display() {
+        let mut query = DnsPacket::question();
+        query.set_id(0x1234);
+        query.push_typed_question("example.com".into(), QType::Mx);
+        let mut response = DnsPacket::response(&query);
+        response.push_answer(Answer::new(
+            "example.com".into(),
+            Class::In,
+            300,
+            RData::Mx {
+                preference: 10,
+                exchange: "mail.example.com".into(),
+            },
+        ));
+        response.push_authority(ResourceRecord::new(
+            "example.com".into(),
+            Class::In,
+            3600,
+            RData::Soa {
+                mname: "ns.example.com".into(),
+                rname: "hostmaster.example.com".into(),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            },
+        ));
+        response.push_additional(ResourceRecord::new(
+            "example.com".into(),
+            Class::In,
+            60,
+            RData::Txt(vec![b"v=spf1 \"-all\"".to_vec(), vec![0x00]]),
+        ));
+        response.push_additional(ResourceRecord::new(
+            "".into(),
+            Class::Unknown(1232),
+            0,
+            RData::Unknown(RType::Unknown(41), vec![0xde, 0xad]),
+        ));
+
+        let (packet, _) = DnsPacket::from_bytes(&response.to_bytes().unwrap()).unwrap();
+        assert_eq!(packet.authorities().len(), 1);
+        assert_eq!(packet.additionals().len(), 2);
+        assert_eq!(
+            packet.to_string(),
+            ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660\n\
+             ;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 1, ADDITIONAL: 2\n\
+             \n\
+             ;; QUESTION SECTION:\n\
+             ;example.com.\t\t\tIN\tMX\n\
+             \n\
+             ;; ANSWER SECTION:\n\
+             example.com.\t\t300\tIN\tMX\t10 mail.example.com.\n\
+             \n\
+             ;; AUTHORITY SECTION:\n\
+             example.com.\t\t3600\tIN\tSOA\tns.example.com. hostmaster.example.com. \
+             2024010101 7200 3600 1209600 300\n\
+             \n\
+             ;; ADDITIONAL SECTION:\n\
+             example.com.\t\t60\tIN\tTXT\t\"v=spf1 \\\"-all\\\"\" \"\\000\"\n\
+             .\t\t0\tCLASS1232\tTYPE41\t\\# 2 dead\n"
+        );
+    }
+
+    #[test]
+    fn test_type_and_class_names() {
+        assert_eq!("aaaa".parse::<RType>(), Ok(RType::AAAA));
+        assert_eq!("TYPE16".parse::<RType>(), Ok(RType::Txt));
+        assert_eq!("type65".parse::<RType>(), Ok(RType::Unknown(65)));
+        assert!("example.com".parse::<RType>().is_err());
+        assert_eq!("ch".parse::<Class>(), Ok(Class::Ch));
+        assert_eq!("CLASS254".parse::<Class>(), Ok(Class::Unknown(254)));
+        assert_eq!(RType::Unknown(65).to_string(), "TYPE65");
+    }
+}
@@ -0,0 +1,725 @@
+use super::DnsPacket;
+use super::DnsResult;
+use super::random_bytes;
+use crate::http::HttpRequest;
+use crate::http::HttpResponse;
+use crate::http::Status;
+use crate::net;
+use crate::net::AsyncStream;
+use crate::net::AsyncUdpSocket;
+use crate::net::Connector;
+use crate::net::TcpConnector;
+use crate::net::TlsConnector;
+use crate::reactor;
+use crate::url::Url;
+use std::future::Future;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+
+pub trait DnsTransport {
+    /// Sends `query` and returns the response that answers it.
+    fn exchange(&self, query: &DnsPacket) -> DnsResult<DnsPacket>;
+}
+
+pub trait AsyncDnsTransport {
+    fn exchange_async(&self, query: &DnsPacket) -> impl Future<Output = DnsResult<DnsPacket>>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UdpTransport {
+    server: SocketAddr,
+}
+
+impl UdpTransport {
+    pub const PORT: u16 = 53;
+    pub const MAX_PACKET_SIZE: usize = 512;
+
+    pub fn new(server: IpAddr) -> Self {
+        Self::with_addr(SocketAddr::new(server, Self::PORT))
+    }
+
+    pub fn with_addr(server: SocketAddr) -> Self {
+        Self { server }
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Binds to a random unprivileged port instead of relying on the
+    /// operating system's choice of ephemeral port.
+    fn bind(&self) -> DnsResult<AsyncUdpSocket> {
+        let ip: IpAddr = if self.server.is_ipv4() {
+            "0.0.0.0".parse().unwrap()
+        } else {
+            "::".parse().unwrap()
+        };
+        for _ in 0..8 {
+            let port = 1024 + u16::from_be_bytes(random_bytes()?) % (u16::MAX - 1024);
+            if let Ok(udp_socket) = AsyncUdpSocket::bind(SocketAddr::new(ip, port)) {
+                return Ok(udp_socket);
+            }
+        }
+        AsyncUdpSocket::bind(SocketAddr::new(ip, 0)).map_err(|_| "failed to bind udp socket")
+    }
+
+    async fn receive(
+        &self,
+        udp_socket: &AsyncUdpSocket,
+        query: &DnsPacket,
+    ) -> DnsResult<DnsPacket> {
+        let mut response_buff = [0u8; Self::MAX_PACKET_SIZE];
+        loop {
+            let (response_len, peer) = udp_socket
+                .recv_from(&mut response_buff)
+                .await
+                .map_err(|_| "failed to receive packet")?;
+            if peer != self.server {
+                continue;
+            }
+            if let Ok(response) = DnsPacket::try_from(&response_buff[..response_len])
+                && response.is_response_to(query)
+            {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+impl DnsTransport for UdpTransport {
+    fn exchange(&self, query: &DnsPacket) -> DnsResult<DnsPacket> {
+        reactor::block_on(self.exchange_async(query))
+    }
+}
+
+impl AsyncDnsTransport for UdpTransport {
+    /// Datagrams from other addresses, unparsable datagrams and responses
+    /// that don't match `query` are discarded until the timeout expires.
+    async fn exchange_async(&self, query: &DnsPacket) -> DnsResult<DnsPacket> {
+        let bytes = query.to_bytes()?;
+        if Self::MAX_PACKET_SIZE < bytes.len() {
+            return Err("unsupported packet size");
+        }
+
+        let udp_socket = self.bind()?;
+        udp_socket
+            .send_to(&bytes, self.server)
+            .await
+            .map_err(|_| "failed to send packet")?;
+
+        reactor::timeout(net::TIMEOUT, self.receive(&udp_socket, query))
+            .await
+            .ok_or("timed out waiting for response")?
+    }
+}
+
+/// DNS over a stream connection with the two-byte length prefix of RFC 1035
+/// section 4.2.2. With a `TlsConnector` this is DNS over TLS (RFC 7858).
+#[derive(Clone, Debug)]
+pub struct TcpTransport<C: Connector = TcpConnector> {
+    server: SocketAddr,
+    server_name: String,
+    connector: C,
+}
+
+pub type TlsTransport = TcpTransport<TlsConnector>;
+
+impl TcpTransport {
+    pub const PORT: u16 = 53;
+
+    pub fn new(server: IpAddr) -> Self {
+        let server = SocketAddr::new(server, Self::PORT);
+        Self::with_connector(server, &server.ip().to_string(), TcpConnector)
+    }
+}
+
+impl TlsTransport {
+    pub const TLS_PORT: u16 = 853;
+
+    pub fn tls(server: IpAddr, server_name: &str) -> Self {
+        let server = SocketAddr::new(server, Self::TLS_PORT);
+        Self::with_connector(server, server_name, TlsConnector::new())
+    }
+}
+
+impl<C: Connector> TcpTransport<C> {
+    pub fn with_connector(server: SocketAddr, server_name: &str, connector: C) -> Self {
+        Self {
+            server,
+            server_name: server_name.into(),
+            connector,
+        }
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+}
+
+impl<C: Connector> DnsTransport for TcpTransport<C> {
+    fn exchange(&self, query: &DnsPacket) -> DnsResult<DnsPacket> {
+        reactor::block_on(self.exchange_async(query))
+    }
+}
+
+impl<C: Connector> AsyncDnsTransport for TcpTransport<C> {
+    async fn exchange_async(&self, query: &DnsPacket) -> DnsResult<DnsPacket> {
+        let bytes = query.to_bytes()?;
+        let len: u16 = bytes
+            .len()
+            .try_into()
+            .map_err(|_| "unsupported packet size")?;
+
+        let mut stream = self
+            .connector
+            .connect_async(&self.server_name, self.server)
+            .await?;
+        let mut message = len.to_be_bytes().to_vec();
+        message.extend_from_slice(&bytes);
+        let response_buff = reactor::timeout(net::TIMEOUT, async {
+            stream
+                .write_all(&message)
+                .await
+                .map_err(|_| "failed to send packet")?;
+            let mut len_buff = [0u8; 2];
+            stream
+                .read_exact(&mut len_buff)
+                .await
+                .map_err(|_| "failed to receive packet")?;
+            let mut response_buff = vec![0u8; u16::from_be_bytes(len_buff) as usize];
+            stream
+                .read_exact(&mut response_buff)
+                .await
+                .map_err(|_| "failed to receive packet")?;
+            Ok(response_buff)
+        })
+        .await
+        .ok_or("timed out waiting for response")??;
+
+        let response = DnsPacket::try_from(response_buff.as_slice())?;
+        if !response.is_response_to(query) {
+            return Err("mismatched response");
+        }
+        Ok(response)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DohMethod {
+    Get,
+    Post,
+}
+
+/// DNS over HTTPS (RFC 8484) using the `application/dns-message` media type.
+#[derive(Clone, Debug)]
+pub struct HttpsTransport<C: Connector = TlsConnector> {
+    url: String,
+    server: SocketAddr,
+    method: DohMethod,
+    connector: C,
+}
+
+impl HttpsTransport {
+    pub const PORT: u16 = 443;
+
+    pub fn new(url: &str, server: IpAddr) -> DnsResult<Self> {
+        let port = Url::new(url)
+            .map_err(|_| "invalid url")?
+            .port()
+            .unwrap_or(Self::PORT);
+        Self::with_connector(url, SocketAddr::new(server, port), TlsConnector::new())
+    }
+}
+
+impl<C: Connector> HttpsTransport<C> {
+    pub const MEDIA_TYPE: &str = "application/dns-message";
+
+    pub fn with_connector(url: &str, server: SocketAddr, connector: C) -> DnsResult<Self> {
+        Url::new(url).map_err(|_| "invalid url")?;
+        Ok(Self {
+            url: url.into(),
+            server,
+            method: DohMethod::Post,
+            connector,
+        })
+    }
+
+    pub fn with_method(mut self, method: DohMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn method(&self) -> DohMethod {
+        self.method
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    fn request(&self, bytes: &[u8]) -> DnsResult<HttpRequest> {
+        let request = match self.method {
+            DohMethod::Get => {
+                let separator = if self.url.contains('?') { '&' } else { '?' };
+                let url = format!("{}{}dns={}", self.url, separator, base64url(bytes));
+                let mut request =
+                    HttpRequest::new("GET", Url::new(&url).map_err(|_| "invalid url")?);
+                request.push_header("Accept".into(), Self::MEDIA_TYPE.into());
+                request
+            }
+            DohMethod::Post => {
+                let mut request =
+                    HttpRequest::new("POST", Url::new(&self.url).map_err(|_| "invalid url")?);
+                request.push_header("Accept".into(), Self::MEDIA_TYPE.into());
+                request.push_header("Content-Type".into(), Self::MEDIA_TYPE.into());
+                request.set_content(bytes);
+                request
+            }
+        };
+        Ok(request)
+    }
+}
+
+impl<C: Connector> DnsTransport for HttpsTransport<C> {
+    fn exchange(&self, query: &DnsPacket) -> DnsResult<DnsPacket> {
+        reactor::block_on(self.exchange_async(query))
+    }
+}
+
+impl<C: Connector> AsyncDnsTransport for HttpsTransport<C> {
+    async fn exchange_async(&self, query: &DnsPacket) -> DnsResult<DnsPacket> {
+        let request = self.request(&query.to_bytes()?)?;
+        let url = Url::new(&self.url).map_err(|_| "invalid url")?;
+
+        let mut stream = self
+            .connector
+            .connect_async(url.hostname(), self.server)
+            .await?;
+        let response_message = reactor::timeout(net::TIMEOUT, async {
+            stream
+                .write_all(&request.as_bytes())
+                .await
+                .map_err(|_| "failed to send request")?;
+            let mut response_message = Vec::new();
+            stream
+                .read_to_end(&mut response_message)
+                .await
+                .map_err(|_| "failed to receive response")?;
+            Ok(response_message)
+        })
+        .await
+        .ok_or("timed out waiting for response")??;
+
+        let response = HttpResponse::from_bytes(&response_message)?;
+        if response.statue() != Status::Ok {
+            return Err("unexpected http status");
+        }
+        let content_type = response
+            .headers()
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.split(';').next().unwrap_or_default().trim());
+        if !content_type.is_some_and(|media_type| media_type.eq_ignore_ascii_case(Self::MEDIA_TYPE))
+        {
+            return Err("unexpected content type");
+        }
+        let response = DnsPacket::try_from(response.content())?;
+        if !response.is_response_to(query) {
+            return Err("mismatched response");
+        }
+        Ok(response)
+    }
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut string = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut n = 0u32;
+        for (i, byte) in chunk.iter().enumerate() {
+            n |= (*byte as u32) << (16 - i * 8);
+        }
+        for i in 0..chunk.len() + 1 {
+            string.push(TABLE[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+        }
+    }
+    string
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Answer;
+    use super::super::Class;
+    use super::super::RData;
+    use super::super::Resolver;
+    use super::*;
+    use crate::net::test::CA;
+    use crate::net::test::tls_accept;
+    use rustls::ServerConnection;
+    use rustls::StreamOwned;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::Ipv4Addr;
+    use std::net::TcpListener;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    const ADDR: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    fn answer(query: &[u8]) -> Vec<u8> {
+        let query: DnsPacket = query.try_into().unwrap();
+        let mut response = DnsPacket::response(&query);
+        let name = query.questions()[0].qname().to_string();
+        response.push_answer(Answer::new(name, Class::In, 60, RData::IpAddr(ADDR.into())));
+        response.to_bytes().unwrap()
+    }
+
+    fn serve_stream(stream: &mut (impl Read + Write)) {
+        let mut len_buff = [0u8; 2];
+        stream.read_exact(&mut len_buff).unwrap();
+        let mut query = vec![0u8; u16::from_be_bytes(len_buff) as usize];
+        stream.read_exact(&mut query).unwrap();
+        let response = answer(&query);
+        stream
+            .write_all(&(response.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&response).unwrap();
+        stream.flush().unwrap();
+    }
+
+    fn serve_https(
+        stream: &mut StreamOwned<ServerConnection, std::net::TcpStream>,
+        content_type: &str,
+    ) -> DohMethod {
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8];
+            stream.read_exact(&mut byte).unwrap();
+            request.push(byte[0]);
+        }
+        let request = String::from_utf8(request).unwrap();
+        let request_line = request.lines().next().unwrap();
+        assert!(request.contains("Accept: application/dns-message\r\n"));
+
+        let (method, query) = if let Some(target) = request_line.strip_prefix("GET ") {
+            let encoded = target
+                .split_once("dns=")
+                .unwrap()
+                .1
+                .split(' ')
+                .next()
+                .unwrap();
+            (DohMethod::Get, base64url_decode(encoded))
+        } else {
+            assert!(request_line.starts_with("POST /dns-query "));
+            assert!(request.contains("Content-Type: application/dns-message\r\n"));
+            let len: usize = request
+                .split_once("Content-Length: ")
+                .unwrap()
+                .1
+                .split("\r\n")
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut query = vec![0u8; len];
+            stream.read_exact(&mut query).unwrap();
+            (DohMethod::Post, query)
+        };
+
+        let response = answer(&query);
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            content_type,
+            response.len()
+        )
+        .unwrap();
+        stream.write_all(&response).unwrap();
+        stream.conn.send_close_notify();
+        stream.flush().unwrap();
+        method
+    }
+
+    fn base64url_decode(s: &str) -> Vec<u8> {
+        const TABLE: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+        let mut bytes = Vec::new();
+        let mut n = 0u32;
+        let mut bits = 0;
+        for c in s.bytes() {
+            n = (n << 6) | TABLE.iter().position(|t| *t == c).unwrap() as u32;
+            bits += 6;
+            if 8 <= bits {
+                bits -= 8;
+                bytes.push((n >> bits) as u8);
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_base64url() {
+        assert_eq!(base64url(b""), "");
+        assert_eq!(base64url(b"f"), "Zg");
+        assert_eq!(base64url(b"fo"), "Zm8");
+        assert_eq!(base64url(b"foo"), "Zm9v");
+        assert_eq!(base64url(&[0xfb, 0xff]), "-_8");
+        assert_eq!(base64url_decode("-_8"), vec![0xfb, 0xff]);
+    }
+
+    #[test]
+    fn test_udp_transport() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buff = [0u8; 512];
+            let (len, peer) = server.recv_from(&mut buff).unwrap();
+            server.send_to(&answer(&buff[..len]), peer).unwrap();
+        });
+
+        let resolver = Resolver::new(UdpTransport::with_addr(addr));
+        assert_eq!(resolver.lookup_host("example.com").unwrap(), ADDR);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            serve_stream(&mut tcp);
+        });
+
+        let transport = TcpTransport::with_connector(addr, "localhost", TcpConnector);
+        let resolver = Resolver::new(transport);
+        assert_eq!(resolver.lookup_host("example.com").unwrap(), ADDR);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_tls_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            serve_stream(&mut tls_accept(tcp));
+        });
+
+        let connector = TlsConnector::with_root_certificates(&[CA]).unwrap();
+        let transport = TlsTransport::with_connector(addr, "localhost", connector);
+        let resolver = Resolver::new(transport);
+        assert_eq!(resolver.lookup_host("example.com").unwrap(), ADDR);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_tls_transport_rejects_untrusted_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut stream = tls_accept(tcp);
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+
+        let transport = TlsTransport::with_connector(addr, "localhost", TlsConnector::new());
+        assert!(Resolver::new(transport).lookup_host("example.com").is_err());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_https_transport() {
+        for method in [DohMethod::Get, DohMethod::Post] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let handle = thread::spawn(move || {
+                let (tcp, _) = listener.accept().unwrap();
+                serve_https(&mut tls_accept(tcp), "application/dns-message")
+            });
+
+            let url = format!("https://localhost:{}/dns-query", addr.port());
+            let connector = TlsConnector::with_root_certificates(&[CA]).unwrap();
+            let transport = HttpsTransport::with_connector(&url, addr, connector)
+                .unwrap()
+                .with_method(method);
+            let resolver = Resolver::new(transport);
+            assert_eq!(resolver.lookup_host("example.com").unwrap(), ADDR);
+            assert_eq!(handle.join().unwrap(), method);
+        }
+    }
+
+    #[test]
+    fn test_udp_transport_discards_spoofed_responses() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buff = [0u8; 512];
+            let (len, peer) = server.recv_from(&mut buff).unwrap();
+            let query = DnsPacket::try_from(&buff[..len]).unwrap();
+            let name = query.questions()[0].qname().to_string();
+            let forge = |id: u16, name: &str, addr: Ipv4Addr| {
+                let mut response = DnsPacket::response(&query);
+                response.set_id(id);
+                response.questions[0].qname = name.into();
+                response.push_answer(Answer::new(
+                    name.into(),
+                    Class::In,
+                    60,
+                    RData::IpAddr(addr.into()),
+                ));
+                response.to_bytes().unwrap()
+            };
+            let spoofed = Ipv4Addr::new(203, 0, 113, 66);
+
+            let attacker = UdpSocket::bind("127.0.0.1:0").unwrap();
+            attacker
+                .send_to(&forge(query.id(), &name, spoofed), peer)
+                .unwrap();
+            server
+                .send_to(&forge(query.id().wrapping_add(1), &name, spoofed), peer)
+                .unwrap();
+            server
+                .send_to(
+                    &forge(query.id(), &name.to_ascii_lowercase(), spoofed),
+                    peer,
+                )
+                .unwrap();
+            server.send_to(b"garbage", peer).unwrap();
+            server
+                .send_to(&forge(query.id(), &name, ADDR), peer)
+                .unwrap();
+        });
+
+        let resolver = Resolver::new(UdpTransport::with_addr(addr));
+        assert_eq!(
+            resolver
+                .lookup_host("abcdefghijklmnopqrstuvwxyz.example.com")
+                .unwrap(),
+            IpAddr::V4(ADDR)
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_transport_rejects_mismatched_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            let mut len_buff = [0u8; 2];
+            tcp.read_exact(&mut len_buff).unwrap();
+            let mut query = vec![0u8; u16::from_be_bytes(len_buff) as usize];
+            tcp.read_exact(&mut query).unwrap();
+            let mut response = DnsPacket::response(&DnsPacket::try_from(query.as_slice()).unwrap());
+            response.set_id(response.id().wrapping_add(1));
+            let response = response.to_bytes().unwrap();
+            tcp.write_all(&(response.len() as u16).to_be_bytes())
+                .unwrap();
+            tcp.write_all(&response).unwrap();
+        });
+
+        let transport = TcpTransport::with_connector(addr, "localhost", TcpConnector);
+        assert_eq!(
+            Resolver::new(transport)
+                .lookup_host("example.com")
+                .unwrap_err(),
+            "mismatched response"
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_udp_transport() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut queries = Vec::new();
+            for _ in 0..4 {
+                let mut buff = [0u8; 512];
+                let (len, peer) = server.recv_from(&mut buff).unwrap();
+                queries.push((buff[..len].to_vec(), peer));
+            }
+            for (query, peer) in queries.iter().rev() {
+                server.send_to(&answer(query), *peer).unwrap();
+            }
+        });
+
+        let resolver = Resolver::new(UdpTransport::with_addr(addr));
+        let lookups = [
+            "a.example.com",
+            "b.example.com",
+            "c.example.com",
+            "d.example.com",
+        ]
+        .map(|host| resolver.lookup_host_async(host))
+        .into();
+        for addr in reactor::block_on(reactor::join_all(lookups)) {
+            assert_eq!(addr.unwrap(), ADDR);
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_tcp_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            serve_stream(&mut tcp);
+        });
+
+        let transport = TcpTransport::with_connector(addr, "localhost", TcpConnector);
+        let resolver = Resolver::new(transport);
+        assert_eq!(
+            reactor::block_on(resolver.lookup_host_async("example.com")).unwrap(),
+            ADDR
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_tls_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            serve_stream(&mut tls_accept(tcp));
+        });
+
+        let connector = TlsConnector::with_root_certificates(&[CA]).unwrap();
+        let transport = TlsTransport::with_connector(addr, "localhost", connector);
+        let resolver = Resolver::new(transport);
+        assert_eq!(
+            reactor::block_on(resolver.lookup_host_async("example.com")).unwrap(),
+            ADDR
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_https_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            serve_https(
+                &mut tls_accept(tcp),
+                "Application/DNS-Message; charset=binary",
+            )
+        });
+
+        let url = format!("https://localhost:{}/dns-query", addr.port());
+        let connector = TlsConnector::with_root_certificates(&[CA]).unwrap();
+        let transport = HttpsTransport::with_connector(&url, addr, connector).unwrap();
+        let resolver = Resolver::new(transport);
+        assert_eq!(
+            reactor::block_on(resolver.lookup_host_async("example.com")).unwrap(),
+            ADDR
+        );
+        assert_eq!(handle.join().unwrap(), DohMethod::Post);
+    }
+}
@@ -1,9 +1,96 @@
+use crate::dns;
+use crate::net;
+use crate::net::AsyncStream;
+use crate::net::AsyncTcpStream;
+use crate::net::AsyncTlsStream;
+use crate::net::Connector;
+use crate::net::TlsConnector;
+use crate::reactor;
 use crate::url::Url;
 use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::io::Result as IoResult;
+use std::net::IpAddr;
+use std::net::SocketAddr;
 use std::str::FromStr;
 
 pub type HttpResult<T> = Result<T, &'static str>;
 
+pub const MAX_REDIRECTS: usize = 10;
+
+pub async fn fetch(request: &HttpRequest, dns: IpAddr) -> HttpResult<HttpResponse> {
+    let url = Url::new(&request.url).map_err(|_| "invalid url")?;
+    let port = match (url.port(), url.scheme()) {
+        (Some(port), _) => port,
+        (None, "http") => 80,
+        (None, "https") => 443,
+        _ => return Err("unsupported scheme"),
+    };
+    let ip = match url.hostname().parse() {
+        Ok(ip) => ip,
+        Err(_) => dns::get_host_addr_async(url.hostname(), dns).await?,
+    };
+    let addr = SocketAddr::new(ip, port);
+
+    let mut stream = match url.scheme() {
+        "http" => HttpStream::Tcp(AsyncTcpStream::connect(addr).await?),
+        "https" => HttpStream::Tls(Box::new(
+            TlsConnector::new()
+                .connect_async(url.hostname(), addr)
+                .await?,
+        )),
+        _ => return Err("unsupported scheme"),
+    };
+    reactor::timeout(net::TIMEOUT, stream.write_all(&request.as_bytes()))
+        .await
+        .ok_or("request timed out")?
+        .map_err(|_| "failed to send request")?;
+
+    let mut response_message = Vec::new();
+    let mut buff = [0u8; 4096];
+    loop {
+        let len = match reactor::timeout(net::TIMEOUT, stream.read(&mut buff))
+            .await
+            .ok_or("response timed out")?
+        {
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => 0,
+            Err(_) => return Err("failed to receive response"),
+        };
+        if len == 0 {
+            break;
+        }
+        response_message.extend_from_slice(&buff[..len]);
+    }
+
+    HttpResponse::from_bytes(&response_message)
+}
+
+pub fn fetch_blocking(request: &HttpRequest, dns: IpAddr) -> HttpResult<HttpResponse> {
+    reactor::block_on(fetch(request, dns))
+}
+
+enum HttpStream {
+    Tcp(AsyncTcpStream),
+    Tls(Box<AsyncTlsStream>),
+}
+
+impl HttpStream {
+    async fn read(&mut self, buff: &mut [u8]) -> IoResult<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buff).await,
+            Self::Tls(stream) => stream.read(buff).await,
+        }
+    }
+
+    async fn write_all(&mut self, buff: &[u8]) -> IoResult<()> {
+        match self {
+            Self::Tcp(stream) => stream.write_all(buff).await,
+            Self::Tls(stream) => stream.write_all(buff).await,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct HttpRequest {
     version: String,
@@ -28,6 +115,14 @@ impl HttpRequest {
         }
     }
 
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
     pub fn header(&self) -> &HashMap<String, String> {
         &self.headers
     }
@@ -37,12 +132,62 @@ impl HttpRequest {
     }
 
     pub fn set_content(&mut self, content: &[u8]) {
+        self.headers
+            .insert("Content-Length".into(), content.len().to_string());
         self.content = Some(content.to_vec());
     }
 
+    /// Builds the request that follows `response` if it is a redirect. Like
+    /// browsers, a 303 or a redirected POST is retried as a GET without the
+    /// body, while 307 and 308 repeat the request as is. Credentials aren't
+    /// sent to a different scheme, host or port.
+    pub fn redirect(&self, response: &HttpResponse) -> HttpResult<Option<HttpRequest>> {
+        let Some(location) = response.location() else {
+            return Ok(None);
+        };
+        let url = Url::new(&self.url).map_err(|_| "invalid url")?;
+        let target = url.join(location);
+        let target = Url::new(&target).map_err(|_| "invalid redirect location")?;
+
+        let as_get = match response.statue() {
+            Status::SeeOther => self.method != "HEAD",
+            Status::MovedPermanently | Status::Found => self.method == "POST",
+            _ => false,
+        };
+        let cross_origin = url.scheme() != target.scheme()
+            || !url.hostname().eq_ignore_ascii_case(target.hostname())
+            || url.port() != target.port();
+        let mut dropped = vec!["Host"];
+        if as_get {
+            dropped.extend(["Content-Length", "Content-Type"]);
+        }
+        if cross_origin {
+            dropped.extend(["Authorization", "Cookie", "Proxy-Authorization"]);
+        }
+
+        let mut request = HttpRequest::new(if as_get { "GET" } else { &self.method }, target);
+        for (name, value) in &self.headers {
+            if !dropped
+                .iter()
+                .any(|dropped| dropped.eq_ignore_ascii_case(name))
+            {
+                request.push_header(name.clone(), value.clone());
+            }
+        }
+        if !as_get {
+            request.content = self.content.clone();
+        }
+        Ok(Some(request))
+    }
+
     pub fn header_as_string(&self) -> String {
         let url = Url::new(&self.url).unwrap();
-        let mut string = format!("{} {} {}\r\n", self.method, url.path(), self.version);
+        let target = if url.query().is_empty() {
+            url.path().to_string()
+        } else {
+            format!("{}?{}", url.path(), url.query())
+        };
+        let mut string = format!("{} {} {}\r\n", self.method, target, self.version);
         for header in &self.headers {
             let (key, value) = header;
             string += &format!("{}: {}\r\n", key, value);
@@ -75,14 +220,49 @@ pub struct HttpResponse {
 }
 
 impl HttpResponse {
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
     pub fn statue(&self) -> Status {
         self.status
     }
 
+    pub fn note(&self) -> &str {
+        &self.note
+    }
+
     pub fn headers(&self) -> &HashMap<String, String> {
         &self.headers
     }
 
+    /// Looks up a header, ignoring the case of `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `charset` parameter of the `Content-Type` header.
+    pub fn charset(&self) -> Option<&str> {
+        self.header("Content-Type")?
+            .split(';')
+            .skip(1)
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    }
+
+    /// The `Location` of a redirect response.
+    pub fn location(&self) -> Option<&str> {
+        if self.status.is_redirect() {
+            self.header("Location")
+        } else {
+            None
+        }
+    }
+
     pub fn content(&self) -> &[u8] {
         &self.content
     }
@@ -109,7 +289,7 @@ impl HttpResponse {
             version: version.trim().into(),
             status: status.trim().parse()?,
             note: note.trim().into(),
-            content: if let Some(ref s) = headers.get("Transfer-Encoding")
+            content: if let Some(s) = headers.get("Transfer-Encoding")
                 && s.as_str() == "chunked"
             {
                 Self::load_chunk_content(content)
@@ -129,7 +309,7 @@ impl HttpResponse {
                 && let Ok(line) = str::from_utf8(line_u8)
                 && let Ok(len) = usize::from_str_radix(line.trim(), 16)
                 && len != 0
-                && let Some(slice) = content.get(..len)
+                && content.get(..len).is_some()
             {
                 content = iter.next().unwrap_or(&[]);
                 if let Some(slice) = content.get(..len) {
@@ -148,13 +328,13 @@ impl HttpResponse {
         let mut i = 0;
 
         loop {
-            if bytes.len() - 2 < i {
+            if bytes.len() < i + 2 {
                 break Err("invalid response");
             }
             if &bytes[i..i + 2] == b"\n\n" {
                 break Ok(bytes.split_at(i + 1));
             }
-            if i <= bytes.len() - 4 && &bytes[i..i + 4] == b"\r\n\r\n" {
+            if bytes[i..].starts_with(b"\r\n\r\n") {
                 break Ok(bytes.split_at(i + 4));
             }
             i += 1;
@@ -193,10 +373,12 @@ pub enum Status {
     NotModified = 304,
     UseProxy = 305,
     TemporaryRedirect = 307,
+    PermanentRedirect = 308,
     BadRequest = 400,
     Unauthorized = 401,
     PaymentRequired = 402,
     Forbidden = 403,
+    NotFound = 404,
     MethodNotAllowed = 405,
     NotAcceptable = 406,
     ProxyAuthenticationRequired = 407,
@@ -210,6 +392,7 @@ pub enum Status {
     UnsupportedMediaType = 415,
     RequestedRangeNotSatisfiable = 416,
     ExpectationFailed = 417,
+    TooManyRequests = 429,
     InternalServerError = 500,
     NotImplemented = 501,
     BadGateway = 502,
@@ -218,6 +401,23 @@ pub enum Status {
     HttpVersionNotSupported = 505,
 }
 
+impl Status {
+    pub fn as_u16(self) -> u16 {
+        self as u16
+    }
+
+    pub fn is_redirect(self) -> bool {
+        matches!(
+            self,
+            Self::MovedPermanently
+                | Self::Found
+                | Self::SeeOther
+                | Self::TemporaryRedirect
+                | Self::PermanentRedirect
+        )
+    }
+}
+
 impl FromStr for Status {
     type Err = &'static str;
 
@@ -248,10 +448,12 @@ impl TryFrom<u32> for Status {
             304 => Ok(Self::NotModified),
             305 => Ok(Self::UseProxy),
             307 => Ok(Self::TemporaryRedirect),
+            308 => Ok(Self::PermanentRedirect),
             400 => Ok(Self::BadRequest),
             401 => Ok(Self::Unauthorized),
             402 => Ok(Self::PaymentRequired),
             403 => Ok(Self::Forbidden),
+            404 => Ok(Self::NotFound),
             405 => Ok(Self::MethodNotAllowed),
             406 => Ok(Self::NotAcceptable),
             407 => Ok(Self::ProxyAuthenticationRequired),
@@ -265,6 +467,7 @@ impl TryFrom<u32> for Status {
             415 => Ok(Self::UnsupportedMediaType),
             416 => Ok(Self::RequestedRangeNotSatisfiable),
             417 => Ok(Self::ExpectationFailed),
+            429 => Ok(Self::TooManyRequests),
             500 => Ok(Self::InternalServerError),
             501 => Ok(Self::NotImplemented),
             502 => Ok(Self::BadGateway),
@@ -275,3 +478,140 @@ impl TryFrom<u32> for Status {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::Ipv4Addr;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const NO_DNS: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+    #[test]
+    fn test_fetch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            for _ in 0..3 {
+                let (mut tcp, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0u8];
+                    tcp.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let path = request.split(' ').nth(1).unwrap().to_string();
+                write!(
+                    tcp,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    path.len(),
+                    path
+                )
+                .unwrap();
+            }
+        });
+
+        let requests: Vec<_> = ["/a", "/b", "/c?d"]
+            .iter()
+            .map(|path| {
+                let url = format!("http://{}{}", addr, path);
+                HttpRequest::new("GET", Url::new(&url).unwrap())
+            })
+            .collect();
+        let responses = reactor::block_on(reactor::join_all(
+            requests.iter().map(|r| fetch(r, NO_DNS)).collect(),
+        ));
+        for (response, path) in responses.into_iter().zip(["/a", "/b", "/c?d"]) {
+            let response = response.unwrap();
+            assert_eq!(response.statue(), Status::Ok);
+            assert_eq!(response.content(), path.as_bytes());
+        }
+        handle.join().unwrap();
+
+        let url = format!("http://{}/", addr);
+        let request = HttpRequest::new("GET", Url::new(&url).unwrap());
+        assert!(fetch_blocking(&request, NO_DNS).is_err());
+    }
+
+    #[test]
+    fn test_redirect() {
+        let mut request = HttpRequest::new("POST", Url::new("http://a.example/b/c").unwrap());
+        request.push_header("Content-Type".into(), "text/plain".into());
+        request.push_header("X-Trace".into(), "1".into());
+        request.set_content(b"body");
+
+        let response =
+            HttpResponse::from_bytes(b"HTTP/1.1 302 Found\r\nlocation: ../d?e\r\n\r\n").unwrap();
+        let redirected = request.redirect(&response).unwrap().unwrap();
+        assert_eq!(redirected.method(), "GET");
+        assert_eq!(redirected.url(), "http://a.example/d?e");
+        assert_eq!(redirected.header().get("X-Trace").unwrap(), "1");
+        assert!(!redirected.header().contains_key("Content-Type"));
+        assert!(redirected.content.is_none());
+
+        let response = HttpResponse::from_bytes(
+            b"HTTP/1.1 308 Permanent Redirect\r\nLocation: https://b.example/\r\n\r\n",
+        )
+        .unwrap();
+        let redirected = request.redirect(&response).unwrap().unwrap();
+        assert_eq!(redirected.method(), "POST");
+        assert_eq!(redirected.url(), "https://b.example/");
+        assert_eq!(redirected.header().get("Host").unwrap(), "b.example");
+        assert_eq!(redirected.content.as_deref(), Some(b"body".as_slice()));
+
+        let response = HttpResponse::from_bytes(b"HTTP/1.1 404 Not Found\r\n\r\n").unwrap();
+        assert!(request.redirect(&response).unwrap().is_none());
+        assert!(HttpResponse::from_bytes(b"HTT").is_err());
+    }
+
+    #[test]
+    fn test_redirect_credentials() {
+        let mut request = HttpRequest::new("GET", Url::new("https://a.example/").unwrap());
+        request.push_header("Authorization".into(), "Bearer t".into());
+        request.push_header("cookie".into(), "s=1".into());
+        request.push_header("Proxy-Authorization".into(), "Basic p".into());
+
+        let redirect = |location: &str| {
+            let response = HttpResponse::from_bytes(
+                format!("HTTP/1.1 302 Found\r\nLocation: {}\r\n\r\n", location).as_bytes(),
+            )
+            .unwrap();
+            request.redirect(&response).unwrap().unwrap()
+        };
+
+        let redirected = redirect("/b");
+        assert_eq!(
+            redirected.header().get("Authorization").unwrap(),
+            "Bearer t"
+        );
+        assert_eq!(redirected.header().get("cookie").unwrap(), "s=1");
+
+        for location in [
+            "https://b.example/",
+            "http://a.example/",
+            "https://a.example:8443/",
+        ] {
+            let redirected = redirect(location);
+            assert!(!redirected.header().contains_key("Authorization"));
+            assert!(!redirected.header().contains_key("cookie"));
+            assert!(!redirected.header().contains_key("Proxy-Authorization"));
+        }
+    }
+
+    #[test]
+    fn test_charset() {
+        let response = HttpResponse::from_bytes(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/html; Charset=\"Shift_JIS\"\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(response.charset(), Some("Shift_JIS"));
+        let response =
+            HttpResponse::from_bytes(b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n")
+                .unwrap();
+        assert_eq!(response.charset(), None);
+    }
+}
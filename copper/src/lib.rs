@@ -2,4 +2,6 @@
 
 pub mod dns;
 pub mod http;
+pub mod net;
+pub mod reactor;
 pub mod url;
@@ -1,30 +1,25 @@
-use copper::dns::CLOUDFLARE_DNS;
-use copper::dns::get_host_addr;
-use copper::http::HttpRequest;
-use copper::http::HttpResponse;
-use copper::url::Url;
-use std::io::Read;
-use std::io::Write;
-use std::net::TcpStream;
+mod cli;
 
-fn main() {
-    const ADDR: &str = "http://www.google.com/";
+use std::env;
+use std::process::ExitCode;
 
-    let url: Url<'_> = ADDR.try_into().unwrap();
-    println!("{:?}", url);
+const USAGE: &str = "\
+usage: copper dns [@server] [name] [type] [class] [-x addr] [--hex packet] [+tcp] [+tls[=name]] [+https[=url]] [+short]
+       copper http [-X method] [-H header]... [-d data] [-i] [-L] [-o file] [-v] [--dns addr] url";
 
-    let ip = get_host_addr(url.host(), CLOUDFLARE_DNS).unwrap();
-    println!("{:?}", ip);
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("dns") => cli::dns::run(&args[1..]),
+        Some("http") => cli::http::run(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
 
-    let mut http_request = HttpRequest::new("GET", url);
-    let http_message = http_request.as_bytes();
-    println!("{}", str::from_utf8(&http_message).unwrap());
-
-    let mut tcp = TcpStream::connect((ip, 80)).unwrap();
-    tcp.write(&http_message).unwrap();
-    let mut response_message = Vec::new();
-    tcp.read_to_end(&mut response_message).unwrap();
-    let response = HttpResponse::from_bytes(&response_message).unwrap();
-    println!("{:?}", response);
-    println!("{}", str::from_utf8(response.content()).unwrap());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("copper: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
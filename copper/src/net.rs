@@ -0,0 +1,453 @@
+use crate::reactor;
+use crate::reactor::Direction;
+use crate::reactor::Reactor;
+use crate::reactor::Registration;
+use mio::net::TcpStream as MioTcpStream;
+use mio::net::UdpSocket as MioUdpSocket;
+use rustls::ClientConfig;
+use rustls::ClientConnection;
+use rustls::RootCertStore;
+use rustls::StreamOwned;
+use rustls::crypto::ring;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::ServerName;
+use std::future::Future;
+use std::future::poll_fn;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type NetResult<T> = Result<T, &'static str>;
+
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub trait Connector {
+    type Stream: Read + Write;
+    type AsyncStream: AsyncStream;
+
+    fn connect(&self, server_name: &str, addr: SocketAddr) -> NetResult<Self::Stream>;
+
+    fn connect_async(
+        &self,
+        server_name: &str,
+        addr: SocketAddr,
+    ) -> impl Future<Output = NetResult<Self::AsyncStream>>;
+}
+
+/// A non-blocking stream driven by the reactor.
+pub trait AsyncStream {
+    fn read(&mut self, buff: &mut [u8]) -> impl Future<Output = IoResult<usize>>;
+
+    fn write_all(&mut self, buff: &[u8]) -> impl Future<Output = IoResult<()>>;
+
+    fn read_exact(&mut self, mut buff: &mut [u8]) -> impl Future<Output = IoResult<()>> {
+        async move {
+            while !buff.is_empty() {
+                match self.read(buff).await? {
+                    0 => return Err(ErrorKind::UnexpectedEof.into()),
+                    len => buff = &mut buff[len..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Like `read_to_end`, but for a non-blocking stream.
+    fn read_to_end(&mut self, buff: &mut Vec<u8>) -> impl Future<Output = IoResult<usize>> {
+        async move {
+            let len = buff.len();
+            let mut chunk = [0u8; 4096];
+            loop {
+                match self.read(&mut chunk).await {
+                    Ok(0) => return Ok(buff.len() - len),
+                    Ok(n) => buff.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(buff.len() - len),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpConnector;
+
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+    type AsyncStream = AsyncTcpStream;
+
+    fn connect(&self, _server_name: &str, addr: SocketAddr) -> NetResult<TcpStream> {
+        let tcp = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|_| "failed to connect")?;
+        tcp.set_read_timeout(Some(TIMEOUT))
+            .map_err(|_| "failed to set read timeout")?;
+        tcp.set_write_timeout(Some(TIMEOUT))
+            .map_err(|_| "failed to set write timeout")?;
+        Ok(tcp)
+    }
+
+    async fn connect_async(
+        &self,
+        _server_name: &str,
+        addr: SocketAddr,
+    ) -> NetResult<AsyncTcpStream> {
+        AsyncTcpStream::connect(addr).await
+    }
+}
+
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+#[derive(Clone, Debug)]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl Default for TlsConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsConnector {
+    pub fn new() -> Self {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Self::with_root_store(roots).expect("failed to build default tls config")
+    }
+
+    pub fn with_root_certificates(certificates: &[&[u8]]) -> NetResult<Self> {
+        let mut roots = RootCertStore::empty();
+        for certificate in certificates {
+            roots
+                .add(CertificateDer::from(certificate.to_vec()))
+                .map_err(|_| "invalid root certificate")?;
+        }
+        Self::with_root_store(roots)
+    }
+
+    fn with_root_store(roots: RootCertStore) -> NetResult<Self> {
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|_| "unsupported tls protocol versions")?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+}
+
+impl Connector for TlsConnector {
+    type Stream = TlsStream;
+    type AsyncStream = AsyncTlsStream;
+
+    fn connect(&self, server_name: &str, addr: SocketAddr) -> NetResult<TlsStream> {
+        let tcp = TcpConnector.connect(server_name, addr)?;
+        let server_name =
+            ServerName::try_from(server_name.to_string()).map_err(|_| "invalid server name")?;
+        let connection = ClientConnection::new(self.config.clone(), server_name)
+            .map_err(|_| "failed to start tls session")?;
+        Ok(StreamOwned::new(connection, tcp))
+    }
+
+    async fn connect_async(
+        &self,
+        server_name: &str,
+        addr: SocketAddr,
+    ) -> NetResult<AsyncTlsStream> {
+        let tcp = AsyncTcpStream::connect(addr).await?;
+        let server_name =
+            ServerName::try_from(server_name.to_string()).map_err(|_| "invalid server name")?;
+        let connection = ClientConnection::new(self.config.clone(), server_name)
+            .map_err(|_| "failed to start tls session")?;
+        let mut stream = AsyncTlsStream { tcp, connection };
+        reactor::timeout(TIMEOUT, stream.handshake())
+            .await
+            .ok_or("tls handshake timed out")?
+            .map_err(|_| "tls handshake failed")?;
+        Ok(stream)
+    }
+}
+
+/// Like `Read::read_to_end`, but treats a peer that closes the connection
+/// without a TLS close_notify as a regular end of stream.
+pub fn read_to_end(stream: &mut impl Read, buff: &mut Vec<u8>) -> IoResult<usize> {
+    let len = buff.len();
+    match stream.read_to_end(buff) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(buff.len() - len),
+        result => result,
+    }
+}
+
+pub struct AsyncTcpStream {
+    stream: MioTcpStream,
+    registration: Registration,
+}
+
+impl AsyncTcpStream {
+    pub async fn connect(addr: SocketAddr) -> NetResult<Self> {
+        let mut stream = MioTcpStream::connect(addr).map_err(|_| "failed to connect")?;
+        let registration = Reactor::get()
+            .register(&mut stream)
+            .map_err(|_| "failed to register socket")?;
+        let this = Self {
+            stream,
+            registration,
+        };
+
+        let connected = this.write_with(|stream| {
+            if let Some(e) = stream.take_error()? {
+                return Err(e);
+            }
+            match stream.peer_addr() {
+                Err(e) if e.kind() == ErrorKind::NotConnected => {
+                    Err(IoError::from(ErrorKind::WouldBlock))
+                }
+                result => result.map(|_| ()),
+            }
+        });
+        reactor::timeout(TIMEOUT, connected)
+            .await
+            .ok_or("connection timed out")?
+            .map_err(|_| "failed to connect")?;
+        Ok(this)
+    }
+
+    async fn read_with<R>(&self, mut op: impl FnMut(&MioTcpStream) -> IoResult<R>) -> IoResult<R> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Direction::Read, || op(&self.stream))
+        })
+        .await
+    }
+
+    async fn write_with<R>(&self, mut op: impl FnMut(&MioTcpStream) -> IoResult<R>) -> IoResult<R> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Direction::Write, || op(&self.stream))
+        })
+        .await
+    }
+}
+
+impl AsyncStream for AsyncTcpStream {
+    async fn read(&mut self, buff: &mut [u8]) -> IoResult<usize> {
+        self.read_with(|mut stream| stream.read(buff)).await
+    }
+
+    async fn write_all(&mut self, mut buff: &[u8]) -> IoResult<()> {
+        while !buff.is_empty() {
+            match self.write_with(|mut stream| stream.write(buff)).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                len => buff = &buff[len..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AsyncTcpStream {
+    fn drop(&mut self) {
+        Reactor::get().deregister(&mut self.stream, &self.registration);
+    }
+}
+
+pub struct AsyncTlsStream {
+    tcp: AsyncTcpStream,
+    connection: ClientConnection,
+}
+
+impl AsyncTlsStream {
+    async fn write_tls(&mut self) -> IoResult<()> {
+        while self.connection.wants_write() {
+            let connection = &mut self.connection;
+            self.tcp
+                .write_with(|mut stream| connection.write_tls(&mut stream))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_tls(&mut self) -> IoResult<usize> {
+        let connection = &mut self.connection;
+        let len = self
+            .tcp
+            .read_with(|mut stream| connection.read_tls(&mut stream))
+            .await?;
+        self.connection
+            .process_new_packets()
+            .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+        Ok(len)
+    }
+
+    async fn handshake(&mut self) -> IoResult<()> {
+        while self.connection.is_handshaking() {
+            self.write_tls().await?;
+            if self.connection.is_handshaking() && self.read_tls().await? == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+        self.write_tls().await
+    }
+}
+
+impl AsyncStream for AsyncTlsStream {
+    async fn read(&mut self, buff: &mut [u8]) -> IoResult<usize> {
+        loop {
+            match self.connection.reader().read(buff) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.read_tls().await?;
+                    self.write_tls().await?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Plaintext is buffered by the connection until it is encrypted, so
+    /// each chunk is flushed to the socket before the next one is written.
+    async fn write_all(&mut self, mut buff: &[u8]) -> IoResult<()> {
+        while !buff.is_empty() {
+            match self.connection.writer().write(buff)? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                len => buff = &buff[len..],
+            }
+            self.write_tls().await?;
+        }
+        Ok(())
+    }
+}
+
+pub struct AsyncUdpSocket {
+    socket: MioUdpSocket,
+    registration: Registration,
+}
+
+impl AsyncUdpSocket {
+    pub fn bind(addr: SocketAddr) -> IoResult<Self> {
+        let mut socket = MioUdpSocket::bind(addr)?;
+        let registration = Reactor::get().register(&mut socket)?;
+        Ok(Self {
+            socket,
+            registration,
+        })
+    }
+
+    pub async fn send_to(&self, buff: &[u8], addr: SocketAddr) -> IoResult<usize> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Direction::Write, || self.socket.send_to(buff, addr))
+        })
+        .await
+    }
+
+    pub async fn recv_from(&self, buff: &mut [u8]) -> IoResult<(usize, SocketAddr)> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Direction::Read, || self.socket.recv_from(buff))
+        })
+        .await
+    }
+}
+
+impl Drop for AsyncUdpSocket {
+    fn drop(&mut self) {
+        Reactor::get().deregister(&mut self.socket, &self.registration);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use rustls::ServerConfig;
+    use rustls::ServerConnection;
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use std::net::TcpListener;
+    use std::thread;
+
+    pub(crate) const CA: &[u8] = include_bytes!("../testdata/ca.der");
+    const CERTIFICATE: &[u8] = include_bytes!("../testdata/localhost.der");
+    const PRIVATE_KEY: &[u8] = include_bytes!("../testdata/localhost.key.der");
+
+    pub(crate) fn tls_accept(tcp: TcpStream) -> StreamOwned<ServerConnection, TcpStream> {
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(CERTIFICATE.to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(PRIVATE_KEY.to_vec())),
+            )
+            .unwrap();
+        StreamOwned::new(ServerConnection::new(Arc::new(config)).unwrap(), tcp)
+    }
+
+    #[test]
+    fn test_async_tcp_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            let mut buff = [0u8; 5];
+            tcp.read_exact(&mut buff).unwrap();
+            tcp.write_all(&buff.repeat(1000)).unwrap();
+        });
+
+        let received = reactor::block_on(async {
+            let mut tcp = AsyncTcpStream::connect(addr).await.unwrap();
+            tcp.write_all(b"hello").await.unwrap();
+            let mut received = vec![0u8; 5000];
+            tcp.read_exact(&mut received).await.unwrap();
+            received
+        });
+        assert_eq!(received, b"hello".repeat(1000));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_tls_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut stream = tls_accept(tcp);
+            let mut buff = vec![0u8; 100000];
+            stream.read_exact(&mut buff).unwrap();
+            stream.write_all(&buff[..5].repeat(10000)).unwrap();
+            stream.conn.send_close_notify();
+            stream.flush().unwrap();
+        });
+
+        let received = reactor::block_on(async {
+            let connector = TlsConnector::with_root_certificates(&[CA]).unwrap();
+            let mut stream = connector.connect_async("localhost", addr).await.unwrap();
+            stream.write_all(&b"hello".repeat(20000)).await.unwrap();
+            let mut received = Vec::new();
+            let mut buff = [0u8; 4096];
+            loop {
+                match stream.read(&mut buff).await.unwrap() {
+                    0 => break,
+                    len => received.extend_from_slice(&buff[..len]),
+                }
+            }
+            received
+        });
+        assert_eq!(received, b"hello".repeat(10000));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_connect_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(reactor::block_on(AsyncTcpStream::connect(addr)).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use mio::Events;
+use mio::Interest;
+use mio::Poll as MioPoll;
+use mio::Registry;
+use mio::Token;
+use mio::Waker as MioWaker;
+use mio::event::Source;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::future::Future;
+use std::future::poll_fn;
+use std::io::ErrorKind;
+use std::io::Result as IoResult;
+use std::pin::Pin;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Wake;
+use std::task::Waker;
+use std::thread;
+use std::thread::Thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const WAKE_TOKEN: Token = Token(usize::MAX);
+
+static REACTOR: LazyLock<Reactor> = LazyLock::new(Reactor::start);
+
+/// A single background thread that waits on `mio::Poll` and wakes the tasks
+/// whose sockets became ready or whose timers expired. The futures in copper
+/// only rely on `Waker`, so they can be driven by any executor.
+pub struct Reactor {
+    registry: Registry,
+    waker: MioWaker,
+    state: Mutex<ReactorState>,
+}
+
+#[derive(Default)]
+struct ReactorState {
+    next_token: usize,
+    sources: HashMap<usize, Arc<Mutex<Readiness>>>,
+    next_timer: usize,
+    timers: BTreeMap<(Instant, usize), Waker>,
+}
+
+#[derive(Default)]
+struct Readiness {
+    readable: bool,
+    writable: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Read,
+    Write,
+}
+
+impl Readiness {
+    fn ready(&mut self, direction: Direction) -> &mut bool {
+        match direction {
+            Direction::Read => &mut self.readable,
+            Direction::Write => &mut self.writable,
+        }
+    }
+
+    fn waker(&mut self, direction: Direction) -> &mut Option<Waker> {
+        match direction {
+            Direction::Read => &mut self.read_waker,
+            Direction::Write => &mut self.write_waker,
+        }
+    }
+}
+
+impl Reactor {
+    pub fn get() -> &'static Self {
+        &REACTOR
+    }
+
+    fn start() -> Self {
+        let poll = MioPoll::new().expect("failed to create poll");
+        let registry = poll
+            .registry()
+            .try_clone()
+            .expect("failed to clone registry");
+        let waker = MioWaker::new(&registry, WAKE_TOKEN).expect("failed to create waker");
+        thread::Builder::new()
+            .name("copper-reactor".into())
+            .spawn(move || Self::get().run(poll))
+            .expect("failed to spawn reactor thread");
+
+        Self {
+            registry,
+            waker,
+            state: Mutex::new(ReactorState::default()),
+        }
+    }
+
+    fn run(&self, mut poll: MioPoll) {
+        let mut events = Events::with_capacity(256);
+        loop {
+            let timeout = self.fire_timers();
+            match poll.poll(&mut events, timeout) {
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => panic!("reactor failed: {}", e),
+            }
+
+            for event in &events {
+                if event.token() == WAKE_TOKEN {
+                    continue;
+                }
+                let Some(readiness) = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .sources
+                    .get(&event.token().0)
+                    .cloned()
+                else {
+                    continue;
+                };
+                let mut wakers = Vec::new();
+                let mut readiness = readiness.lock().unwrap();
+                if event.is_readable() || event.is_read_closed() || event.is_error() {
+                    readiness.readable = true;
+                    wakers.extend(readiness.read_waker.take());
+                }
+                if event.is_writable() || event.is_write_closed() || event.is_error() {
+                    readiness.writable = true;
+                    wakers.extend(readiness.write_waker.take());
+                }
+                drop(readiness);
+                wakers.into_iter().for_each(Waker::wake);
+            }
+        }
+    }
+
+    fn fire_timers(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let pending = state.timers.split_off(&(now, usize::MAX));
+        let expired = std::mem::replace(&mut state.timers, pending);
+        let timeout = state
+            .timers
+            .keys()
+            .next()
+            .map(|(deadline, _)| deadline.saturating_duration_since(now));
+        drop(state);
+
+        expired.into_values().for_each(Waker::wake);
+        timeout
+    }
+
+    pub(crate) fn register(&self, source: &mut impl Source) -> IoResult<Registration> {
+        let readiness = Arc::new(Mutex::new(Readiness::default()));
+        let token = {
+            let mut state = self.state.lock().unwrap();
+            let token = state.next_token;
+            state.next_token += 1;
+            state.sources.insert(token, readiness.clone());
+            token
+        };
+        if let Err(e) = self.registry.register(
+            source,
+            Token(token),
+            Interest::READABLE | Interest::WRITABLE,
+        ) {
+            self.state.lock().unwrap().sources.remove(&token);
+            return Err(e);
+        }
+
+        Ok(Registration { token, readiness })
+    }
+
+    pub(crate) fn deregister(&self, source: &mut impl Source, registration: &Registration) {
+        let _ = self.registry.deregister(source);
+        self.state
+            .lock()
+            .unwrap()
+            .sources
+            .remove(&registration.token);
+    }
+
+    fn set_timer(&self, key: &mut Option<(Instant, usize)>, deadline: Instant, waker: Waker) {
+        let mut state = self.state.lock().unwrap();
+        let key = *key.get_or_insert_with(|| {
+            state.next_timer += 1;
+            (deadline, state.next_timer)
+        });
+        state.timers.insert(key, waker);
+        drop(state);
+        let _ = self.waker.wake();
+    }
+
+    fn remove_timer(&self, key: (Instant, usize)) {
+        self.state.lock().unwrap().timers.remove(&key);
+    }
+}
+
+pub(crate) struct Registration {
+    token: usize,
+    readiness: Arc<Mutex<Readiness>>,
+}
+
+impl Registration {
+    /// Runs a non-blocking `op` until it stops returning `WouldBlock`,
+    /// parking the task on the reactor in between.
+    pub(crate) fn poll_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        direction: Direction,
+        mut op: impl FnMut() -> IoResult<R>,
+    ) -> Poll<IoResult<R>> {
+        loop {
+            *self.readiness.lock().unwrap().ready(direction) = false;
+            match op() {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    let mut readiness = self.readiness.lock().unwrap();
+                    if *readiness.ready(direction) {
+                        continue;
+                    }
+                    *readiness.waker(direction) = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+    key: Option<(Instant, usize)>,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        key: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.deadline <= Instant::now() {
+            if let Some(key) = this.key.take() {
+                Reactor::get().remove_timer(key);
+            }
+            Poll::Ready(())
+        } else {
+            Reactor::get().set_timer(&mut this.key, this.deadline, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            Reactor::get().remove_timer(key);
+        }
+    }
+}
+
+/// Resolves to `None` if `future` doesn't complete within `duration`.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut sleep = sleep(duration);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            Poll::Ready(Some(output))
+        } else if Pin::new(&mut sleep).poll(cx).is_ready() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Polls every future concurrently and collects the outputs in order.
+pub async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    poll_fn(|cx| {
+        let mut ready = true;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_none() {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => *output = Some(value),
+                    Poll::Pending => ready = false,
+                }
+            }
+        }
+        if ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    outputs.into_iter().map(Option::unwrap).collect()
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread. This is the executor
+/// behind copper's blocking API.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sleep_and_timeout() {
+        let start = Instant::now();
+        block_on(sleep(Duration::from_millis(20)));
+        assert!(Duration::from_millis(20) <= start.elapsed());
+
+        let output = block_on(timeout(
+            Duration::from_millis(10),
+            sleep(Duration::from_secs(10)),
+        ));
+        assert_eq!(output, None);
+        let output = block_on(timeout(Duration::from_secs(10), async { 42 }));
+        assert_eq!(output, Some(42));
+    }
+
+    #[test]
+    fn test_join_all() {
+        let start = Instant::now();
+        let futures = (0..8)
+            .map(|i| async move {
+                sleep(Duration::from_millis(50)).await;
+                i
+            })
+            .collect();
+        assert_eq!(block_on(join_all(futures)), (0..8).collect::<Vec<_>>());
+        assert!(start.elapsed() < Duration::from_millis(400));
+    }
+}
@@ -1,5 +1,3 @@
-use std::str::FromStr;
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Url<'a> {
     raw: &'a str,
@@ -9,16 +7,6 @@ pub struct Url<'a> {
     query: &'a str,
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn test_url() {
-        assert_eq!(Url::new("http://example.com/").unwrap(), Url::EXAMPLE_COM);
-    }
-}
-
 impl<'a> TryFrom<&'a str> for Url<'a> {
     type Error = String;
 
@@ -75,6 +63,23 @@ impl<'a> Url<'a> {
         self.host
     }
 
+    pub fn hostname(&self) -> &'a str {
+        if let Some(remaining) = self.host.strip_prefix('[') {
+            remaining.split(']').next().unwrap_or(remaining)
+        } else {
+            self.host.split(':').next().unwrap_or(self.host)
+        }
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        let port = if self.host.starts_with('[') {
+            self.host.rsplit_once("]:")?.1
+        } else {
+            self.host.rsplit_once(':')?.1
+        };
+        port.parse().ok()
+    }
+
     pub fn path(&self) -> &'a str {
         self.path
     }
@@ -82,4 +87,104 @@ impl<'a> Url<'a> {
     pub fn query(&self) -> &'a str {
         self.query
     }
+
+    /// Resolves `reference` against this URL as a base (RFC 3986 section 5.2).
+    /// Fragments are dropped since they are never sent to a server.
+    pub fn join(&self, reference: &str) -> String {
+        let reference = reference.trim();
+        let reference = reference.split('#').next().unwrap_or(reference);
+        if reference.contains("://") {
+            return reference.to_string();
+        }
+        if let Some(remaining) = reference.strip_prefix("//") {
+            return format!("{}://{}", self.scheme, remaining);
+        }
+
+        let (path, query) = match reference.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (reference, None),
+        };
+        let path = if path.is_empty() {
+            self.path.split('#').next().unwrap_or(self.path).to_string()
+        } else if path.starts_with('/') {
+            remove_dot_segments(path)
+        } else {
+            let directory = &self.path[..self.path.rfind('/').map_or(0, |i| i + 1)];
+            remove_dot_segments(&format!("/{}{}", directory.trim_start_matches('/'), path))
+        };
+        let query = match query {
+            Some(query) => query,
+            None if reference.is_empty() => self.query.split('#').next().unwrap_or(""),
+            None => "",
+        };
+
+        let mut url = format!("{}://{}{}", self.scheme, self.host, path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." => (),
+            ".." => {
+                segments.pop();
+            }
+            _ => {
+                segments.push(part);
+                continue;
+            }
+        }
+        if last {
+            segments.push("");
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_url() {
+        assert_eq!(Url::new("http://example.com/").unwrap(), Url::EXAMPLE_COM);
+    }
+
+    #[test]
+    fn test_url_port() {
+        let url = Url::new("https://localhost:8443/dns-query?dns=AAAB").unwrap();
+        assert_eq!(url.hostname(), "localhost");
+        assert_eq!(url.port(), Some(8443));
+        assert_eq!(url.path(), "/dns-query");
+        assert_eq!(url.query(), "dns=AAAB");
+
+        let url = Url::new("https://[::1]:853/").unwrap();
+        assert_eq!(url.hostname(), "::1");
+        assert_eq!(url.port(), Some(853));
+
+        assert_eq!(Url::EXAMPLE_COM.port(), None);
+    }
+
+    #[test]
+    fn test_url_join() {
+        let base = Url::new("http://a.example/b/c/d?q").unwrap();
+        assert_eq!(base.join("g"), "http://a.example/b/c/g");
+        assert_eq!(base.join("./g/"), "http://a.example/b/c/g/");
+        assert_eq!(base.join("../g"), "http://a.example/b/g");
+        assert_eq!(base.join("../../../g"), "http://a.example/g");
+        assert_eq!(base.join(".."), "http://a.example/b/");
+        assert_eq!(base.join("/g?y#s"), "http://a.example/g?y");
+        assert_eq!(base.join("?y"), "http://a.example/b/c/d?y");
+        assert_eq!(base.join(""), "http://a.example/b/c/d?q");
+        assert_eq!(base.join("//g.example/x"), "http://g.example/x");
+        assert_eq!(base.join("https://g.example/"), "https://g.example/");
+    }
 }
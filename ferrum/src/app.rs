@@ -1,6 +1,7 @@
 use copper::dns;
+use copper::http;
 use copper::http::HttpRequest;
-use copper::http::HttpResponse;
+use copper::reactor;
 use copper::url::Url;
 use magnetite::css::CssomArena;
 use magnetite::html::*;
@@ -8,41 +9,60 @@ use magnetite::render::*;
 use softbuffer::Context;
 use softbuffer::Surface;
 use std::io::Cursor;
-use std::io::Read;
-use std::io::Write;
-use std::net::TcpStream;
 use std::num::NonZeroU32;
 use std::rc::Rc;
+use std::thread;
 use winit::application::ApplicationHandler;
 use winit::dpi::PhysicalSize;
 use winit::dpi::Size;
 use winit::event::WindowEvent;
 use winit::event_loop::ActiveEventLoop;
 use winit::event_loop::EventLoop;
+use winit::event_loop::EventLoopProxy;
 use winit::window::Window;
 use winit::window::WindowId;
 
-fn get_stream() -> impl Read {
-    const ADDR: &str = "http://www.example.com/";
+const URL: &str = "http://www.example.com/";
 
-    let url: Url<'_> = ADDR.try_into().unwrap();
-    let ip = dns::get_host_addr(url.host(), dns::CLOUDFLARE_DNS).unwrap();
+/// A fetched document, with the charset its `Content-Type` declared.
+pub struct Resource {
+    content: Vec<u8>,
+    charset: Option<String>,
+}
 
-    let mut http_request = HttpRequest::new("GET", url);
-    let http_message = http_request.as_bytes();
+/// Fetches `url` with the async `http::fetch`, driven by the reactor on a
+/// worker thread so the event loop stays responsive, and hands the body over
+/// to the window through `proxy` once it arrives.
+fn spawn_fetch(url: &str, proxy: EventLoopProxy<Resource>) {
+    let url = url.to_string();
+    thread::spawn(move || {
+        let resource = reactor::block_on(fetch(&url));
+        let _ = proxy.send_event(resource);
+    });
+}
 
-    let mut tcp = TcpStream::connect((ip, 80)).unwrap();
-    tcp.write(&http_message).unwrap();
-    let mut response_message = Vec::new();
-    tcp.read_to_end(&mut response_message).unwrap();
-    let response = HttpResponse::from_bytes(&response_message).unwrap();
-    println!("{}", str::from_utf8(response.content()).unwrap());
-    Cursor::new(response.content().to_vec())
+/// Fetches `url`, falling back to the sample page if the request fails.
+async fn fetch(url: &str) -> Resource {
+    let response = match Url::new(url) {
+        Ok(url) => http::fetch(&HttpRequest::new("GET", url), dns::CLOUDFLARE_DNS).await,
+        Err(_) => Err("invalid url"),
+    };
+    match response {
+        Ok(response) => Resource {
+            content: response.content().to_vec(),
+            charset: response.charset().map(String::from),
+        },
+        Err(e) => {
+            eprintln!("failed to fetch {}: {}", url, e);
+            Resource {
+                content: SAMPLE_PAGE.as_bytes().to_vec(),
+                charset: None,
+            }
+        }
+    }
 }
 
-pub fn view() {
-    let stream = Cursor::new(
-        r#"
+const SAMPLE_PAGE: &str = r#"
 <!DOCTYPE html>
 <html><head>
     <title>Example Domain</title>
@@ -87,17 +107,13 @@ pub fn view() {
     <p><a href="https://www.iana.org/domains/example">More information...</a></p>
 </div>
 </body></html>
-"#,
-    );
+"#;
 
-    let stream = get_stream();
+pub fn view() {
+    let event_loop = EventLoop::<Resource>::with_user_event().build().unwrap();
+    spawn_fetch(URL, event_loop.create_proxy());
 
-    let mut app = Ferrum::new(
-        stream,
-        NonZeroU32::new(800).unwrap(),
-        NonZeroU32::new(600).unwrap(),
-    );
-    let event_loop = EventLoop::new().unwrap();
+    let mut app = Ferrum::new(NonZeroU32::new(800).unwrap(), NonZeroU32::new(600).unwrap());
     event_loop.run_app(&mut app).unwrap();
 }
 
@@ -106,14 +122,21 @@ pub struct Ferrum {
     height: NonZeroU32,
     window: Option<Rc<Window>>,
     surface: Option<Surface<Rc<Window>, Rc<Window>>>,
+    page: Option<Page>,
+}
+
+struct Page {
     dom: DomArena,
     cssom: CssomArena,
     renderer: Renderer,
 }
 
-impl Ferrum {
-    pub fn new(stream: impl Read, width: NonZeroU32, height: NonZeroU32) -> Self {
-        let byte_stream_decoder = ByteStreamDecoder::new(stream);
+impl Page {
+    fn new(resource: Resource, width: NonZeroU32) -> Self {
+        let mut byte_stream_decoder = ByteStreamDecoder::new(Cursor::new(resource.content));
+        if let Some(charset) = resource.charset {
+            byte_stream_decoder = byte_stream_decoder.with_transport_charset(&charset);
+        }
         let input_stream_preprocessor = InputStreamPreprocessor::new(byte_stream_decoder).unwrap();
         let mut tree_constructor = TreeConstructor::new();
         let mut tokenizer = Tokenizer::new(input_stream_preprocessor, &mut tree_constructor);
@@ -130,14 +153,28 @@ impl Ferrum {
         println!("{}", *layout_arena);
         let renderer = Renderer::new(render_arena, layout_arena);
 
+        Self {
+            dom,
+            cssom,
+            renderer,
+        }
+    }
+
+    fn layout(&mut self, width: NonZeroU32) {
+        let render_arena = RenderArena::new(&self.dom, &self.cssom);
+        let layout_arena = LayoutArena::new(&render_arena, width.get() as f32);
+        self.renderer = Renderer::new(render_arena, layout_arena);
+    }
+}
+
+impl Ferrum {
+    pub fn new(width: NonZeroU32, height: NonZeroU32) -> Self {
         Self {
             width,
             height,
             window: None,
             surface: None,
-            dom,
-            renderer,
-            cssom,
+            page: None,
         }
     }
 
@@ -145,9 +182,9 @@ impl Ferrum {
         self.width = width;
         self.height = height;
 
-        let render_arena = RenderArena::new(&self.dom, &self.cssom);
-        let layout_arena = LayoutArena::new(&render_arena, width.get() as f32);
-        self.renderer = Renderer::new(render_arena, layout_arena);
+        if let Some(page) = self.page.as_mut() {
+            page.layout(width);
+        }
 
         let window = self.window.as_ref().unwrap().clone();
         let context = Context::new(window.clone()).unwrap();
@@ -159,7 +196,7 @@ impl Ferrum {
     }
 }
 
-impl ApplicationHandler for Ferrum {
+impl ApplicationHandler<Resource> for Ferrum {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let mut attributes = Window::default_attributes();
         attributes.inner_size = Some(Size::Physical(PhysicalSize {
@@ -174,6 +211,13 @@ impl ApplicationHandler for Ferrum {
         self.resize(self.width, self.height);
     }
 
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, resource: Resource) {
+        self.page = Some(Page::new(resource, self.width));
+        if let Some(window) = self.window.as_ref() {
+            window.request_redraw();
+        }
+    }
+
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
@@ -198,7 +242,9 @@ impl ApplicationHandler for Ferrum {
                     self.width.get() as usize,
                     self.height.get() as usize,
                 );
-                self.renderer.render(&mut sbuff);
+                if let Some(page) = self.page.as_ref() {
+                    page.renderer.render(&mut sbuff);
+                }
                 buff.present().unwrap();
             }
             _ => {}
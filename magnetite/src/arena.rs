@@ -51,6 +51,14 @@ impl<T> Arena<T> {
         self.arena.len() - 1
     }
 
+    pub fn get(&self, id: NodeId) -> Option<&ArenaNode<T>> {
+        self.arena.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut ArenaNode<T>> {
+        self.arena.get_mut(id)
+    }
+
     pub fn unlink(&mut self, index: NodeId) {
         if let Some(prev) = self[index].prev {
             self[prev].next = self[index].next;
@@ -79,6 +87,24 @@ impl<T> Arena<T> {
         }
     }
 
+    /// The parent of `id`, its parent, and so on up to the root.
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_, T> {
+        Ancestors {
+            arena: self,
+            id: self[id].parent,
+        }
+    }
+
+    /// The nodes under `id` in tree order, that is depth-first with parents
+    /// before their children.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_, T> {
+        Descendants {
+            arena: self,
+            root: id,
+            id: self[id].child,
+        }
+    }
+
     pub fn insert_child(&mut self, id: NodeId, value: T) -> NodeId {
         if let Some(child) = self[id].child {
             self.append(child, value)
@@ -90,6 +116,19 @@ impl<T> Arena<T> {
         }
     }
 
+    /// Moves `id`, along with its children, to be the last child of `parent`.
+    pub fn append_node(&mut self, parent: NodeId, id: NodeId) {
+        match self.children(parent).last() {
+            Some(last_child) if last_child == id => (),
+            Some(last_child) => self.insert_after_node(id, last_child),
+            None => {
+                self.unlink(id);
+                self[parent].child = Some(id);
+                self[id].parent = Some(parent);
+            }
+        }
+    }
+
     pub fn append(&mut self, id: NodeId, value: T) -> NodeId {
         let last_sibling_id = self.siblings(id).last().unwrap();
         self.insert_after(last_sibling_id, value)
@@ -113,6 +152,27 @@ impl<T> Arena<T> {
         }
         self[at].next = Some(id);
     }
+
+    pub fn insert_before(&mut self, at: NodeId, value: T) -> NodeId {
+        let id = self.push(value);
+        self.insert_before_node(id, at);
+        id
+    }
+
+    pub fn insert_before_node(&mut self, id: NodeId, at: NodeId) {
+        self.unlink(id);
+
+        self[id].next = Some(at);
+        self[id].prev = self[at].prev;
+        self[id].parent = self[at].parent;
+
+        if let Some(prev) = self[at].prev {
+            self[prev].next = Some(id);
+        } else if let Some(parent) = self[at].parent {
+            self[parent].child = Some(id);
+        }
+        self[at].prev = Some(id);
+    }
 }
 
 impl<T, I> Index<I> for Arena<T>
@@ -202,6 +262,44 @@ impl<'a, T> Iterator for Siblings<'a, T> {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct Ancestors<'a, T> {
+    arena: &'a Arena<T>,
+    id: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.id?;
+        self.id = self.arena[id].parent();
+        Some(id)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Descendants<'a, T> {
+    arena: &'a Arena<T>,
+    root: NodeId,
+    id: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for Descendants<'a, T> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.id?;
+        self.id = self.arena[id].child();
+        let mut node = id;
+        while self.id.is_none() && node != self.root {
+            self.id = self.arena[node].next();
+            node = self.arena[node].parent()?;
+        }
+        Some(id)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -230,4 +328,20 @@ mod test {
         );
         println!("{:?}", arena);
     }
+
+    #[test]
+    fn test_tree_iterators() {
+        let mut arena: Arena<i32> = Arena::new();
+        let root = arena.push(0);
+        let a = arena.insert_child(root, 1);
+        let b = arena.insert_child(a, 2);
+        let c = arena.insert_child(b, 3);
+        let d = arena.insert_child(a, 4);
+        let e = arena.insert_child(root, 5);
+        assert_eq!(arena.descendants(root).collect::<Vec<_>>(), [a, b, c, d, e]);
+        assert_eq!(arena.descendants(a).collect::<Vec<_>>(), [b, c, d]);
+        assert_eq!(arena.descendants(c).count(), 0);
+        assert_eq!(arena.ancestors(c).collect::<Vec<_>>(), [b, a, root]);
+        assert_eq!(arena.ancestors(root).count(), 0);
+    }
 }
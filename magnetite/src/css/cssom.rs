@@ -11,8 +11,6 @@ use super::style::*;
 use crate::arena::Arena;
 use crate::arena::NodeId;
 use crate::render::Color;
-use crate::render::RenderArena;
-use crate::render::RenderNodeType;
 use std::ops::Deref;
 use std::sync::LazyLock;
 
@@ -131,7 +129,15 @@ impl Selector {
 
         while let Some(token) = prelude.next() {
             match token {
-                Token::Whitespace => {}
+                Token::Whitespace => {
+                    if this
+                        .units
+                        .last()
+                        .is_some_and(|unit| *unit != SelectorUnit::Descendant)
+                    {
+                        this.units.push(SelectorUnit::Descendant);
+                    }
+                }
                 Token::Ident(name) => {
                     this.units.push(SelectorUnit::Type(name.clone()));
                 }
@@ -144,8 +150,11 @@ impl Selector {
                     };
                     this.units.push(SelectorUnit::Class(name.clone()));
                 }
-                Token::Delim(c) if *c == ',' => {
-                    selectors.push(this);
+                Token::Delim(c) if *c == '*' => {
+                    this.units.push(SelectorUnit::Universal);
+                }
+                Token::Comma => {
+                    selectors.push(this.finish()?);
                     this = Self::new(origin, important);
                 }
                 _ => {
@@ -155,31 +164,46 @@ impl Selector {
             }
         }
 
-        selectors.push(this);
+        selectors.push(this.finish()?);
         Some(selectors)
     }
 
-    pub fn match_with(&self, render_arena: &RenderArena, mut id: NodeId) -> bool {
-        let mut iter = self.units.iter().rev();
-        if let Some(unit) = iter.next()
-            && unit.match_with(render_arena[id].node_type())
+    /// Drops trailing whitespace, and rejects a selector with nothing in it.
+    fn finish(mut self) -> Option<Self> {
+        if self.units.last() == Some(&SelectorUnit::Descendant) {
+            self.units.pop();
+        }
+        (!self.units.is_empty()).then_some(self)
+    }
+
+    /// Whether the node `id` of `tree` matches. Units between descendant
+    /// combinators form compound selectors: the last has to match the node
+    /// itself and each of the others one of its ancestors, in order.
+    pub fn match_with<T: SelectorTarget>(&self, tree: &T, mut id: NodeId) -> bool {
+        let matches =
+            |compound: &[SelectorUnit], id| compound.iter().all(|unit| unit.match_with(tree, id));
+        let mut compounds = self
+            .units
+            .split(|unit| *unit == SelectorUnit::Descendant)
+            .rev();
+        if !compounds
+            .next()
+            .is_some_and(|compound| matches(compound, id))
         {
-            while let Some(unit) = iter.next() {
-                let Some(i) = render_arena[id].parent() else {
+            return false;
+        }
+        for compound in compounds {
+            loop {
+                let Some(parent) = tree.parent_element(id) else {
                     return false;
                 };
-                id = i;
-                if unit.match_with(render_arena[id].node_type()) {
-                    let Some(i) = render_arena[id].parent() else {
-                        return false;
-                    };
-                    id = i;
+                id = parent;
+                if matches(compound, id) {
+                    break;
                 }
             }
-            true
-        } else {
-            false
         }
+        true
     }
 }
 
@@ -189,19 +213,35 @@ pub enum SelectorUnit {
     Id(String),
     Class(String),
     Universal,
+    Descendant,
 }
 
 impl SelectorUnit {
-    pub fn match_with(&self, nodetype: &RenderNodeType) -> bool {
+    pub fn match_with<T: SelectorTarget>(&self, tree: &T, id: NodeId) -> bool {
         match self {
-            Self::Type(t) => {
-                matches!(nodetype, RenderNodeType::Element{name, ..} if name == t)
-            }
-            Self::Universal => true,
-            _ => panic!(),
+            Self::Type(t) => tree
+                .local_name(id)
+                .is_some_and(|name| name.eq_ignore_ascii_case(t)),
+            Self::Id(i) => tree.attribute(id, "id") == Some(i.as_str()),
+            Self::Class(c) => tree
+                .attribute(id, "class")
+                .is_some_and(|classes| classes.split_ascii_whitespace().any(|class| class == c)),
+            Self::Universal | Self::Descendant => true,
         }
     }
 }
 
+/// A tree that selectors can be matched against, such as the DOM or the
+/// render tree.
+pub trait SelectorTarget {
+    /// The parent of `id`, if it's an element.
+    fn parent_element(&self, id: NodeId) -> Option<NodeId>;
+
+    /// The name of `id`, if it's an element.
+    fn local_name(&self, id: NodeId) -> Option<&str>;
+
+    fn attribute(&self, id: NodeId, name: &str) -> Option<&str>;
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct CssomAtRule {}
@@ -21,6 +21,7 @@ pub struct CssStyle {
     pub padding_left: Option<Padding>,
     pub width: Option<Width>,
     pub height: Option<Height>,
+    pub white_space: Option<WhiteSpace>,
 }
 
 impl Default for CssStyle {
@@ -40,6 +41,7 @@ impl Default for CssStyle {
             padding_left: Some(Padding::Length(Length::Pixel(Num::Integer(0)))),
             width: Some(Width::Auto),
             height: Some(Height::Auto),
+            white_space: Some(WhiteSpace::Normal),
         }
     }
 }
@@ -61,6 +63,7 @@ impl CssStyle {
             padding_left: None,
             width: None,
             height: None,
+            white_space: None,
         }
     }
 
@@ -159,6 +162,9 @@ impl CssStyle {
                 "height" => {
                     this.height = Height::from(value).or(this.height);
                 }
+                "white-space" => {
+                    this.white_space = WhiteSpace::from(value).or(this.white_space);
+                }
                 _ => {}
             }
 
@@ -172,6 +178,7 @@ impl CssStyle {
         let mut this = Self::default();
         this.font_size = Some(FontSize::Length(Length::Em(Num::Integer(1))));
         this.color = self.color;
+        this.white_space = self.white_space;
         this
     }
 
@@ -196,6 +203,7 @@ impl CssStyle {
         self.padding_left = from.padding_left.or(self.padding_left);
         self.width = from.width.or(self.width);
         self.height = from.height.or(self.height);
+        self.white_space = from.white_space.or(self.white_space);
     }
 }
 
@@ -221,6 +229,48 @@ impl Display {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    pub fn from(tokens: &[Token]) -> Option<Self> {
+        if let Token::Ident(ident) = tokens.first()? {
+            match ident.as_str() {
+                "normal" => Some(Self::Normal),
+                "pre" => Some(Self::Pre),
+                "nowrap" => Some(Self::Nowrap),
+                "pre-wrap" => Some(Self::PreWrap),
+                "pre-line" => Some(Self::PreLine),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Collapses the white space in `text` the way this value says to.
+    /// Collapsed runs become a single space, and are dropped at the start
+    /// and end of the text and around preserved line breaks.
+    pub fn process(&self, text: &str) -> String {
+        let collapse = |text: &str| text.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+        match self {
+            Self::Normal | Self::Nowrap => collapse(text),
+            Self::Pre | Self::PreWrap => text.to_string(),
+            Self::PreLine => text
+                .split('\n')
+                .map(collapse)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ForegroundColor {
     Color(AlphaColor),
@@ -1,11 +1,17 @@
 pub mod byte_stream_decoder;
+pub mod diagnostic;
 pub mod dom;
+pub mod encoding;
 pub mod input_stream_preprocessor;
+pub mod query;
+pub mod serializer;
 pub mod tokenizer;
 pub mod tree_constructor;
 
 pub use byte_stream_decoder::*;
+pub use diagnostic::*;
 pub use dom::*;
+pub use encoding::*;
 pub use input_stream_preprocessor::*;
 pub use tokenizer::*;
 pub use tree_constructor::*;
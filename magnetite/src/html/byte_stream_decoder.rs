@@ -1,20 +1,449 @@
+use super::encoding::Decoder;
+use super::encoding::Encoding;
+use std::io;
+use std::io::Empty;
 use std::io::Error as IoError;
+use std::io::ErrorKind;
 use std::io::Read;
+use std::mem;
+
+/// How many bytes the prescan looks at for a `<meta>` encoding declaration.
+pub const PRESCAN_LENGTH: usize = 1024;
+
+/// How many bytes `read_chunk` reads from the stream at a time.
+const CHUNK_LENGTH: usize = 8192;
 
 pub struct ByteStreamDecoder<S: Read> {
     stream: S,
+    transport_encoding: Option<Encoding>,
+    fallback: Encoding,
+    encoding: Option<Encoding>,
+    pending: Vec<u8>,
+    decoder: Option<Decoder>,
+    finished: bool,
+}
+
+impl ByteStreamDecoder<Empty> {
+    /// A decoder without a stream, for bytes handed over with `push` as they
+    /// arrive.
+    pub fn streaming() -> Self {
+        Self::new(io::empty())
+    }
 }
 
 impl<S: Read> ByteStreamDecoder<S> {
     pub fn new(stream: S) -> Self {
-        Self { stream }
+        Self {
+            stream,
+            transport_encoding: None,
+            fallback: Encoding::Windows1252,
+            encoding: None,
+            pending: Vec::new(),
+            decoder: None,
+            finished: false,
+        }
+    }
+
+    /// Sets the charset given by the transport layer, such as the `charset`
+    /// parameter of an HTTP `Content-Type`. Unknown labels are ignored.
+    pub fn with_transport_charset(mut self, label: &str) -> Self {
+        self.transport_encoding = Encoding::for_label(label);
+        self
+    }
+
+    /// Sets the encoding used when nothing declares one and the document
+    /// isn't valid UTF-8.
+    pub fn with_fallback(mut self, encoding: Encoding) -> Self {
+        self.fallback = encoding;
+        self
     }
 
+    /// The encoding in use, once enough bytes have been seen to determine it.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+
+    /// Reads the rest of the stream and decodes it in one go.
     pub fn decode(&mut self) -> Result<String, IoError> {
-        let mut vec = Vec::new();
-        self.stream.read_to_end(&mut vec)?;
+        let mut string = String::new();
+        while let Some(chunk) = self.read_chunk()? {
+            string.push_str(&chunk);
+        }
+        Ok(string)
+    }
+
+    /// Reads and decodes the next chunk of the stream. Returns `None` once the
+    /// stream has ended and everything held back has been flushed.
+    pub fn read_chunk(&mut self) -> Result<Option<String>, IoError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let mut buff = [0u8; CHUNK_LENGTH];
+        let len = loop {
+            match self.stream.read(&mut buff) {
+                Ok(len) => break len,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if len == 0 {
+            self.finished = true;
+            Ok(Some(self.finish()))
+        } else {
+            Ok(Some(self.push(&buff[..len])))
+        }
+    }
+
+    /// Decodes the next chunk of the document. Until the encoding is known
+    /// the bytes are held back, which takes at most `PRESCAN_LENGTH` bytes
+    /// unless the transport layer already declared a charset.
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        if let Some(decoder) = self.decoder.as_mut() {
+            return decoder.decode(bytes, false);
+        }
+
+        self.pending.extend_from_slice(bytes);
+        if PRESCAN_LENGTH <= self.pending.len()
+            || (self.transport_encoding.is_some() && 3 <= self.pending.len())
+        {
+            self.start(false)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Decodes whatever is still held back at the end of the document.
+    pub fn finish(&mut self) -> String {
+        match self.decoder.as_mut() {
+            Some(decoder) => decoder.decode(&[], true),
+            None => self.start(true),
+        }
+    }
+
+    fn start(&mut self, last: bool) -> String {
+        let pending = mem::take(&mut self.pending);
+        let (encoding, bom_length) = self.sniff(&pending, last);
+        self.encoding = Some(encoding);
+        self.decoder
+            .insert(encoding.new_decoder())
+            .decode(&pending[bom_length..], last)
+    }
+
+    /// Determines the encoding of `bytes` following the HTML encoding sniffing
+    /// algorithm: a BOM, then the transport layer, then `<meta>` in the first
+    /// `PRESCAN_LENGTH` bytes, then the fallback. Also returns the length of
+    /// the BOM to skip. Unless `last`, a sequence cut off at the end of
+    /// `bytes` doesn't count against UTF-8.
+    fn sniff(&self, bytes: &[u8], last: bool) -> (Encoding, usize) {
+        if let Some(bom) = sniff_bom(bytes) {
+            return bom;
+        }
+        if let Some(encoding) = self.transport_encoding {
+            return (encoding, 0);
+        }
+        if let Some(encoding) = prescan(bytes) {
+            return (encoding, 0);
+        }
+        let is_utf8 = match str::from_utf8(bytes) {
+            Ok(_) => true,
+            Err(e) => !last && e.error_len().is_none(),
+        };
+        if is_utf8 {
+            (Encoding::Utf8, 0)
+        } else {
+            (self.fallback, 0)
+        }
+    }
+}
+
+fn sniff_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(&[0xef, 0xbb, 0xbf]) {
+        Some((Encoding::Utf8, 3))
+    } else if bytes.starts_with(&[0xfe, 0xff]) {
+        Some((Encoding::Utf16Be, 2))
+    } else if bytes.starts_with(&[0xff, 0xfe]) {
+        Some((Encoding::Utf16Le, 2))
+    } else {
+        None
+    }
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes
+        .get(..prefix.len())
+        .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+}
+
+/// Prescans the first `PRESCAN_LENGTH` bytes for a `<meta charset>` or a
+/// `<meta http-equiv="content-type" content="...; charset=...">`.
+pub fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+        if rest.starts_with(b"<!--") {
+            let end = rest[2..].windows(3).position(|window| window == b"-->")?;
+            position += 2 + end + 2;
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .is_some_and(|&byte| is_space(byte) || byte == b'/')
+        {
+            position += 6;
+            if let Some(encoding) = meta_encoding(bytes, &mut position)? {
+                return Some(match encoding {
+                    Encoding::Utf16Be | Encoding::Utf16Le => Encoding::Utf8,
+                    Encoding::XUserDefined => Encoding::Windows1252,
+                    encoding => encoding,
+                });
+            }
+        } else if rest.len() >= 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic()
+                || (rest[1] == b'/' && rest.get(2).is_some_and(u8::is_ascii_alphabetic)))
+        {
+            position += rest
+                .iter()
+                .position(|&byte| is_space(byte) || byte == b'>')?;
+            while get_attribute(bytes, &mut position)?.is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            position += rest.iter().position(|&byte| byte == b'>')?;
+        }
+        position += 1;
+    }
+
+    None
+}
+
+/// Reads the attributes of a `<meta>` tag. The outer `None` means the input
+/// ended, the inner one that the tag doesn't declare a usable encoding.
+fn meta_encoding(bytes: &[u8], position: &mut usize) -> Option<Option<Encoding>> {
+    let mut names = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(bytes, position)? {
+        if names.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "http-equiv" => got_pragma |= value == "content-type",
+            "content" if charset.is_none() => {
+                if let Some(encoding) = extract_encoding_from_meta(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => (),
+        }
+        names.push(name);
+    }
+
+    Some(match need_pragma {
+        Some(true) if !got_pragma => None,
+        Some(_) => charset,
+        None => None,
+    })
+}
+
+/// The "get an attribute" algorithm of the prescan. Names and values are
+/// lowercased. Returns `Some(None)` at the end of the tag.
+fn get_attribute(bytes: &[u8], position: &mut usize) -> Option<Option<(String, String)>> {
+    let byte_at = |position: usize| bytes.get(position).copied();
+
+    while is_space(byte_at(*position)?) || byte_at(*position)? == b'/' {
+        *position += 1;
+    }
+    if byte_at(*position)? == b'>' {
+        return Some(None);
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+        let byte = byte_at(*position)?;
+        match byte {
+            b'=' if !name.is_empty() => {
+                *position += 1;
+                break;
+            }
+            _ if is_space(byte) => {
+                while is_space(byte_at(*position)?) {
+                    *position += 1;
+                }
+                if byte_at(*position)? != b'=' {
+                    return Some(Some((to_string(name), String::new())));
+                }
+                *position += 1;
+                break;
+            }
+            b'/' | b'>' => return Some(Some((to_string(name), String::new()))),
+            _ => name.push(byte.to_ascii_lowercase()),
+        }
+        *position += 1;
+    }
+
+    while is_space(byte_at(*position)?) {
+        *position += 1;
+    }
+    match byte_at(*position)? {
+        quote @ (b'"' | b'\'') => loop {
+            *position += 1;
+            let byte = byte_at(*position)?;
+            if byte == quote {
+                *position += 1;
+                return Some(Some((to_string(name), to_string(value))));
+            }
+            value.push(byte.to_ascii_lowercase());
+        },
+        b'>' => return Some(Some((to_string(name), String::new()))),
+        _ => (),
+    }
+    loop {
+        let byte = byte_at(*position)?;
+        if is_space(byte) || byte == b'>' {
+            return Some(Some((to_string(name), to_string(value))));
+        }
+        value.push(byte.to_ascii_lowercase());
+        *position += 1;
+    }
+}
+
+fn to_string(bytes: Vec<u8>) -> String {
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Finds `charset=...` in the `content` of a `<meta http-equiv>`.
+fn extract_encoding_from_meta(content: &str) -> Option<Encoding> {
+    let bytes = content.as_bytes();
+    let mut position = 0;
+    loop {
+        let found = bytes[position..]
+            .windows(7)
+            .position(|window| window.eq_ignore_ascii_case(b"charset"))?;
+        position += found + 7;
+        while bytes.get(position).is_some_and(|&byte| is_space(byte)) {
+            position += 1;
+        }
+        if bytes.get(position) == Some(&b'=') {
+            position += 1;
+            break;
+        }
+    }
+    while bytes.get(position).is_some_and(|&byte| is_space(byte)) {
+        position += 1;
+    }
+
+    let rest = &content[position..];
+    match rest.bytes().next()? {
+        quote @ (b'"' | b'\'') => {
+            let end = rest[1..].find(quote as char)?;
+            Encoding::for_label(&rest[1..1 + end])
+        }
+        _ => {
+            let end = rest
+                .find(|c: char| c.is_ascii_whitespace() || c == ';')
+                .unwrap_or(rest.len());
+            Encoding::for_label(&rest[..end])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sniff(bytes: &[u8]) -> Encoding {
+        let mut decoder = ByteStreamDecoder::new(bytes);
+        decoder.decode().unwrap();
+        decoder.encoding().unwrap()
+    }
+
+    #[test]
+    fn test_prescan() {
+        assert_eq!(
+            prescan(b"<meta charset=shift_jis>"),
+            Some(Encoding::ShiftJis)
+        );
+        assert_eq!(
+            prescan(b"<!-- <meta charset=koi8-r> --><META CHARSET='EUC-JP'>"),
+            Some(Encoding::EucJp)
+        );
+        assert_eq!(
+            prescan(
+                b"<html lang=ja><head><meta http-equiv=\"Content-Type\" \
+                  content=\"text/html; charset=iso-2022-jp\">"
+            ),
+            Some(Encoding::Iso2022Jp)
+        );
+        assert_eq!(
+            prescan(b"<meta content=\"text/html; charset=iso-2022-jp\">"),
+            None
+        );
+        assert_eq!(
+            prescan(b"<div title='<meta charset=koi8-r>'><meta charset=utf-16>"),
+            Some(Encoding::Utf8)
+        );
+        let mut late = vec![b' '; PRESCAN_LENGTH];
+        late.extend_from_slice(b"<meta charset=shift_jis>");
+        assert_eq!(prescan(&late), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\xff\xfe<\x00p\x00>\x00"), Encoding::Utf16Le);
+        assert_eq!(sniff(b"<p>caf\xc3\xa9"), Encoding::Utf8);
+        assert_eq!(sniff(b"<p>caf\xe9"), Encoding::Windows1252);
+        assert_eq!(sniff(b"<meta charset=sjis>\x93\xfa"), Encoding::ShiftJis);
+
+        let mut decoder = ByteStreamDecoder::new(&b"<meta charset=sjis>\xc6\xfc"[..])
+            .with_transport_charset("EUC-JP");
+        assert_eq!(decoder.decode().unwrap(), "<meta charset=sjis>日");
+
+        let mut decoder = ByteStreamDecoder::new(&b"\xef\xbb\xbf<p>"[..]);
+        assert_eq!(decoder.decode().unwrap(), "<p>");
+    }
+
+    #[test]
+    fn test_push() {
+        let mut decoder = ByteStreamDecoder::streaming();
+        assert_eq!(decoder.push(b"<meta charset=euc-jp>"), "");
+        assert_eq!(decoder.encoding(), None);
+        assert_eq!(
+            decoder.push(&[b' '; PRESCAN_LENGTH]).len(),
+            21 + PRESCAN_LENGTH
+        );
+        assert_eq!(decoder.encoding(), Some(Encoding::EucJp));
+        assert_eq!(decoder.push(b"\xc6"), "");
+        assert_eq!(decoder.push(b"\xfc"), "日");
+
+        let mut decoder = ByteStreamDecoder::streaming().with_transport_charset("utf-8");
+        assert_eq!(decoder.push(b"<p>\xe6\x97"), "<p>");
+        assert_eq!(decoder.push(b"\xa5"), "日");
+        assert_eq!(decoder.finish(), "");
+    }
 
-        let string_cow = String::from_utf8_lossy(&vec);
-        Ok(string_cow.into_owned())
+    #[test]
+    fn test_read_chunk() {
+        let text = "日".repeat(CHUNK_LENGTH);
+        let mut decoder = ByteStreamDecoder::new(text.as_bytes()).with_transport_charset("utf-8");
+        let first = decoder.read_chunk().unwrap().unwrap();
+        assert!(first.len() < text.len());
+        let mut decoded = first;
+        while let Some(chunk) = decoder.read_chunk().unwrap() {
+            decoded.push_str(&chunk);
+        }
+        assert_eq!(decoded, text);
+        assert_eq!(decoder.read_chunk().unwrap(), None);
     }
 }
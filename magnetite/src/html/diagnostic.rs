@@ -0,0 +1,161 @@
+use super::tokenizer::ParseError;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::fmt::Write;
+
+/// A place in the input. The offset counts bytes after newlines have been
+/// normalized and a leading BOM dropped; line and column start at 1 and the
+/// column counts characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourcePosition {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for SourcePosition {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Display for SourcePosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The part of the input between `start` and `end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+impl SourceSpan {
+    pub fn new(start: SourcePosition, end: SourcePosition) -> Self {
+        Self { start, end }
+    }
+}
+
+/// A parse error along with where it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub error: ParseError,
+    pub span: SourceSpan,
+}
+
+impl Diagnostic {
+    /// Formats the error with the line of `source` it's on, marking the span
+    /// with carets:
+    ///
+    /// ```text
+    /// 1:5: unexpected-null-character
+    ///   |
+    /// 1 | <p>a\0</p>
+    ///   |     ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let source = source.replace("\r\n", "\n").replace('\r', "\n");
+        let line = source
+            .split('\n')
+            .nth(self.span.start.line - 1)
+            .unwrap_or("");
+
+        let gutter = " ".repeat(self.span.start.line.to_string().len());
+        let mut rendered = format!("{}: {}\n", self.span.start, self.error);
+        writeln!(rendered, "{} |", gutter).unwrap();
+        writeln!(rendered, "{} | {}", self.span.start.line, line).unwrap();
+
+        // Tabs are kept so the carets line up however wide they're shown.
+        let indent: String = line
+            .chars()
+            .take(self.span.start.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = if self.span.end.line == self.span.start.line {
+            self.span.end.column.saturating_sub(self.span.start.column)
+        } else {
+            line.chars().count() + 1 - self.span.start.column
+        };
+        write!(
+            rendered,
+            "{} | {}{}",
+            gutter,
+            indent,
+            "^".repeat(width.max(1))
+        )
+        .unwrap();
+        rendered
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}: {}", self.span.start, self.error)
+    }
+}
+
+/// Writes the error in kebab case, the way the spec names it, such as
+/// `unexpected-null-character`.
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let name = format!("{:?}", self).replace("CData", "Cdata");
+        for (i, c) in name.chars().enumerate() {
+            if c.is_ascii_uppercase() && i != 0 {
+                write!(f, "-")?;
+            }
+            write!(f, "{}", c.to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::html::ByteStreamDecoder;
+    use crate::html::InputStreamPreprocessor;
+    use crate::html::Tokenizer;
+    use crate::html::TreeConstructor;
+
+    fn diagnostics(html: &str) -> Vec<Diagnostic> {
+        let preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(html.as_bytes())).unwrap();
+        let mut tree_constructor = TreeConstructor::new();
+        Tokenizer::new(preprocessor, &mut tree_constructor).run();
+        tree_constructor.diagnostics().to_vec()
+    }
+
+    #[test]
+    fn test_render() {
+        let html = "<!DOCTYPE html>\r\n<p>\ta\0b</p>\n<div></span></div>";
+        let diagnostics = diagnostics(html);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].error, ParseError::UnexpectedNullCharacter);
+        assert_eq!(
+            diagnostics[0].span.start,
+            SourcePosition {
+                offset: 21,
+                line: 2,
+                column: 6,
+            }
+        );
+        assert_eq!(
+            diagnostics[0].render(html),
+            "2:6: unexpected-null-character\n  |\n2 | <p>\ta\0b</p>\n  |    \t ^"
+        );
+        assert_eq!(diagnostics[1].span, diagnostics[0].span);
+        assert_eq!(diagnostics[2].error, ParseError::UnexpectedEndTag);
+        assert_eq!(
+            diagnostics[2].render(html),
+            "3:6: unexpected-end-tag\n  |\n3 | <div></span></div>\n  |      ^^^^^^^"
+        );
+    }
+}
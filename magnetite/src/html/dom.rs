@@ -1,3 +1,4 @@
+use super::diagnostic::SourceSpan;
 use crate::arena::Arena;
 use crate::arena::ArenaNode;
 pub use crate::arena::NodeId;
@@ -5,14 +6,22 @@ use crate::css::CssomArena;
 use crate::css::Origin;
 use crate::css::Parser as CssParser;
 use crate::css::Tokenizer as CssTokenizer;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Error as FmtError;
+use std::fmt::Formatter;
 use std::iter::Iterator;
+use std::mem;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::ops::Index;
 use std::ops::IndexMut;
+use std::slice;
 use std::slice::SliceIndex;
 use std::str::FromStr;
+use std::vec;
 
 type Node = DomNode;
 type NodeType = DomNodeType;
@@ -20,6 +29,10 @@ type NodeType = DomNodeType;
 #[derive(Clone, Debug)]
 pub struct DomArena {
     arena: Arena<Node>,
+    quirks_mode: QuirksMode,
+    template_contents: HashMap<NodeId, NodeId>,
+    source_spans: HashMap<NodeId, SourceSpan>,
+    mutations: Vec<Mutation>,
 }
 
 impl DomArena {
@@ -28,7 +41,223 @@ impl DomArena {
     pub fn new() -> Self {
         let mut arena = Arena::new();
         arena.push(Node::DOCUMENT);
-        Self { arena }
+        Self {
+            arena,
+            quirks_mode: QuirksMode::default(),
+            template_contents: HashMap::new(),
+            source_spans: HashMap::new(),
+            mutations: Vec::new(),
+        }
+    }
+
+    /// The document's mode, decided by its DOCTYPE.
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    pub fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+    }
+
+    /// The document fragment holding the contents of the `template` element
+    /// `template`. It isn't a child of the template, so tree walks from the
+    /// document never reach it.
+    pub fn template_contents(&self, template: NodeId) -> Option<NodeId> {
+        self.template_contents.get(&template).copied()
+    }
+
+    pub fn create_template_contents(&mut self, template: NodeId) -> NodeId {
+        let fragment = self
+            .arena
+            .push(Node::new(NodeType::DocumentFragment, Namespace::Html));
+        self.template_contents.insert(template, fragment);
+        fragment
+    }
+
+    /// Where the token that created the element `id` was in the input.
+    /// Elements the parser implied get the span of the token that implied
+    /// them.
+    pub fn source_span(&self, id: NodeId) -> Option<SourceSpan> {
+        self.source_spans.get(&id).copied()
+    }
+
+    pub fn set_source_span(&mut self, id: NodeId, span: SourceSpan) {
+        self.source_spans.insert(id, span);
+    }
+
+    /// The changes made through the mutation methods below since the last
+    /// call to `take_mutations`. The parser's insertions aren't recorded.
+    /// Nothing reads these yet: `RenderArena` and `LayoutArena` are still
+    /// rebuilt from the whole document.
+    pub fn mutations(&self) -> &[Mutation] {
+        &self.mutations
+    }
+
+    pub fn take_mutations(&mut self) -> Vec<Mutation> {
+        mem::take(&mut self.mutations)
+    }
+
+    /// The nodes changed since the last call to `take_mutations`, each once.
+    pub fn dirty_nodes(&self) -> BTreeSet<NodeId> {
+        self.mutations.iter().map(Mutation::target).collect()
+    }
+
+    /// Creates an element that isn't in the tree yet.
+    pub fn create_element(&mut self, name: &str, namespace: Namespace) -> NodeId {
+        self.arena.push(Node::new(
+            NodeType::Element {
+                name: name.to_string(),
+                attributes: Attributes::new(),
+            },
+            namespace,
+        ))
+    }
+
+    /// Creates a text node that isn't in the tree yet.
+    pub fn create_text_node(&mut self, text: &str) -> NodeId {
+        self.arena
+            .push(Node::new(NodeType::Text(text.to_string()), Namespace::Html))
+    }
+
+    /// Moves the node `child` to be the last child of `parent`.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) -> Result<(), DomError> {
+        self.check_can_insert(parent, child)?;
+        self.remove(child)?;
+        self.arena.append_node(parent, child);
+        self.mutations.push(Mutation::ChildList { parent });
+        Ok(())
+    }
+
+    /// Moves the node `child` to be the previous sibling of `before`, which
+    /// has to have a parent. Like in the DOM, inserting a node before itself
+    /// leaves it where it is.
+    pub fn insert_child_before(&mut self, child: NodeId, before: NodeId) -> Result<(), DomError> {
+        let parent = self.node(before)?.parent().ok_or(DomError::NotFound)?;
+        self.check_can_insert(parent, child)?;
+        if child == before {
+            return Ok(());
+        }
+        self.remove(child)?;
+        self.arena.insert_before_node(child, before);
+        self.mutations.push(Mutation::ChildList { parent });
+        Ok(())
+    }
+
+    /// Takes the node `id` out of the tree. It stays in the arena, so it can
+    /// be inserted again.
+    pub fn remove(&mut self, id: NodeId) -> Result<(), DomError> {
+        if let Some(parent) = self.node(id)?.parent() {
+            self.arena.unlink(id);
+            self.mutations.push(Mutation::ChildList { parent });
+        }
+        Ok(())
+    }
+
+    /// Puts the node `new` where the node `old` is, and takes `old` out of
+    /// the tree.
+    pub fn replace(&mut self, old: NodeId, new: NodeId) -> Result<(), DomError> {
+        self.node(old)?;
+        if old != new {
+            self.insert_child_before(new, old)?;
+            self.remove(old)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the attribute `name` of the element `id`, adding it if it's
+    /// missing.
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) -> Result<(), DomError> {
+        let NodeType::Element {
+            ref mut attributes, ..
+        } = self.node_mut(id)?.node_type
+        else {
+            return Err(DomError::NotAnElement);
+        };
+        attributes.set(name, value);
+        self.mutations.push(Mutation::Attribute {
+            target: id,
+            name: name.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Removes the attribute `name` of the element `id`, returning its value
+    /// if it had one.
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) -> Result<Option<String>, DomError> {
+        let NodeType::Element {
+            ref mut attributes, ..
+        } = self.node_mut(id)?.node_type
+        else {
+            return Err(DomError::NotAnElement);
+        };
+        let Some(value) = attributes.remove(name) else {
+            return Ok(None);
+        };
+        self.mutations.push(Mutation::Attribute {
+            target: id,
+            name: name.to_string(),
+        });
+        Ok(Some(value))
+    }
+
+    /// Sets the text of a text or comment node. An element or a fragment
+    /// has its children replaced by a single text node, or by nothing if
+    /// `text` is empty. Like `textContent`, this does nothing to a document
+    /// or a doctype.
+    pub fn set_text(&mut self, id: NodeId, text: &str) -> Result<(), DomError> {
+        match self.node_mut(id)?.node_type {
+            NodeType::Text(ref mut data) | NodeType::Comment(ref mut data) => {
+                *data = text.to_string();
+                self.mutations.push(Mutation::Text { target: id });
+            }
+            NodeType::Document | NodeType::DocType { .. } => (),
+            NodeType::DocumentFragment | NodeType::Element { .. } => {
+                let children: Vec<NodeId> = self.arena.children(id).collect();
+                for child in children {
+                    self.remove(child)?;
+                }
+                if !text.is_empty() {
+                    let text = self.create_text_node(text);
+                    self.arena.append_node(id, text);
+                    self.mutations.push(Mutation::ChildList { parent: id });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The node `id`, or `NotFound` if the arena has no such node.
+    fn node(&self, id: NodeId) -> Result<&ArenaNode<Node>, DomError> {
+        self.arena.get(id).ok_or(DomError::NotFound)
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut ArenaNode<Node>, DomError> {
+        self.arena.get_mut(id).ok_or(DomError::NotFound)
+    }
+
+    /// Checks that `child` can go under `parent`, the way the DOM standard
+    /// checks a node before inserting it.
+    fn check_can_insert(&self, parent: NodeId, child: NodeId) -> Result<(), DomError> {
+        let parent_type = &self.node(parent)?.node_type;
+        if !matches!(
+            parent_type,
+            NodeType::Document | NodeType::DocumentFragment | NodeType::Element { .. }
+        ) {
+            return Err(DomError::HierarchyRequest);
+        }
+        if child == parent || self.arena.ancestors(parent).any(|id| id == child) {
+            return Err(DomError::HierarchyRequest);
+        }
+        match self.node(child)?.node_type {
+            NodeType::Document => Err(DomError::HierarchyRequest),
+            NodeType::Text(_) if *parent_type == NodeType::Document => {
+                Err(DomError::HierarchyRequest)
+            }
+            NodeType::DocType { .. } if *parent_type != NodeType::Document => {
+                Err(DomError::HierarchyRequest)
+            }
+            _ => Ok(()),
+        }
     }
 
     pub fn cssom(&self) -> CssomArena {
@@ -46,7 +275,7 @@ impl DomArena {
         let head_id = self.get_child_element(html_id, "head")?;
         let style_id = self.get_child_element(head_id, "style")?;
         if let Some(id) = self.arena[style_id].child()
-            && let NodeType::String(ref s) = self.arena[id].node_type
+            && let NodeType::Text(ref s) = self.arena[id].node_type
         {
             Some(s.as_str())
         } else {
@@ -67,43 +296,6 @@ impl DomArena {
         }
         None
     }
-
-    pub fn insert_child(&mut self, id: NodeId, mut node: Node) -> NodeId {
-        if let Some(last_child) = self.arena.children(id).last() {
-            self.insert_after(last_child, node)
-        } else {
-            if let NodeType::Character(c) = node.node_type {
-                node.node_type = NodeType::String(format!("{}", c));
-            }
-            self.arena.insert_child(id, node)
-        }
-    }
-
-    pub fn insert_after(&mut self, at: NodeId, mut node: Node) -> NodeId {
-        if let NodeType::String(ref mut s) = self.arena[at].node_type
-            && let NodeType::Character(c) = node.node_type
-        {
-            s.push(c);
-            at
-        } else if let NodeType::String(ref mut s) = self.arena[at].node_type
-            && let NodeType::String(ref s2) = node.node_type
-        {
-            s.push_str(s2.as_str());
-            at
-        } else {
-            if let NodeType::Character(c) = node.node_type {
-                node.node_type = NodeType::String(format!("{}", c));
-            }
-            self.arena.insert_after(at, node)
-        }
-    }
-
-    pub fn push(&mut self, mut node: Node) -> NodeId {
-        if let NodeType::Character(c) = node.node_type {
-            node.node_type = NodeType::String(format!("{}", c));
-        }
-        self.arena.push(node)
-    }
 }
 
 impl<I: SliceIndex<[ArenaNode<Node>]>> Index<I> for DomArena {
@@ -140,6 +332,65 @@ impl Default for DomArena {
     }
 }
 
+/// A change made to a `DomArena`, recorded so that styles and layout could
+/// later be recomputed only for the nodes that changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    /// Children were added to or removed from `parent`.
+    ChildList { parent: NodeId },
+    /// The attribute `name` of `target` was set or removed.
+    Attribute { target: NodeId, name: String },
+    /// The text of the text or comment node `target` changed.
+    Text { target: NodeId },
+}
+
+impl Mutation {
+    /// The node whose style or layout is out of date.
+    pub fn target(&self) -> NodeId {
+        match *self {
+            Self::ChildList { parent } => parent,
+            Self::Attribute { target, .. } | Self::Text { target } => target,
+        }
+    }
+}
+
+/// Why a DOM operation failed. The variants are named after the exceptions
+/// the DOM standard throws in the same cases.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomError {
+    /// A node would be inserted where it can't go, such as into itself or
+    /// one of its descendants, or under a text node.
+    HierarchyRequest,
+    /// A node that should have been in the tree, such as the reference node
+    /// of an insertion, isn't, or a `NodeId` doesn't name a node at all.
+    NotFound,
+    /// An operation that only applies to elements was given another node.
+    NotAnElement,
+    /// A selector couldn't be parsed, or uses syntax that isn't supported.
+    Syntax,
+}
+
+impl Display for DomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::HierarchyRequest => write!(f, "the node can't be inserted there"),
+            Self::NotFound => write!(f, "the node isn't in the tree"),
+            Self::NotAnElement => write!(f, "the node isn't an element"),
+            Self::Syntax => write!(f, "invalid or unsupported selector"),
+        }
+    }
+}
+
+impl Error for DomError {}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuirksMode {
+    #[default]
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DomNode {
     namespace: Namespace,
@@ -187,14 +438,18 @@ impl FromStr for Namespace {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DomNodeType {
     Document,
-    DocType(String),
+    DocumentFragment,
+    DocType {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
     Element {
         name: String,
-        attributes: HashMap<String, String>,
+        attributes: Attributes,
     },
     Comment(String),
-    Character(char),
-    String(String),
+    Text(String),
 }
 
 impl DomNode {
@@ -217,4 +472,316 @@ impl DomNode {
     pub fn node_type(&self) -> &NodeType {
         &self.node_type
     }
+
+    /// The value of the attribute `name` of an element, such as `href` or
+    /// `xlink:href`.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        match self.node_type {
+            NodeType::Element { ref attributes, .. } => attributes.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.attribute("id")
+    }
+
+    /// The classes in the `class` attribute, in order.
+    pub fn class_list(&self) -> Vec<&str> {
+        self.attribute("class")
+            .map(|classes| classes.split_ascii_whitespace().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn has_class(&self, name: &str) -> bool {
+        self.class_list().contains(&name)
+    }
+}
+
+/// The attributes of an element, in the order they appeared in the source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    attributes: Vec<Attribute>,
+}
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Attribute> {
+        self.attributes.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Attribute> {
+        self.attributes.iter_mut()
+    }
+
+    /// The value of the first attribute whose qualified name is `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter()
+            .find(|attribute| attribute.has_qualified_name(name))
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Adds `attribute` at the end, without checking for one with the same
+    /// name.
+    pub fn push(&mut self, attribute: Attribute) {
+        self.attributes.push(attribute);
+    }
+
+    /// Sets the value of the attribute `name`, adding it at the end if it's
+    /// missing.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self
+            .attributes
+            .iter_mut()
+            .find(|attribute| attribute.has_qualified_name(name))
+        {
+            Some(attribute) => attribute.value = value.to_string(),
+            None => self.push(Attribute::new(name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Removes the attribute `name`, returning its value.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let i = self
+            .attributes
+            .iter()
+            .position(|attribute| attribute.has_qualified_name(name))?;
+        Some(self.attributes.remove(i).value)
+    }
+}
+
+impl IntoIterator for Attributes {
+    type Item = Attribute;
+    type IntoIter = vec::IntoIter<Attribute>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.attributes.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Attributes {
+    type Item = &'a Attribute;
+    type IntoIter = slice::Iter<'a, Attribute>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Attribute> for Attributes {
+    fn from_iter<I: IntoIterator<Item = Attribute>>(iter: I) -> Self {
+        Self {
+            attributes: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// An attribute of an element. Only attributes of foreign elements such as
+/// `xlink:href` or `xml:lang` have a namespace, and most of those a prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub namespace: Option<Namespace>,
+    pub prefix: Option<String>,
+    pub local_name: String,
+    pub value: String,
+}
+
+impl Attribute {
+    /// An attribute without a namespace, as the tokenizer creates them.
+    pub fn new(name: String, value: String) -> Self {
+        Self {
+            namespace: None,
+            prefix: None,
+            local_name: name,
+            value,
+        }
+    }
+
+    /// The name with its prefix, such as `xlink:href`.
+    pub fn qualified_name(&self) -> String {
+        match self.prefix {
+            Some(ref prefix) => format!("{}:{}", prefix, self.local_name),
+            None => self.local_name.clone(),
+        }
+    }
+
+    fn has_qualified_name(&self, name: &str) -> bool {
+        match self.prefix {
+            Some(ref prefix) => name
+                .strip_prefix(prefix.as_str())
+                .and_then(|name| name.strip_prefix(':'))
+                .is_some_and(|name| name == self.local_name),
+            None => name == self.local_name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mutations() {
+        let mut dom = DomArena::new();
+        let document = DomArena::DOCUMENT_IDX;
+        let html = dom.create_element("html", Namespace::Html);
+        let p = dom.create_element("p", Namespace::Html);
+        let div = dom.create_element("div", Namespace::Html);
+        dom.append_child(document, html).unwrap();
+        dom.append_child(html, p).unwrap();
+        dom.insert_child_before(div, p).unwrap();
+        assert_eq!(dom.children(html).collect::<Vec<_>>(), [div, p]);
+        assert_eq!(
+            dom.take_mutations(),
+            [
+                Mutation::ChildList { parent: document },
+                Mutation::ChildList { parent: html },
+                Mutation::ChildList { parent: html },
+            ]
+        );
+
+        dom.set_text(p, "a").unwrap();
+        let text = dom.children(p).next().unwrap();
+        dom.set_text(text, "b").unwrap();
+        dom.set_attribute(p, "class", "x").unwrap();
+        dom.append_child(div, p).unwrap();
+        assert_eq!(
+            dom.outer_html(html),
+            "<html><div><p class=\"x\">b</p></div></html>"
+        );
+        assert_eq!(dom.dirty_nodes(), BTreeSet::from([html, div, p, text]));
+        dom.take_mutations();
+
+        let span = dom.create_element("span", Namespace::Html);
+        dom.replace(div, span).unwrap();
+        assert_eq!(dom.remove_attribute(p, "class"), Ok(Some("x".to_string())));
+        assert_eq!(dom.remove_attribute(p, "class"), Ok(None));
+        assert_eq!(dom.outer_html(html), "<html><span></span></html>");
+        assert_eq!(dom.outer_html(div), "<div><p>b</p></div>");
+        assert_eq!(dom.dirty_nodes(), BTreeSet::from([html, p]));
+        dom.set_text(div, "").unwrap();
+        assert_eq!(dom.children(div).count(), 0);
+    }
+
+    #[test]
+    fn test_insert_before_itself() {
+        let mut dom = DomArena::new();
+        let div = dom.create_element("div", Namespace::Html);
+        let p = dom.create_element("p", Namespace::Html);
+        let span = dom.create_element("span", Namespace::Html);
+        dom.append_child(div, p).unwrap();
+        dom.append_child(div, span).unwrap();
+        dom.take_mutations();
+
+        dom.insert_child_before(p, p).unwrap();
+        dom.insert_child_before(span, span).unwrap();
+        assert_eq!(dom.children(div).collect::<Vec<_>>(), [p, span]);
+        assert_eq!(dom[p].parent(), Some(div));
+        assert_eq!(dom[p].next(), Some(span));
+        assert_eq!(dom[span].prev(), Some(p));
+        assert!(dom.mutations().is_empty());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut dom = DomArena::new();
+        let div = dom.create_element("div", Namespace::Html);
+        dom.set_attribute(div, "class", "b  a\tc").unwrap();
+        dom.set_attribute(div, "id", "x").unwrap();
+        dom.set_attribute(div, "class", "a b").unwrap();
+        let DomNodeType::Element {
+            ref mut attributes, ..
+        } = dom[div].node_type
+        else {
+            unreachable!();
+        };
+        attributes.push(Attribute {
+            namespace: Some(Namespace::XLink),
+            prefix: Some("xlink".to_string()),
+            local_name: "href".to_string(),
+            value: "#y".to_string(),
+        });
+        let names: Vec<String> = attributes.iter().map(Attribute::qualified_name).collect();
+        assert_eq!(names, ["class", "id", "xlink:href"]);
+        assert_eq!(attributes.get("xlink:href"), Some("#y"));
+        assert_eq!(attributes.get("href"), None);
+
+        assert_eq!(dom[div].id(), Some("x"));
+        assert_eq!(dom[div].class_list(), ["a", "b"]);
+        assert!(dom[div].has_class("b"));
+        assert!(!dom[div].has_class("c"));
+        assert_eq!(
+            dom.remove_attribute(div, "xlink:href"),
+            Ok(Some("#y".to_string()))
+        );
+        assert_eq!(dom[div].attribute("xlink:href"), None);
+    }
+
+    #[test]
+    fn test_invalid_mutations() {
+        let mut dom = DomArena::new();
+        let document = DomArena::DOCUMENT_IDX;
+        let div = dom.create_element("div", Namespace::Html);
+        let p = dom.create_element("p", Namespace::Html);
+        let text = dom.create_text_node("a");
+        dom.append_child(div, p).unwrap();
+        dom.append_child(p, text).unwrap();
+        dom.take_mutations();
+
+        assert_eq!(dom.append_child(p, div), Err(DomError::HierarchyRequest));
+        assert_eq!(dom.append_child(div, div), Err(DomError::HierarchyRequest));
+        assert_eq!(dom.append_child(text, div), Err(DomError::HierarchyRequest));
+        assert_eq!(
+            dom.append_child(p, document),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(
+            dom.append_child(document, text),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(dom.insert_child_before(p, div), Err(DomError::NotFound));
+        assert_eq!(dom.replace(div, p), Err(DomError::NotFound));
+        assert_eq!(
+            dom.set_attribute(text, "id", "x"),
+            Err(DomError::NotAnElement)
+        );
+        assert_eq!(
+            dom.remove_attribute(text, "id"),
+            Err(DomError::NotAnElement)
+        );
+
+        let missing = NodeId::MAX;
+        assert_eq!(dom.append_child(div, missing), Err(DomError::NotFound));
+        assert_eq!(dom.append_child(missing, p), Err(DomError::NotFound));
+        assert_eq!(
+            dom.insert_child_before(div, missing),
+            Err(DomError::NotFound)
+        );
+        assert_eq!(dom.insert_child_before(missing, p), Err(DomError::NotFound));
+        assert_eq!(dom.replace(missing, missing), Err(DomError::NotFound));
+        assert_eq!(dom.remove(missing), Err(DomError::NotFound));
+        assert_eq!(dom.set_text(missing, "a"), Err(DomError::NotFound));
+        assert_eq!(
+            dom.set_attribute(missing, "id", "x"),
+            Err(DomError::NotFound)
+        );
+        assert_eq!(dom.remove_attribute(missing, "id"), Err(DomError::NotFound));
+        assert!(dom.mutations().is_empty());
+        assert_eq!(dom.outer_html(div), "<div><p>a</p></div>");
+    }
 }
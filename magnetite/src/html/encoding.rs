@@ -0,0 +1,439 @@
+mod index_jis0208;
+mod index_jis0212;
+mod japanese;
+mod single_byte;
+
+use japanese::EucJpDecoder;
+use japanese::Iso2022JpDecoder;
+use japanese::ShiftJisDecoder;
+
+/// The encodings of the WHATWG Encoding Standard that magnetite can decode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Utf8,
+    Utf16Be,
+    Utf16Le,
+    ShiftJis,
+    EucJp,
+    Iso2022Jp,
+    Ibm866,
+    Iso8859_2,
+    Iso8859_3,
+    Iso8859_4,
+    Iso8859_5,
+    Iso8859_6,
+    Iso8859_7,
+    Iso8859_8,
+    Iso8859_8I,
+    Iso8859_10,
+    Iso8859_13,
+    Iso8859_14,
+    Iso8859_15,
+    Iso8859_16,
+    Koi8R,
+    Koi8U,
+    Macintosh,
+    Windows874,
+    Windows1250,
+    Windows1251,
+    Windows1252,
+    Windows1253,
+    Windows1254,
+    Windows1255,
+    Windows1256,
+    Windows1257,
+    Windows1258,
+    XMacCyrillic,
+    XUserDefined,
+}
+
+impl Encoding {
+    /// Gets an encoding from a label such as `"Shift_JIS"` or `"latin1"`.
+    /// Labels are matched case-insensitively after trimming whitespace.
+    pub fn for_label(label: &str) -> Option<Self> {
+        let label = label
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .to_ascii_lowercase();
+        let encoding =
+            match label.as_str() {
+                "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+                | "x-unicode20utf8" => Self::Utf8,
+                "unicodefffe" | "utf-16be" => Self::Utf16Be,
+                "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff"
+                | "utf-16" | "utf-16le" => Self::Utf16Le,
+                "csshiftjis" | "ms932" | "ms_kanji" | "shift-jis" | "shift_jis" | "sjis"
+                | "windows-31j" | "x-sjis" => Self::ShiftJis,
+                "cseucpkdfmtjapanese" | "euc-jp" | "x-euc-jp" => Self::EucJp,
+                "csiso2022jp" | "iso-2022-jp" => Self::Iso2022Jp,
+                "866" | "cp866" | "csibm866" | "ibm866" => Self::Ibm866,
+                "csisolatin2" | "iso-8859-2" | "iso-ir-101" | "iso8859-2" | "iso88592"
+                | "iso_8859-2" | "iso_8859-2:1987" | "l2" | "latin2" => Self::Iso8859_2,
+                "csisolatin3" | "iso-8859-3" | "iso-ir-109" | "iso8859-3" | "iso88593"
+                | "iso_8859-3" | "iso_8859-3:1988" | "l3" | "latin3" => Self::Iso8859_3,
+                "csisolatin4" | "iso-8859-4" | "iso-ir-110" | "iso8859-4" | "iso88594"
+                | "iso_8859-4" | "iso_8859-4:1988" | "l4" | "latin4" => Self::Iso8859_4,
+                "csisolatincyrillic" | "cyrillic" | "iso-8859-5" | "iso-ir-144" | "iso8859-5"
+                | "iso88595" | "iso_8859-5" | "iso_8859-5:1988" => Self::Iso8859_5,
+                "arabic" | "asmo-708" | "csiso88596e" | "csiso88596i" | "csisolatinarabic"
+                | "ecma-114" | "iso-8859-6" | "iso-8859-6-e" | "iso-8859-6-i" | "iso-ir-127"
+                | "iso8859-6" | "iso88596" | "iso_8859-6" | "iso_8859-6:1987" => Self::Iso8859_6,
+                "csisolatingreek" | "ecma-118" | "elot_928" | "greek" | "greek8" | "iso-8859-7"
+                | "iso-ir-126" | "iso8859-7" | "iso88597" | "iso_8859-7" | "iso_8859-7:1987"
+                | "sun_eu_greek" => Self::Iso8859_7,
+                "csiso88598e" | "csisolatinhebrew" | "hebrew" | "iso-8859-8" | "iso-8859-8-e"
+                | "iso-ir-138" | "iso8859-8" | "iso88598" | "iso_8859-8" | "iso_8859-8:1988"
+                | "visual" => Self::Iso8859_8,
+                "csiso88598i" | "iso-8859-8-i" | "logical" => Self::Iso8859_8I,
+                "csisolatin6" | "iso-8859-10" | "iso-ir-157" | "iso8859-10" | "iso885910"
+                | "l6" | "latin6" => Self::Iso8859_10,
+                "iso-8859-13" | "iso8859-13" | "iso885913" => Self::Iso8859_13,
+                "iso-8859-14" | "iso8859-14" | "iso885914" => Self::Iso8859_14,
+                "csisolatin9" | "iso-8859-15" | "iso8859-15" | "iso885915" | "iso_8859-15"
+                | "l9" => Self::Iso8859_15,
+                "iso-8859-16" => Self::Iso8859_16,
+                "cskoi8r" | "koi" | "koi8" | "koi8-r" | "koi8_r" => Self::Koi8R,
+                "koi8-ru" | "koi8-u" => Self::Koi8U,
+                "csmacintosh" | "mac" | "macintosh" | "x-mac-roman" => Self::Macintosh,
+                "dos-874" | "iso-8859-11" | "iso8859-11" | "iso885911" | "tis-620"
+                | "windows-874" => Self::Windows874,
+                "cp1250" | "windows-1250" | "x-cp1250" => Self::Windows1250,
+                "cp1251" | "windows-1251" | "x-cp1251" => Self::Windows1251,
+                "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+                | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+                | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252"
+                | "x-cp1252" => Self::Windows1252,
+                "cp1253" | "windows-1253" | "x-cp1253" => Self::Windows1253,
+                "cp1254" | "csisolatin5" | "iso-8859-9" | "iso-ir-148" | "iso8859-9"
+                | "iso88599" | "iso_8859-9" | "iso_8859-9:1989" | "l5" | "latin5"
+                | "windows-1254" | "x-cp1254" => Self::Windows1254,
+                "cp1255" | "windows-1255" | "x-cp1255" => Self::Windows1255,
+                "cp1256" | "windows-1256" | "x-cp1256" => Self::Windows1256,
+                "cp1257" | "windows-1257" | "x-cp1257" => Self::Windows1257,
+                "cp1258" | "windows-1258" | "x-cp1258" => Self::Windows1258,
+                "x-mac-cyrillic" | "x-mac-ukrainian" => Self::XMacCyrillic,
+                "x-user-defined" => Self::XUserDefined,
+                _ => return None,
+            };
+        Some(encoding)
+    }
+
+    /// The canonical name of the encoding.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Utf16Le => "UTF-16LE",
+            Self::ShiftJis => "Shift_JIS",
+            Self::EucJp => "EUC-JP",
+            Self::Iso2022Jp => "ISO-2022-JP",
+            Self::Ibm866 => "IBM866",
+            Self::Iso8859_2 => "ISO-8859-2",
+            Self::Iso8859_3 => "ISO-8859-3",
+            Self::Iso8859_4 => "ISO-8859-4",
+            Self::Iso8859_5 => "ISO-8859-5",
+            Self::Iso8859_6 => "ISO-8859-6",
+            Self::Iso8859_7 => "ISO-8859-7",
+            Self::Iso8859_8 => "ISO-8859-8",
+            Self::Iso8859_8I => "ISO-8859-8-I",
+            Self::Iso8859_10 => "ISO-8859-10",
+            Self::Iso8859_13 => "ISO-8859-13",
+            Self::Iso8859_14 => "ISO-8859-14",
+            Self::Iso8859_15 => "ISO-8859-15",
+            Self::Iso8859_16 => "ISO-8859-16",
+            Self::Koi8R => "KOI8-R",
+            Self::Koi8U => "KOI8-U",
+            Self::Macintosh => "macintosh",
+            Self::Windows874 => "windows-874",
+            Self::Windows1250 => "windows-1250",
+            Self::Windows1251 => "windows-1251",
+            Self::Windows1252 => "windows-1252",
+            Self::Windows1253 => "windows-1253",
+            Self::Windows1254 => "windows-1254",
+            Self::Windows1255 => "windows-1255",
+            Self::Windows1256 => "windows-1256",
+            Self::Windows1257 => "windows-1257",
+            Self::Windows1258 => "windows-1258",
+            Self::XMacCyrillic => "x-mac-cyrillic",
+            Self::XUserDefined => "x-user-defined",
+        }
+    }
+
+    fn single_byte_index(self) -> Option<&'static [u16; 128]> {
+        let index = match self {
+            Self::Ibm866 => &single_byte::IBM866,
+            Self::Iso8859_2 => &single_byte::ISO_8859_2,
+            Self::Iso8859_3 => &single_byte::ISO_8859_3,
+            Self::Iso8859_4 => &single_byte::ISO_8859_4,
+            Self::Iso8859_5 => &single_byte::ISO_8859_5,
+            Self::Iso8859_6 => &single_byte::ISO_8859_6,
+            Self::Iso8859_7 => &single_byte::ISO_8859_7,
+            Self::Iso8859_8 | Self::Iso8859_8I => &single_byte::ISO_8859_8,
+            Self::Iso8859_10 => &single_byte::ISO_8859_10,
+            Self::Iso8859_13 => &single_byte::ISO_8859_13,
+            Self::Iso8859_14 => &single_byte::ISO_8859_14,
+            Self::Iso8859_15 => &single_byte::ISO_8859_15,
+            Self::Iso8859_16 => &single_byte::ISO_8859_16,
+            Self::Koi8R => &single_byte::KOI8_R,
+            Self::Koi8U => &single_byte::KOI8_U,
+            Self::Macintosh => &single_byte::MACINTOSH,
+            Self::Windows874 => &single_byte::WINDOWS_874,
+            Self::Windows1250 => &single_byte::WINDOWS_1250,
+            Self::Windows1251 => &single_byte::WINDOWS_1251,
+            Self::Windows1252 => &single_byte::WINDOWS_1252,
+            Self::Windows1253 => &single_byte::WINDOWS_1253,
+            Self::Windows1254 => &single_byte::WINDOWS_1254,
+            Self::Windows1255 => &single_byte::WINDOWS_1255,
+            Self::Windows1256 => &single_byte::WINDOWS_1256,
+            Self::Windows1257 => &single_byte::WINDOWS_1257,
+            Self::Windows1258 => &single_byte::WINDOWS_1258,
+            Self::XMacCyrillic => &single_byte::X_MAC_CYRILLIC,
+            _ => return None,
+        };
+        Some(index)
+    }
+
+    pub fn new_decoder(self) -> Decoder {
+        let state = match self {
+            Self::Utf8 => DecoderState::Utf8(Vec::new()),
+            Self::Utf16Be | Self::Utf16Le => DecoderState::Utf16 {
+                big_endian: self == Self::Utf16Be,
+                byte: None,
+                surrogate: None,
+            },
+            Self::ShiftJis => DecoderState::ShiftJis(ShiftJisDecoder::default()),
+            Self::EucJp => DecoderState::EucJp(EucJpDecoder::default()),
+            Self::Iso2022Jp => DecoderState::Iso2022Jp(Iso2022JpDecoder::default()),
+            Self::XUserDefined => DecoderState::XUserDefined,
+            _ => DecoderState::SingleByte(self.single_byte_index().unwrap()),
+        };
+        Decoder {
+            encoding: self,
+            state,
+        }
+    }
+
+    /// Decodes a complete byte sequence, replacing errors with U+FFFD.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        self.new_decoder().decode(bytes, true)
+    }
+}
+
+/// A streaming decoder. Sequences cut off at the end of a chunk are kept
+/// until the next call to `decode`.
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    encoding: Encoding,
+    state: DecoderState,
+}
+
+#[derive(Clone, Debug)]
+enum DecoderState {
+    Utf8(Vec<u8>),
+    Utf16 {
+        big_endian: bool,
+        byte: Option<u8>,
+        surrogate: Option<u16>,
+    },
+    ShiftJis(ShiftJisDecoder),
+    EucJp(EucJpDecoder),
+    Iso2022Jp(Iso2022JpDecoder),
+    SingleByte(&'static [u16; 128]),
+    XUserDefined,
+}
+
+impl Decoder {
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Decodes the next chunk of the stream. `last` flushes any incomplete
+    /// sequence as U+FFFD.
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let mut output = String::with_capacity(bytes.len());
+        match &mut self.state {
+            DecoderState::Utf8(pending) => decode_utf8(pending, bytes, last, &mut output),
+            DecoderState::Utf16 {
+                big_endian,
+                byte,
+                surrogate,
+            } => {
+                for &next in bytes {
+                    let Some(first) = byte.take() else {
+                        *byte = Some(next);
+                        continue;
+                    };
+                    let unit = if *big_endian {
+                        u16::from_be_bytes([first, next])
+                    } else {
+                        u16::from_le_bytes([first, next])
+                    };
+                    match (surrogate.take(), unit) {
+                        (Some(lead), 0xdc00..=0xdfff) => {
+                            let code_point =
+                                0x10000 + ((lead as u32 - 0xd800) << 10) + (unit as u32 - 0xdc00);
+                            output.push(char::from_u32(code_point).unwrap());
+                        }
+                        (lead, _) => {
+                            if lead.is_some() {
+                                output.push(char::REPLACEMENT_CHARACTER);
+                            }
+                            match unit {
+                                0xd800..=0xdbff => *surrogate = Some(unit),
+                                0xdc00..=0xdfff => output.push(char::REPLACEMENT_CHARACTER),
+                                _ => output.push(char::from_u32(unit as u32).unwrap()),
+                            }
+                        }
+                    }
+                }
+                if last && (byte.take().is_some() | surrogate.take().is_some()) {
+                    output.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+            DecoderState::ShiftJis(decoder) => {
+                bytes
+                    .iter()
+                    .for_each(|&byte| decoder.push(byte, &mut output));
+                if last {
+                    decoder.finish(&mut output);
+                }
+            }
+            DecoderState::EucJp(decoder) => {
+                bytes
+                    .iter()
+                    .for_each(|&byte| decoder.push(byte, &mut output));
+                if last {
+                    decoder.finish(&mut output);
+                }
+            }
+            DecoderState::Iso2022Jp(decoder) => {
+                bytes
+                    .iter()
+                    .for_each(|&byte| decoder.push(byte, &mut output));
+                if last {
+                    decoder.finish(&mut output);
+                }
+            }
+            DecoderState::SingleByte(index) => {
+                output.extend(bytes.iter().map(|&byte| match byte {
+                    0x00..=0x7f => byte as char,
+                    _ => char::from_u32(index[byte as usize - 0x80] as u32).unwrap(),
+                }));
+            }
+            DecoderState::XUserDefined => {
+                output.extend(bytes.iter().map(|&byte| match byte {
+                    0x00..=0x7f => byte as char,
+                    _ => char::from_u32(0xf780 + byte as u32 - 0x80).unwrap(),
+                }));
+            }
+        }
+        output
+    }
+}
+
+fn decode_utf8(pending: &mut Vec<u8>, bytes: &[u8], last: bool, output: &mut String) {
+    let joined;
+    let bytes = if pending.is_empty() {
+        bytes
+    } else {
+        pending.extend_from_slice(bytes);
+        joined = std::mem::take(pending);
+        &joined[..]
+    };
+
+    let mut chunks = bytes.utf8_chunks().peekable();
+    while let Some(chunk) = chunks.next() {
+        output.push_str(chunk.valid());
+        let invalid = chunk.invalid();
+        if invalid.is_empty() {
+            continue;
+        }
+        let truncated = str::from_utf8(invalid)
+            .err()
+            .is_some_and(|e| e.error_len().is_none());
+        if !last && chunks.peek().is_none() && truncated {
+            pending.extend_from_slice(invalid);
+        } else {
+            output.push(char::REPLACEMENT_CHARACTER);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(
+            Encoding::for_label(" Shift_JIS\n"),
+            Some(Encoding::ShiftJis)
+        );
+        assert_eq!(Encoding::for_label("latin1"), Some(Encoding::Windows1252));
+        assert_eq!(Encoding::for_label("UTF8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::for_label("utf-7"), None);
+    }
+
+    #[test]
+    fn test_decode_japanese() {
+        // "日本語ｶﾅ" in each encoding.
+        let expected = "日本語ｶﾅ";
+        assert_eq!(
+            Encoding::ShiftJis.decode(b"\x93\xfa\x96\x7b\x8c\xea\xb6\xc5"),
+            expected
+        );
+        assert_eq!(
+            Encoding::EucJp.decode(b"\xc6\xfc\xcb\xdc\xb8\xec\x8e\xb6\x8e\xc5"),
+            expected
+        );
+        assert_eq!(
+            Encoding::Iso2022Jp.decode(b"\x1b$B\x46\x7c\x4b\x5c\x38\x6c\x1b(I\x36\x45\x1b(B"),
+            expected
+        );
+        assert_eq!(Encoding::EucJp.decode(b"\x8f\xb0\xa1"), "丂");
+        assert_eq!(
+            Encoding::ShiftJis.decode(b"\x81\x60\xf0\x40"),
+            "\u{ff5e}\u{e000}"
+        );
+        assert_eq!(Encoding::ShiftJis.decode(b"\x82 \x82"), "\u{fffd} \u{fffd}");
+        assert_eq!(
+            Encoding::Iso2022Jp.decode(b"\x1b(J\\~\x1b(B\\"),
+            "\u{a5}\u{203e}\\"
+        );
+        assert_eq!(Encoding::Iso2022Jp.decode(b"\x1b(B\x1b(Ba"), "\u{fffd}a");
+    }
+
+    #[test]
+    fn test_decode_single_byte() {
+        assert_eq!(
+            Encoding::Windows1252.decode(b"\x80\x81caf\xe9"),
+            "€\u{81}café"
+        );
+        assert_eq!(
+            Encoding::Koi8R.decode(b"\xf0\xd2\xc9\xd7\xc5\xd4"),
+            "Привет"
+        );
+        assert_eq!(Encoding::Iso8859_8.decode(b"\xa1"), "\u{fffd}");
+    }
+
+    #[test]
+    fn test_streaming_decode() {
+        let mut decoder = Encoding::Utf8.new_decoder();
+        let bytes = "añ日😀".as_bytes();
+        let mut output = String::new();
+        for byte in bytes {
+            output += &decoder.decode(&[*byte], false);
+        }
+        output += &decoder.decode(&[0xe6], true);
+        assert_eq!(output, "añ日😀\u{fffd}");
+
+        let mut decoder = Encoding::Utf16Le.new_decoder();
+        let mut output = decoder.decode(b"a\x00\x3d", false);
+        output += &decoder.decode(b"\xd8\x00\xde\x00", true);
+        assert_eq!(output, "a😀\u{fffd}");
+
+        let mut decoder = Encoding::ShiftJis.new_decoder();
+        let mut output = decoder.decode(b"\x93", false);
+        output += &decoder.decode(b"\xfa", true);
+        assert_eq!(output, "日");
+    }
+}
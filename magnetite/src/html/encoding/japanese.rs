@@ -0,0 +1,254 @@
+use super::index_jis0208::JIS0208;
+use super::index_jis0212::JIS0212;
+
+const REPLACEMENT: char = char::REPLACEMENT_CHARACTER;
+
+fn index(table: &[u16], pointer: usize) -> Option<char> {
+    match table.get(pointer) {
+        Some(0) | None => None,
+        Some(&code_point) => char::from_u32(code_point as u32),
+    }
+}
+
+fn halfwidth_katakana(byte: u8, first: u8) -> char {
+    char::from_u32(0xff61 + (byte - first) as u32).unwrap()
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct ShiftJisDecoder {
+    lead: u8,
+}
+
+impl ShiftJisDecoder {
+    pub(super) fn push(&mut self, byte: u8, output: &mut String) {
+        if self.lead != 0 {
+            let lead = std::mem::take(&mut self.lead);
+            let lead_offset = if lead < 0xa0 { 0x81 } else { 0xc1 };
+            let offset = if byte < 0x7f { 0x40 } else { 0x41 };
+            let pointer = match byte {
+                0x40..=0x7e | 0x80..=0xfc => {
+                    Some((lead - lead_offset) as usize * 188 + (byte - offset) as usize)
+                }
+                _ => None,
+            };
+            let code_point = match pointer {
+                Some(pointer @ 8836..=10715) => char::from_u32(0xe000 - 8836 + pointer as u32),
+                Some(pointer) => index(&JIS0208, pointer),
+                None => None,
+            };
+            match code_point {
+                Some(c) => output.push(c),
+                None => {
+                    output.push(REPLACEMENT);
+                    if byte.is_ascii() {
+                        self.push(byte, output);
+                    }
+                }
+            }
+            return;
+        }
+
+        match byte {
+            0x00..=0x80 => output.push(byte as char),
+            0xa1..=0xdf => output.push(halfwidth_katakana(byte, 0xa1)),
+            0x81..=0x9f | 0xe0..=0xfc => self.lead = byte,
+            _ => output.push(REPLACEMENT),
+        }
+    }
+
+    pub(super) fn finish(&mut self, output: &mut String) {
+        if std::mem::take(&mut self.lead) != 0 {
+            output.push(REPLACEMENT);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct EucJpDecoder {
+    lead: u8,
+    jis0212: bool,
+}
+
+impl EucJpDecoder {
+    pub(super) fn push(&mut self, byte: u8, output: &mut String) {
+        if self.lead == 0x8e && (0xa1..=0xdf).contains(&byte) {
+            self.lead = 0;
+            output.push(halfwidth_katakana(byte, 0xa1));
+            return;
+        }
+        if self.lead == 0x8f && (0xa1..=0xfe).contains(&byte) {
+            self.jis0212 = true;
+            self.lead = byte;
+            return;
+        }
+        if self.lead != 0 {
+            let lead = std::mem::take(&mut self.lead);
+            let jis0212 = std::mem::take(&mut self.jis0212);
+            let mut code_point = None;
+            if (0xa1..=0xfe).contains(&lead) && (0xa1..=0xfe).contains(&byte) {
+                let pointer = (lead - 0xa1) as usize * 94 + (byte - 0xa1) as usize;
+                let table = if jis0212 { &JIS0212[..] } else { &JIS0208[..] };
+                code_point = index(table, pointer);
+            }
+            match code_point {
+                Some(c) => output.push(c),
+                None => {
+                    output.push(REPLACEMENT);
+                    if byte.is_ascii() {
+                        self.push(byte, output);
+                    }
+                }
+            }
+            return;
+        }
+
+        match byte {
+            0x00..=0x7f => output.push(byte as char),
+            0x8e | 0x8f | 0xa1..=0xfe => self.lead = byte,
+            _ => output.push(REPLACEMENT),
+        }
+    }
+
+    pub(super) fn finish(&mut self, output: &mut String) {
+        self.jis0212 = false;
+        if std::mem::take(&mut self.lead) != 0 {
+            output.push(REPLACEMENT);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Iso2022JpState {
+    #[default]
+    Ascii,
+    Roman,
+    Katakana,
+    LeadByte,
+    TrailByte,
+    EscapeStart,
+    Escape,
+}
+
+/// The stateful ISO-2022-JP decoder. Escape sequences switch between ASCII,
+/// JIS X 0201 Roman and Katakana, and JIS X 0208.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Iso2022JpDecoder {
+    state: Iso2022JpState,
+    output_state: Iso2022JpState,
+    lead: u8,
+    output: bool,
+}
+
+impl Iso2022JpDecoder {
+    pub(super) fn push(&mut self, byte: u8, output: &mut String) {
+        match self.state {
+            Iso2022JpState::Ascii | Iso2022JpState::Roman => {
+                self.output &= byte == 0x1b;
+                match byte {
+                    0x1b => self.state = Iso2022JpState::EscapeStart,
+                    0x5c if self.state == Iso2022JpState::Roman => output.push('\u{a5}'),
+                    0x7e if self.state == Iso2022JpState::Roman => output.push('\u{203e}'),
+                    0x0e | 0x0f => output.push(REPLACEMENT),
+                    0x00..=0x7f => output.push(byte as char),
+                    _ => output.push(REPLACEMENT),
+                }
+            }
+            Iso2022JpState::Katakana => {
+                self.output &= byte == 0x1b;
+                match byte {
+                    0x1b => self.state = Iso2022JpState::EscapeStart,
+                    0x21..=0x5f => output.push(halfwidth_katakana(byte, 0x21)),
+                    _ => output.push(REPLACEMENT),
+                }
+            }
+            Iso2022JpState::LeadByte => {
+                self.output &= byte == 0x1b;
+                match byte {
+                    0x1b => self.state = Iso2022JpState::EscapeStart,
+                    0x21..=0x7e => {
+                        self.lead = byte;
+                        self.state = Iso2022JpState::TrailByte;
+                    }
+                    _ => output.push(REPLACEMENT),
+                }
+            }
+            Iso2022JpState::TrailByte => match byte {
+                0x1b => {
+                    self.state = Iso2022JpState::EscapeStart;
+                    output.push(REPLACEMENT);
+                }
+                0x21..=0x7e => {
+                    self.state = Iso2022JpState::LeadByte;
+                    let pointer = (self.lead - 0x21) as usize * 94 + (byte - 0x21) as usize;
+                    output.push(index(&JIS0208, pointer).unwrap_or(REPLACEMENT));
+                }
+                _ => {
+                    self.state = Iso2022JpState::LeadByte;
+                    output.push(REPLACEMENT);
+                    self.push(byte, output);
+                }
+            },
+            Iso2022JpState::EscapeStart => {
+                if byte == 0x24 || byte == 0x28 {
+                    self.lead = byte;
+                    self.state = Iso2022JpState::Escape;
+                } else {
+                    self.output = false;
+                    self.state = self.output_state;
+                    output.push(REPLACEMENT);
+                    self.push(byte, output);
+                }
+            }
+            Iso2022JpState::Escape => {
+                let lead = std::mem::take(&mut self.lead);
+                let state = match (lead, byte) {
+                    (0x28, 0x42) => Some(Iso2022JpState::Ascii),
+                    (0x28, 0x4a) => Some(Iso2022JpState::Roman),
+                    (0x28, 0x49) => Some(Iso2022JpState::Katakana),
+                    (0x24, 0x40 | 0x42) => Some(Iso2022JpState::LeadByte),
+                    _ => None,
+                };
+                match state {
+                    Some(state) => {
+                        self.state = state;
+                        self.output_state = state;
+                        // Two escape sequences in a row hide nothing but are
+                        // still an error.
+                        if std::mem::replace(&mut self.output, true) {
+                            output.push(REPLACEMENT);
+                        }
+                    }
+                    None => {
+                        self.output = false;
+                        self.state = self.output_state;
+                        output.push(REPLACEMENT);
+                        self.push(lead, output);
+                        self.push(byte, output);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) fn finish(&mut self, output: &mut String) {
+        match self.state {
+            Iso2022JpState::TrailByte => {
+                self.state = Iso2022JpState::LeadByte;
+                output.push(REPLACEMENT);
+            }
+            Iso2022JpState::EscapeStart => {
+                self.output = false;
+                self.state = self.output_state;
+                output.push(REPLACEMENT);
+            }
+            Iso2022JpState::Escape => {
+                let lead = std::mem::take(&mut self.lead);
+                self.output = false;
+                self.state = self.output_state;
+                output.push(REPLACEMENT);
+                self.push(lead, output);
+            }
+            _ => (),
+        }
+    }
+}
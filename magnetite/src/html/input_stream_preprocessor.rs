@@ -1,32 +1,226 @@
 use super::byte_stream_decoder::ByteStreamDecoder;
+use super::diagnostic::SourcePosition;
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Error as FmtError;
+use std::fmt::Formatter;
+use std::io::Empty;
 use std::io::Error as IoError;
 use std::io::Read;
 
+/// Input was pushed into a stream after it was closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClosedStreamError;
+
+impl Display for ClosedStreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "pushed into a closed input stream")
+    }
+}
+
+impl Error for ClosedStreamError {}
+
+/// The input stream the tokenizer reads from. Text can be appended in chunks
+/// while the tokenizer consumes it from the front; consumed text is dropped
+/// whenever more arrives, so a document is never held twice in full.
 pub struct InputStreamPreprocessor {
     string: String,
+    position: usize,
+    dropped: usize,
+    line: usize,
+    column: usize,
+    previous_line_length: usize,
+    decoder: Option<ByteStreamDecoder<Empty>>,
+    started: bool,
+    last_was_cr: bool,
+    closed: bool,
 }
 
 impl InputStreamPreprocessor {
+    /// Reads the whole stream up front, appending each chunk as it is decoded.
     pub fn new<S: Read>(mut decoder: ByteStreamDecoder<S>) -> Result<Self, IoError> {
-        Ok(Self {
-            string: decoder.decode()?,
-        })
+        let mut preprocessor = Self::from_decoder(None);
+        while let Some(string) = decoder.read_chunk()? {
+            preprocessor.append(&string);
+        }
+        preprocessor.close();
+        Ok(preprocessor)
     }
 
-    pub fn preprocess(mut self) -> String {
-        self.normalize_crlf();
-        self.delete_bom();
-        self.string
+    /// Wraps text that is already decoded as a complete input stream.
+    pub fn from_text(text: &str) -> Self {
+        let mut preprocessor = Self::from_decoder(None);
+        preprocessor.append(text);
+        preprocessor.close();
+        preprocessor
+    }
+
+    /// Starts an empty input stream that is fed with `push` and ended with
+    /// `close`.
+    pub fn streaming(decoder: ByteStreamDecoder<Empty>) -> Self {
+        Self::from_decoder(Some(decoder))
+    }
+
+    fn from_decoder(decoder: Option<ByteStreamDecoder<Empty>>) -> Self {
+        Self {
+            string: String::new(),
+            position: 0,
+            dropped: 0,
+            line: 1,
+            column: 1,
+            previous_line_length: 0,
+            decoder,
+            started: false,
+            last_was_cr: false,
+            closed: false,
+        }
+    }
+
+    /// Decodes and appends the next chunk of bytes. Only streams started
+    /// with `streaming` have a decoder, and only until they are closed.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), ClosedStreamError> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Err(ClosedStreamError);
+        };
+        let string = decoder.push(bytes);
+        self.append(&string);
+        Ok(())
+    }
+
+    fn append(&mut self, string: &str) {
+        self.string.drain(..self.position);
+        self.dropped += self.position;
+        self.position = 0;
+
+        let mut string = string;
+        if !self.started && !string.is_empty() {
+            self.started = true;
+            string = delete_bom(string);
+        }
+        self.normalize_newlines(string);
+    }
+
+    /// Marks the end of the input, flushing anything the decoder held back.
+    pub fn close(&mut self) {
+        if let Some(mut decoder) = self.decoder.take() {
+            let string = decoder.finish();
+            self.append(&string);
+        }
+        self.closed = true;
+    }
+
+    /// Whether all of the input has arrived.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The text that has arrived but hasn't been consumed yet.
+    pub fn remaining(&self) -> &str {
+        &self.string[self.position..]
+    }
+
+    /// Where the next character to be consumed is.
+    pub fn position(&self) -> SourcePosition {
+        SourcePosition {
+            offset: self.dropped + self.position,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    pub fn advance(&mut self, len: usize) {
+        for c in self.string[self.position..self.position + len].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.previous_line_length = self.column;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.position += len;
+    }
+
+    /// Steps back over text just consumed. Only the most recent newline can
+    /// be stepped back over, which is all reconsuming a character needs.
+    pub fn retreat(&mut self, len: usize) {
+        self.position -= len;
+        for c in self.string[self.position..self.position + len].chars() {
+            if c == '\n' {
+                self.line -= 1;
+                self.column = self.previous_line_length;
+            } else {
+                self.column -= 1;
+            }
+        }
     }
 
-    fn normalize_crlf(&mut self) {
-        self.string = self.string.replace("\r\n", "\n").replace("\r", "\n");
+    /// Converts CRLF and lone CR to LF. A CR at the end of a chunk is
+    /// remembered so an LF starting the next one isn't doubled.
+    fn normalize_newlines(&mut self, string: &str) {
+        let mut string = string;
+        if self.last_was_cr && string.starts_with('\n') {
+            string = &string[1..];
+        }
+        if !string.is_empty() {
+            self.last_was_cr = string.ends_with('\r');
+        }
+        self.string
+            .push_str(&string.replace("\r\n", "\n").replace('\r', "\n"));
     }
+}
 
-    fn delete_bom(&mut self) {
-        const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
-        if &self.string.as_bytes()[..3] == UTF8_BOM {
-            self.string.replace_range(..3, "");
+fn delete_bom(string: &str) -> &str {
+    string.strip_prefix('\u{feff}').unwrap_or(string)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_push_in_chunks() {
+        let mut preprocessor = InputStreamPreprocessor::streaming(ByteStreamDecoder::streaming());
+        for chunk in [&b"\xef\xbb"[..], b"\xbfa\r", b"\nb\r", b"\r", b"c"] {
+            preprocessor.push(chunk).unwrap();
         }
+        assert_eq!(preprocessor.remaining(), "");
+        preprocessor.close();
+        assert_eq!(preprocessor.remaining(), "a\nb\n\nc");
+        assert_eq!(preprocessor.push(b"d"), Err(ClosedStreamError));
+
+        preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(&b"a\r\nb"[..])).unwrap();
+        assert!(preprocessor.is_closed());
+        assert_eq!(preprocessor.remaining(), "a\nb");
+        assert_eq!(preprocessor.push(b"c"), Err(ClosedStreamError));
+    }
+
+    #[test]
+    fn test_position() {
+        let mut preprocessor = InputStreamPreprocessor::streaming(
+            ByteStreamDecoder::streaming().with_transport_charset("utf-8"),
+        );
+        preprocessor.push("ab\r\nあ".as_bytes()).unwrap();
+        preprocessor.advance("ab\n".len());
+        preprocessor.push(b"\nc").unwrap();
+        preprocessor.advance("あ\n".len());
+        assert_eq!(
+            preprocessor.position(),
+            SourcePosition {
+                offset: 7,
+                line: 3,
+                column: 1,
+            }
+        );
+        preprocessor.retreat(1);
+        assert_eq!(
+            preprocessor.position(),
+            SourcePosition {
+                offset: 6,
+                line: 2,
+                column: 2,
+            }
+        );
     }
 }
@@ -0,0 +1,246 @@
+use super::dom::DomArena;
+use super::dom::DomError;
+use super::dom::DomNodeType;
+use super::dom::Namespace;
+use super::dom::NodeId;
+use crate::css::Origin;
+use crate::css::Selector;
+use crate::css::SelectorTarget;
+use crate::css::Tokenizer as CssTokenizer;
+
+impl DomArena {
+    /// The first element in the document whose `id` is `element_id`.
+    pub fn get_element_by_id(&self, element_id: &str) -> Option<NodeId> {
+        self.elements(Self::DOCUMENT_IDX)
+            .find(|&id| self[id].id() == Some(element_id))
+    }
+
+    /// The elements under `root` named `name`, in tree order. The name of
+    /// HTML elements is matched case-insensitively, and `*` matches every
+    /// element.
+    pub fn get_elements_by_tag_name(&self, root: NodeId, name: &str) -> Vec<NodeId> {
+        let lowercase_name = name.to_ascii_lowercase();
+        self.elements(root)
+            .filter(|&id| {
+                let Some(local_name) = self.local_name(id) else {
+                    return false;
+                };
+                name == "*"
+                    || if self[id].namespace() == Namespace::Html {
+                        local_name == lowercase_name
+                    } else {
+                        local_name == name
+                    }
+            })
+            .collect()
+    }
+
+    /// The elements under `root` that have all of the whitespace-separated
+    /// classes in `names`, in tree order.
+    pub fn get_elements_by_class_name(&self, root: NodeId, names: &str) -> Vec<NodeId> {
+        let names: Vec<&str> = names.split_ascii_whitespace().collect();
+        if names.is_empty() {
+            return Vec::new();
+        }
+        self.elements(root)
+            .filter(|&id| {
+                let classes = self[id].class_list();
+                names.iter().all(|name| classes.contains(name))
+            })
+            .collect()
+    }
+
+    /// The first element under `root` that matches the selector list
+    /// `selectors`. Selectors are matched the same way as in style sheets.
+    /// A selector list that is invalid, or uses syntax style sheets don't
+    /// support yet, is a `DomError::Syntax`.
+    pub fn query_selector(
+        &self,
+        root: NodeId,
+        selectors: &str,
+    ) -> Result<Option<NodeId>, DomError> {
+        let selectors = parse_selectors(selectors)?;
+        Ok(self
+            .elements(root)
+            .find(|&id| selectors.iter().any(|s| s.match_with(self, id))))
+    }
+
+    /// All the elements under `root` that match the selector list
+    /// `selectors`, in tree order.
+    pub fn query_selector_all(
+        &self,
+        root: NodeId,
+        selectors: &str,
+    ) -> Result<Vec<NodeId>, DomError> {
+        let selectors = parse_selectors(selectors)?;
+        Ok(self
+            .elements(root)
+            .filter(|&id| selectors.iter().any(|s| s.match_with(self, id)))
+            .collect())
+    }
+
+    /// The text of the node `id`. For a document, a fragment or an element,
+    /// this is the text of all the text nodes under it, joined together.
+    pub fn text_content(&self, id: NodeId) -> String {
+        match self[id].node_type {
+            DomNodeType::DocType { .. } => String::new(),
+            DomNodeType::Comment(ref text) | DomNodeType::Text(ref text) => text.clone(),
+            DomNodeType::Document | DomNodeType::DocumentFragment | DomNodeType::Element { .. } => {
+                let mut text = String::new();
+                for descendant in self.descendants(id) {
+                    if let DomNodeType::Text(ref s) = self[descendant].node_type {
+                        text.push_str(s);
+                    }
+                }
+                text
+            }
+        }
+    }
+
+    /// The elements under `root` in tree order.
+    fn elements(&self, root: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.descendants(root)
+            .filter(|&id| matches!(self[id].node_type, DomNodeType::Element { .. }))
+    }
+}
+
+impl SelectorTarget for DomArena {
+    fn parent_element(&self, id: NodeId) -> Option<NodeId> {
+        self[id]
+            .parent()
+            .filter(|&parent| self.local_name(parent).is_some())
+    }
+
+    fn local_name(&self, id: NodeId) -> Option<&str> {
+        match self[id].node_type {
+            DomNodeType::Element { ref name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    fn attribute(&self, id: NodeId, name: &str) -> Option<&str> {
+        self[id].attribute(name)
+    }
+}
+
+fn parse_selectors(selectors: &str) -> Result<Vec<Selector>, DomError> {
+    let mut tokenizer = CssTokenizer::new(selectors);
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.step() {
+        tokens.push(token);
+    }
+    Selector::from_tokens(&tokens, Origin::Developer, false).ok_or(DomError::Syntax)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::html::ByteStreamDecoder;
+    use crate::html::InputStreamPreprocessor;
+    use crate::html::Tokenizer;
+    use crate::html::TreeConstructor;
+
+    fn parse(html: &str) -> DomArena {
+        let preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(html.as_bytes())).unwrap();
+        let mut tree_constructor = TreeConstructor::new();
+        Tokenizer::new(preprocessor, &mut tree_constructor).run();
+        tree_constructor.take_dom()
+    }
+
+    fn names(dom: &DomArena, ids: &[NodeId]) -> Vec<String> {
+        ids.iter()
+            .map(|&id| {
+                let name = dom.local_name(id).unwrap();
+                match dom.attribute(id, "id") {
+                    Some(element_id) => format!("{name}#{element_id}"),
+                    None => name.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        let dom = parse(
+            "<div id=main class='box wide'><p id=a class=intro>Hello, <b>world</b>!</p>\
+            <p id=b class='intro box'>Bye</p></div><section><p id=c>x</p>\
+            <svg><foreignObject id=f></foreignObject></svg></section><p id=a>dup</p>",
+        );
+        let document = DomArena::DOCUMENT_IDX;
+
+        let main = dom.get_element_by_id("main").unwrap();
+        assert_eq!(names(&dom, &[dom.get_element_by_id("a").unwrap()]), ["p#a"]);
+        assert_eq!(dom.get_element_by_id("missing"), None);
+
+        assert_eq!(
+            names(&dom, &dom.get_elements_by_tag_name(document, "P")),
+            ["p#a", "p#b", "p#c", "p#a"]
+        );
+        assert_eq!(
+            names(&dom, &dom.get_elements_by_tag_name(main, "*")),
+            ["p#a", "b", "p#b"]
+        );
+        assert_eq!(
+            names(
+                &dom,
+                &dom.get_elements_by_tag_name(document, "foreignObject")
+            ),
+            ["foreignObject#f"]
+        );
+        assert!(
+            dom.get_elements_by_tag_name(document, "foreignobject")
+                .is_empty()
+        );
+
+        assert_eq!(
+            names(&dom, &dom.get_elements_by_class_name(document, "box")),
+            ["div#main", "p#b"]
+        );
+        assert_eq!(
+            names(
+                &dom,
+                &dom.get_elements_by_class_name(document, " intro  box ")
+            ),
+            ["p#b"]
+        );
+        assert!(dom.get_elements_by_class_name(document, " ").is_empty());
+
+        assert_eq!(
+            names(
+                &dom,
+                &dom.query_selector_all(document, "div p.intro, section p")
+                    .unwrap()
+            ),
+            ["p#a", "p#b", "p#c"]
+        );
+        assert_eq!(
+            names(&dom, &dom.query_selector_all(main, "#main b").unwrap()),
+            ["b"]
+        );
+        assert_eq!(
+            names(
+                &dom,
+                &[dom.query_selector(document, ".box.intro").unwrap().unwrap()]
+            ),
+            ["p#b"]
+        );
+        assert_eq!(dom.query_selector(main, "section"), Ok(None));
+        assert!(dom.query_selector_all(main, "section").unwrap().is_empty());
+        for invalid in ["body > p", "p:first-child", "[id]", "p,", ""] {
+            assert_eq!(
+                dom.query_selector_all(document, invalid),
+                Err(DomError::Syntax)
+            );
+            assert_eq!(dom.query_selector(document, invalid), Err(DomError::Syntax));
+        }
+
+        let p = dom.get_element_by_id("a").unwrap();
+        assert_eq!(dom.text_content(p), "Hello, world!");
+        assert_eq!(dom.text_content(main), "Hello, world!Bye");
+        assert_eq!(
+            names(&dom, &dom.ancestors(p).take(3).collect::<Vec<_>>()),
+            ["div#main", "body", "html"]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use super::dom::DomArena;
+use super::dom::DomNodeType;
+use super::dom::Namespace;
+use super::dom::NodeId;
+
+/// Elements that have no end tag and never have children.
+const VOID_ELEMENTS: [&str; 17] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "hr", "img", "input", "keygen",
+    "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text is written out as is. The parser runs with scripting
+/// disabled, so `noscript` isn't one of them.
+const RAW_TEXT_ELEMENTS: [&str; 7] = [
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+    "script",
+    "style",
+    "xmp",
+];
+
+impl DomArena {
+    /// Serializes the node `id` along with its descendants.
+    pub fn outer_html(&self, id: NodeId) -> String {
+        let mut html = String::new();
+        self.serialize_node(id, &mut html);
+        html
+    }
+
+    /// Serializes the descendants of the node `id`, following the HTML
+    /// fragment serialization algorithm. For a `template` element, these
+    /// are the descendants of its contents.
+    pub fn inner_html(&self, id: NodeId) -> String {
+        let mut html = String::new();
+        self.serialize_children(id, &mut html);
+        html
+    }
+
+    /// Serializes the node `id` with one node per line, indented by depth.
+    /// Whitespace-only text is dropped and other text is trimmed, so the
+    /// result is meant for reading and doesn't parse back to the same
+    /// tree. The contents of `pre`, `textarea` and raw text elements are
+    /// kept as they are.
+    pub fn pretty_html(&self, id: NodeId) -> String {
+        let mut html = String::new();
+        self.pretty_print(id, 0, &mut html);
+        html
+    }
+
+    fn serialize_node(&self, id: NodeId, html: &mut String) {
+        match self[id].node_type {
+            DomNodeType::Document | DomNodeType::DocumentFragment => {
+                self.serialize_children(id, html);
+            }
+            DomNodeType::DocType { ref name, .. } => {
+                html.push_str("<!DOCTYPE ");
+                html.push_str(name);
+                html.push('>');
+            }
+            DomNodeType::Element { ref name, .. } => {
+                self.serialize_start_tag(id, html);
+                if !self.is_void(id) {
+                    self.serialize_children(id, html);
+                    html.push_str("</");
+                    html.push_str(name);
+                    html.push('>');
+                }
+            }
+            DomNodeType::Comment(ref text) => {
+                html.push_str("<!--");
+                html.push_str(text);
+                html.push_str("-->");
+            }
+            DomNodeType::Text(ref text) => self.serialize_text(id, text, html),
+        }
+    }
+
+    fn serialize_children(&self, id: NodeId, html: &mut String) {
+        let parent = self.template_contents(id).unwrap_or(id);
+        for child in self.children(parent) {
+            self.serialize_node(child, html);
+        }
+    }
+
+    fn serialize_start_tag(&self, id: NodeId, html: &mut String) {
+        let DomNodeType::Element {
+            ref name,
+            ref attributes,
+        } = self[id].node_type
+        else {
+            return;
+        };
+        html.push('<');
+        html.push_str(name);
+        for attribute in attributes {
+            html.push(' ');
+            html.push_str(&attribute.qualified_name());
+            html.push_str("=\"");
+            escape(&attribute.value, true, html);
+            html.push('"');
+        }
+        html.push('>');
+    }
+
+    fn serialize_text(&self, id: NodeId, text: &str, html: &mut String) {
+        if self[id]
+            .parent()
+            .is_some_and(|parent| self.is_html_element(parent, &RAW_TEXT_ELEMENTS))
+        {
+            html.push_str(text);
+        } else {
+            escape(text, false, html);
+        }
+    }
+
+    fn pretty_print(&self, id: NodeId, depth: usize, html: &mut String) {
+        let indent = "  ".repeat(depth);
+        match self[id].node_type {
+            DomNodeType::Document | DomNodeType::DocumentFragment => {
+                for child in self.children(id) {
+                    self.pretty_print(child, depth, html);
+                }
+            }
+            DomNodeType::Element { ref name, .. } => {
+                html.push_str(&indent);
+                if self.is_void(id)
+                    || self.is_html_element(id, &["pre", "textarea"])
+                    || self.is_html_element(id, &RAW_TEXT_ELEMENTS)
+                {
+                    self.serialize_node(id, html);
+                    html.push('\n');
+                    return;
+                }
+
+                let parent = self.template_contents(id).unwrap_or(id);
+                let children: Vec<NodeId> = self
+                    .children(parent)
+                    .filter(|&child| !self.is_whitespace_text(child))
+                    .collect();
+                self.serialize_start_tag(id, html);
+                if let [child] = children[..]
+                    && self.is_text(child)
+                {
+                    html.push_str(self.outer_html(child).trim());
+                } else if !children.is_empty() {
+                    html.push('\n');
+                    for child in children {
+                        self.pretty_print(child, depth + 1, html);
+                    }
+                    html.push_str(&indent);
+                }
+                html.push_str("</");
+                html.push_str(name);
+                html.push_str(">\n");
+            }
+            _ if self.is_whitespace_text(id) => (),
+            _ => {
+                html.push_str(&indent);
+                html.push_str(self.outer_html(id).trim());
+                html.push('\n');
+            }
+        }
+    }
+
+    fn is_void(&self, id: NodeId) -> bool {
+        self.is_html_element(id, &VOID_ELEMENTS)
+    }
+
+    fn is_html_element(&self, id: NodeId, names: &[&str]) -> bool {
+        match self[id].node_type {
+            DomNodeType::Element { ref name, .. } => {
+                self[id].namespace() == Namespace::Html && names.contains(&name.as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn is_text(&self, id: NodeId) -> bool {
+        matches!(self[id].node_type, DomNodeType::Text(_))
+    }
+
+    fn is_whitespace_text(&self, id: NodeId) -> bool {
+        match self[id].node_type {
+            DomNodeType::Text(ref text) => text.chars().all(|c| c.is_ascii_whitespace()),
+            _ => false,
+        }
+    }
+}
+
+/// Escapes `text` as the serialization algorithm does. Quotes are only
+/// escaped in attribute values.
+fn escape(text: &str, attribute_mode: bool, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '\u{a0}' => html.push_str("&nbsp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' if attribute_mode => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::html::ByteStreamDecoder;
+    use crate::html::InputStreamPreprocessor;
+    use crate::html::Tokenizer;
+    use crate::html::TreeConstructor;
+
+    fn parse(html: &str) -> DomArena {
+        let preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(html.as_bytes())).unwrap();
+        let mut tree_constructor = TreeConstructor::new();
+        Tokenizer::new(preprocessor, &mut tree_constructor).run();
+        tree_constructor.take_dom()
+    }
+
+    #[test]
+    fn test_outer_html() {
+        let html = "<!DOCTYPE html><html><head><title>a &amp; b</title></head>\
+            <body><p class=\"x\" id=\"y\">1 &lt; 2&nbsp;<br><img alt=\"&quot;q&quot; &amp; <\"></p>\
+            <!-- c --><script>if (a < b) {}</script><textarea>\n&lt;</textarea>\
+            <template><td>t</td></template><svg xlink:href=\"#a\" viewBox=\"0 0 1 1\"><foreignObject>\
+            <circle/></foreignObject></svg></body></html>";
+        let dom = parse(html);
+        assert_eq!(
+            dom.outer_html(DomArena::DOCUMENT_IDX),
+            "<!DOCTYPE html><html><head><title>a &amp; b</title></head>\
+            <body><p class=\"x\" id=\"y\">1 &lt; 2&nbsp;<br><img alt=\"&quot;q&quot; &amp; &lt;\"></p>\
+            <!-- c --><script>if (a < b) {}</script><textarea>&lt;</textarea>\
+            <template><td>t</td></template><svg xlink:href=\"#a\" viewBox=\"0 0 1 1\"><foreignObject>\
+            <circle></circle></foreignObject></svg></body></html>"
+        );
+
+        let html_id = dom
+            .get_child_element(DomArena::DOCUMENT_IDX, "html")
+            .unwrap();
+        let body_id = dom.get_child_element(html_id, "body").unwrap();
+        let template_id = dom.get_child_element(body_id, "template").unwrap();
+        assert_eq!(dom.inner_html(template_id), "<td>t</td>");
+        let p_id = dom.get_child_element(body_id, "p").unwrap();
+        assert_eq!(
+            dom.inner_html(p_id),
+            "1 &lt; 2&nbsp;<br><img alt=\"&quot;q&quot; &amp; &lt;\">"
+        );
+        assert_eq!(
+            parse(&dom.outer_html(DomArena::DOCUMENT_IDX)).outer_html(0),
+            dom.outer_html(0)
+        );
+    }
+
+    #[test]
+    fn test_pretty_html() {
+        let dom = parse(
+            "<!DOCTYPE html><html><head></head><body>\n  <ul><li>a</li> <li><b>b</b> c</li></ul>\
+            <pre>\n\n x </pre><hr></body></html>",
+        );
+        assert_eq!(
+            dom.pretty_html(DomArena::DOCUMENT_IDX),
+            "<!DOCTYPE html>\n\
+            <html>\n\
+            \x20 <head></head>\n\
+            \x20 <body>\n\
+            \x20   <ul>\n\
+            \x20     <li>a</li>\n\
+            \x20     <li>\n\
+            \x20       <b>b</b>\n\
+            \x20       c\n\
+            \x20     </li>\n\
+            \x20   </ul>\n\
+            \x20   <pre>\n x </pre>\n\
+            \x20   <hr>\n\
+            \x20 </body>\n\
+            </html>\n"
+        );
+    }
+}
@@ -1,38 +1,70 @@
+mod named_character_references;
+
+use super::diagnostic::SourcePosition;
+use super::diagnostic::SourceSpan;
+use super::dom::Attribute;
+use super::dom::Attributes;
 use super::dom::Namespace;
+use super::input_stream_preprocessor::ClosedStreamError;
 use super::input_stream_preprocessor::InputStreamPreprocessor;
 use super::tree_constructor::TreeConstructor;
-use std::collections::HashMap;
+use named_character_references::NAMED_CHARACTER_REFERENCES;
 use std::mem;
 
 type State = TokenizerState;
 
-pub struct Tokenizer<'a> {
+/// Where the tokenizer sends what it produces. That's normally the tree
+/// constructor, but anything that wants the bare tokens can take its place.
+pub trait TokenSink {
+    /// Handles `token`, which spans `span` of the input. Returns the state
+    /// the tokenizer should switch to, if the token calls for one.
+    fn handle_token(&mut self, token: Token, span: SourceSpan) -> Option<TokenizerState>;
+
+    fn handle_error(&mut self, error: ParseError, span: SourceSpan);
+
+    /// The namespace of the adjusted current node, which decides whether
+    /// CDATA sections are allowed.
+    fn adjusted_current_node_namespace(&self) -> Namespace {
+        Namespace::Html
+    }
+
+    fn initial_tokenizer_state(&self) -> TokenizerState {
+        TokenizerState::INIT
+    }
+}
+
+pub struct Tokenizer<'a, S: TokenSink = TreeConstructor> {
     state: State,
     return_state: Option<State>,
-    string: String,
-    string_index: usize,
+    input: InputStreamPreprocessor,
+    finished: bool,
     temporary_buffer: String,
     temporary_token: Option<Token>,
-    tree_constructor: &'a mut TreeConstructor,
+    sink: &'a mut S,
     appropriate_end_tag_name: Option<String>,
     current_attribute: Option<(String, String)>,
+    end_tag_has_attributes: bool,
+    character_reference_code: u32,
+    current_input_character: SourceSpan,
+    token_start: SourcePosition,
 }
 
-impl<'a> Tokenizer<'a> {
-    pub fn new(
-        preprocessor: InputStreamPreprocessor,
-        tree_constructor: &'a mut TreeConstructor,
-    ) -> Self {
+impl<'a, S: TokenSink> Tokenizer<'a, S> {
+    pub fn new(preprocessor: InputStreamPreprocessor, sink: &'a mut S) -> Self {
         Self {
-            state: State::INIT,
+            state: sink.initial_tokenizer_state(),
             return_state: None,
-            string: preprocessor.preprocess(),
-            string_index: 0,
+            input: preprocessor,
+            finished: false,
             temporary_buffer: String::new(),
             temporary_token: None,
-            tree_constructor,
+            sink,
             appropriate_end_tag_name: None,
             current_attribute: None,
+            end_tag_has_attributes: false,
+            character_reference_code: 0,
+            current_input_character: SourceSpan::default(),
+            token_start: SourcePosition::default(),
         }
     }
 
@@ -40,15 +72,52 @@ impl<'a> Tokenizer<'a> {
         while self.step().is_some() {}
     }
 
+    /// Feeds the next chunk of bytes into a streaming input and tokenizes as
+    /// far as it goes. The tree built so far is available in between.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), ClosedStreamError> {
+        self.input.push(bytes)?;
+        self.run();
+        Ok(())
+    }
+
+    /// Ends a streaming input and tokenizes the rest of it.
+    pub fn finish(&mut self) {
+        self.input.close();
+        self.run();
+    }
+
+    /// Whether the end-of-file token has been emitted.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Makes `name` the last start tag emitted, as if the input were a
+    /// continuation of a document where that tag was just seen.
+    pub fn with_last_start_tag(mut self, name: &str) -> Self {
+        self.appropriate_end_tag_name = Some(name.to_string());
+        self
+    }
+
+    pub fn sink(&self) -> &S {
+        self.sink
+    }
+
     pub fn state(&self) -> State {
         self.state
     }
 
     fn emit(&mut self, token: Token) {
+        if token == Token::Eof {
+            self.finished = true;
+        }
         if let Token::StartTag { ref name, .. } = token {
             self.appropriate_end_tag_name = Some(name.clone());
         }
-        if let Some(state) = self.tree_constructor.handle_token(token) {
+        // A token spans everything consumed since the one before it.
+        let end = self.input.position();
+        let start = self.token_start.min(end);
+        self.token_start = end;
+        if let Some(state) = self.sink.handle_token(token, SourceSpan::new(start, end)) {
             self.switch_to(state);
         }
     }
@@ -58,47 +127,84 @@ impl<'a> Tokenizer<'a> {
         self.emit(token);
     }
 
+    fn comment(&mut self) -> &mut String {
+        let Some(Token::Comment(ref mut data)) = self.temporary_token else {
+            panic!();
+        };
+        data
+    }
+
+    fn emit_comment_and_eof(&mut self) {
+        self.error(ParseError::EofInComment);
+        self.emit_temporary_token();
+        self.emit(Token::Eof);
+    }
+
     fn error(&mut self, error: ParseError) {
-        self.tree_constructor.handle_error(error);
+        self.sink.handle_error(error, self.current_input_character);
     }
 
     fn adjusted_current_node_namespace(&self) -> Namespace {
-        self.tree_constructor.adjusted_current_node_namespace()
+        self.sink.adjusted_current_node_namespace()
     }
 
+    fn is_consumed_as_part_of_an_attribute(&self) -> bool {
+        matches!(
+            self.return_state,
+            Some(
+                State::AttributeValueDoubleQuoted
+                    | State::AttributeValueSingleQuoted
+                    | State::AttributeValueUnquoted
+            )
+        )
+    }
+
+    /// Flushes the code points consumed as a character reference, into the
+    /// current attribute value or as character tokens.
     fn flush(&mut self) {
         let temporary_buffer = mem::take(&mut self.temporary_buffer);
 
-        for c in temporary_buffer.chars() {
-            self.emit(Token::Character(c));
+        if self.is_consumed_as_part_of_an_attribute() {
+            self.current_attribute
+                .as_mut()
+                .unwrap()
+                .1
+                .push_str(&temporary_buffer);
+        } else {
+            for c in temporary_buffer.chars() {
+                self.emit(Token::Character(c));
+            }
         }
         self.temporary_buffer = temporary_buffer;
         self.temporary_buffer.clear();
     }
 
     fn look(&self) -> Option<char> {
-        self.string[self.string_index..].chars().next()
+        self.input.remaining().chars().next()
     }
 
     fn read(&mut self) -> Option<char> {
+        let start = self.input.position();
+        self.current_input_character = SourceSpan::new(start, start);
         let c = self.look()?;
-        self.string_index += c.len_utf8();
+        self.input.advance(c.len_utf8());
+        self.current_input_character.end = self.input.position();
         Some(c)
     }
 
     fn unread(&mut self, c: Option<char>) {
         if let Some(c) = c {
-            self.string_index -= c.len_utf8();
+            self.input.retreat(c.len_utf8());
         }
     }
 
     fn look_str(&self, len: usize) -> Option<&str> {
-        self.string[self.string_index..].get(..len)
+        self.input.remaining().get(..len)
     }
 
-    fn read_str(&mut self, len: usize) -> Option<&str> {
-        let s = self.string[self.string_index..].get(..len)?;
-        self.string_index += len;
+    fn read_str(&mut self, len: usize) -> Option<String> {
+        let s = self.look_str(len)?.to_string();
+        self.input.advance(len);
         Some(s)
     }
 
@@ -124,15 +230,39 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    /// How many bytes the current state may need to look ahead. A streaming
+    /// input pauses rather than let a state see a truncated buffer.
+    fn lookahead(&self) -> usize {
+        match self.state {
+            State::MarkupDeclarationOpen => "[CDATA[".len(),
+            State::NamedCharacterReference => LONGEST_CHARACTER_REFERENCE_NAME,
+            State::AfterDoctypeName => "PUBLIC".len(),
+            _ => 1,
+        }
+    }
+
+    /// Runs one state. Returns `None` once the end-of-file token has been
+    /// emitted, or when a streaming input needs more text to go on.
     pub fn step(&mut self) -> Option<()> {
-        if self.look().is_none() {
-            self.emit(Token::Eof);
+        if self.finished {
+            return None;
+        }
+        if !self.input.is_closed() && self.input.remaining().len() < self.lookahead() {
             return None;
         }
-
         match self.state {
             State::Data => self.step_data(),
             State::CharacterReference => self.step_character_reference(),
+            State::NamedCharacterReference => self.step_named_character_reference(),
+            State::AmbiguousAmpersand => self.step_ambiguous_ampersand(),
+            State::NumericCharacterReference => self.step_numeric_character_reference(),
+            State::HexadecimalCharacterReferenceStart => {
+                self.step_hexadecimal_character_reference_start()
+            }
+            State::DecimalCharacterReferenceStart => self.step_decimal_character_reference_start(),
+            State::HexadecimalCharacterReference => self.step_hexadecimal_character_reference(),
+            State::DecimalCharacterReference => self.step_decimal_character_reference(),
+            State::NumericCharacterReferenceEnd => self.step_numeric_character_reference_end(),
             State::TagOpen => self.step_tag_open(),
             State::TagName => self.step_tag_name(),
             State::EndTagOpen => self.step_end_tag_open(),
@@ -140,6 +270,34 @@ impl<'a> Tokenizer<'a> {
             State::Doctype => self.step_doctype(),
             State::BeforeDoctypeName => self.step_before_doctype_name(),
             State::DoctypeName => self.step_doctype_name(),
+            State::AfterDoctypeName => self.step_after_doctype_name(),
+            State::AfterDoctypePublicKeyword => self.step_after_doctype_keyword(DoctypeId::Public),
+            State::BeforeDoctypePublicIdentifier => {
+                self.step_before_doctype_identifier(DoctypeId::Public)
+            }
+            State::DoctypePublicIdentifierDoubleQuoted => {
+                self.step_doctype_identifier_quoted(DoctypeId::Public, '"')
+            }
+            State::DoctypePublicIdentifierSingleQuoted => {
+                self.step_doctype_identifier_quoted(DoctypeId::Public, '\'')
+            }
+            State::AfterDoctypePublicIdentifier => self.step_after_doctype_public_identifier(),
+            State::BetweenDoctypePublicAndSystemIdentifiers => {
+                self.step_between_doctype_public_and_system_identifiers()
+            }
+            State::AfterDoctypeSystemKeyword => self.step_after_doctype_keyword(DoctypeId::System),
+            State::BeforeDoctypeSystemIdentifier => {
+                self.step_before_doctype_identifier(DoctypeId::System)
+            }
+            State::DoctypeSystemIdentifierDoubleQuoted => {
+                self.step_doctype_identifier_quoted(DoctypeId::System, '"')
+            }
+            State::DoctypeSystemIdentifierSingleQuoted => {
+                self.step_doctype_identifier_quoted(DoctypeId::System, '\'')
+            }
+            State::AfterDoctypeSystemIdentifier => self.step_after_doctype_system_identifier(),
+            State::BogusDoctype => self.step_bogus_doctype(),
+            State::PlainText => self.step_plaintext(),
             State::RawText => self.step_raw_text(),
             State::RawTextLessThanSign => self.step_raw_text_less_than(),
             State::RawTextEndTagOpen => self.step_raw_text_end_tag_open(),
@@ -157,7 +315,51 @@ impl<'a> Tokenizer<'a> {
             State::AfterAttributeValueQuoted => self.step_after_attribute_value_quoted(),
             State::AfterAttributeName => self.step_after_attribute_name(),
             State::SelfClosingStartTag => self.step_self_closing_start_tag(),
-            _ => unimplemented!("{:?}\n{:?}", self.state, &self.string[self.string_index..]),
+            State::ScriptData => self.step_script_data(),
+            State::ScriptDataLessThanSign => self.step_script_data_less_than_sign(),
+            State::ScriptDataEndTagOpen => {
+                self.step_text_end_tag_open(State::ScriptDataEndTagName, State::ScriptData)
+            }
+            State::ScriptDataEndTagName => self.step_text_end_tag_name(State::ScriptData),
+            State::ScriptDataEscapeStart => self.step_script_data_escape_start(),
+            State::ScriptDataEscapeStartDash => self.step_script_data_escape_start_dash(),
+            State::ScriptDataEscaped => self.step_script_data_escaped(),
+            State::ScriptDataEscapedDash => self.step_script_data_escaped_dash(),
+            State::ScriptDataEscapedDashDash => self.step_script_data_escaped_dash_dash(),
+            State::ScriptDataEscapedLessThanSign => self.step_script_data_escaped_less_than_sign(),
+            State::ScriptDataEscapedEndTagOpen => self.step_text_end_tag_open(
+                State::ScriptDataEscapedEndTagName,
+                State::ScriptDataEscaped,
+            ),
+            State::ScriptDataEscapedEndTagName => {
+                self.step_text_end_tag_name(State::ScriptDataEscaped)
+            }
+            State::ScriptDataDoubleEscapeStart => self.step_script_data_double_escape_start(),
+            State::ScriptDataDoubleEscaped => self.step_script_data_double_escaped(),
+            State::ScriptDataDoubleEscapedDash => self.step_script_data_double_escaped_dash(),
+            State::ScriptDataDoubleEscapedDashDash => {
+                self.step_script_data_double_escaped_dash_dash()
+            }
+            State::ScriptDataDoubleEscapedLessThanSign => {
+                self.step_script_data_double_escaped_less_than_sign()
+            }
+            State::ScriptDataDoubleEscapeEnd => self.step_script_data_double_escape_end(),
+            State::BogusComment => self.step_bogus_comment(),
+            State::CommentStart => self.step_comment_start(),
+            State::CommentStartDash => self.step_comment_start_dash(),
+            State::Comment => self.step_comment(),
+            State::CommentLessThanSign => self.step_comment_less_than_sign(),
+            State::CommentLessThanSignBang => self.step_comment_less_than_sign_bang(),
+            State::CommentLessThanSignBangDash => self.step_comment_less_than_sign_bang_dash(),
+            State::CommentLessThanSignBangDashDash => {
+                self.step_comment_less_than_sign_bang_dash_dash()
+            }
+            State::CommentEndDash => self.step_comment_end_dash(),
+            State::CommentEnd => self.step_comment_end(),
+            State::CommentEndBang => self.step_comment_end_bang(),
+            State::CDataSection => self.step_cdata_section(),
+            State::CDataSectionBracket => self.step_cdata_section_bracket(),
+            State::CDataSectionEnd => self.step_cdata_section_end(),
         }
 
         Some(())
@@ -166,22 +368,14 @@ impl<'a> Tokenizer<'a> {
     fn step_self_closing_start_tag(&mut self) {
         match self.read() {
             Some('>') => {
-                let Some(Token::StartTag {
-                    ref mut self_closing_flag,
-                    ref mut attributes,
-                    ..
-                }) = self.temporary_token
-                else {
-                    panic!();
-                };
-                *self_closing_flag = true;
-                if let Some(attribute) = self.current_attribute.take() {
-                    attributes.insert(attribute.0, attribute.1);
+                match self.temporary_token {
+                    Some(Token::StartTag {
+                        ref mut self_closing_flag,
+                        ..
+                    }) => *self_closing_flag = true,
+                    _ => self.error(ParseError::EndTagWithTrailingSolidus),
                 }
-
-                self.switch_to(State::Data);
-                let temporary_token = self.temporary_token.take().unwrap();
-                self.emit(temporary_token);
+                self.emit_tag();
             }
             None => {
                 self.error(ParseError::EofInTag);
@@ -204,26 +398,15 @@ impl<'a> Tokenizer<'a> {
             Some('=') => {
                 self.switch_to(State::BeforeAttributeValue);
             }
-            Some('>') => {
-                self.switch_to(State::Data);
-                if let Some(current_attribute) = self.current_attribute.take() {
-                    let Some(Token::StartTag {
-                        ref mut attributes, ..
-                    }) = self.temporary_token
-                    else {
-                        panic!();
-                    };
-                    attributes.insert(current_attribute.0, current_attribute.1);
-                }
-                let temporary_token = self.temporary_token.take().unwrap();
-                self.emit(temporary_token);
-            }
+            Some('>') => self.emit_tag(),
             None => {
                 self.error(ParseError::EofInTag);
                 self.emit(Token::Eof);
             }
-            Some(c) => {
-                self.current_attribute = Some((String::from(c), String::new()));
+            c => {
+                self.start_new_attribute();
+                self.unread(c);
+                self.switch_to(State::AttributeName);
             }
         }
     }
@@ -236,20 +419,7 @@ impl<'a> Tokenizer<'a> {
             Some('/') => {
                 self.switch_to(State::SelfClosingStartTag);
             }
-            Some('>') => {
-                self.switch_to(State::Data);
-                if let Some(current_attribute) = self.current_attribute.take() {
-                    let Some(Token::StartTag {
-                        ref mut attributes, ..
-                    }) = self.temporary_token
-                    else {
-                        panic!();
-                    };
-                    attributes.insert(current_attribute.0, current_attribute.1);
-                }
-                let temporary_token = self.temporary_token.take().unwrap();
-                self.emit(temporary_token);
-            }
+            Some('>') => self.emit_tag(),
             None => {
                 self.error(ParseError::EofInTag);
                 self.emit(Token::Eof);
@@ -268,7 +438,7 @@ impl<'a> Tokenizer<'a> {
                 self.switch_to(State::AfterAttributeValueQuoted);
             }
             Some('&') => {
-                self.set_return_state(State::AttributeValueDoubleQuoted);
+                self.set_return_state(State::AttributeValueSingleQuoted);
                 self.switch_to(State::CharacterReference);
             }
             Some('\0') => {
@@ -317,20 +487,7 @@ impl<'a> Tokenizer<'a> {
                 self.set_return_state(State::AttributeValueUnquoted);
                 self.switch_to(State::CharacterReference);
             }
-            Some('>') => {
-                self.switch_to(State::Data);
-                if let Some(current_attribute) = self.current_attribute.take() {
-                    let Some(Token::StartTag {
-                        ref mut attributes, ..
-                    }) = self.temporary_token
-                    else {
-                        panic!();
-                    };
-                    attributes.insert(current_attribute.0, current_attribute.1);
-                }
-                let temporary_token = self.temporary_token.take().unwrap();
-                self.emit(temporary_token);
-            }
+            Some('>') => self.emit_tag(),
             Some('\0') => {
                 self.error(ParseError::UnexpectedNullCharacter);
                 self.current_attribute.as_mut().unwrap().1.push('\u{fffd}');
@@ -359,7 +516,7 @@ impl<'a> Tokenizer<'a> {
             }
             Some('>') => {
                 self.error(ParseError::MissingAttributeValue);
-                self.switch_to(State::Data);
+                self.emit_tag();
             }
             c => {
                 self.unread(c);
@@ -409,84 +566,60 @@ impl<'a> Tokenizer<'a> {
             }
             Some('=') => {
                 self.error(ParseError::UnexpectedEqualsSignBeforeAttributeName);
-                todo!()
+                self.start_new_attribute();
+                self.current_attribute.as_mut().unwrap().0.push('=');
+                self.switch_to(State::AttributeName);
             }
             c => {
-                if let Some(current_attribute) = self.current_attribute.take() {
-                    let Some(Token::StartTag { attributes, .. }) = &mut self.temporary_token else {
-                        panic!();
-                    };
-                    attributes.insert(current_attribute.0, current_attribute.1);
-                }
-                self.current_attribute = Some((String::new(), String::new()));
+                self.start_new_attribute();
                 self.unread(c);
                 self.switch_to(State::AttributeName);
             }
         }
     }
 
-    fn step_rcdata_end_tag_name(&mut self) {
-        match self.read() {
-            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', ' ', '/', '>'].contains(&c) => {
-                let Token::EndTag { name, .. } = self.temporary_token.as_ref().unwrap() else {
-                    panic!();
-                };
-                if Some(name) == self.appropriate_end_tag_name.as_ref() {
-                    match c {
-                        '/' => {
-                            self.switch_to(State::SelfClosingStartTag);
-                        }
-                        '>' => {
-                            let token = self.temporary_token.take().unwrap();
-                            self.emit(token);
-                            self.switch_to(State::Data);
-                        }
-                        _ => {
-                            self.switch_to(State::BeforeAttributeName);
-                        }
-                    }
+    fn start_new_attribute(&mut self) {
+        self.finish_attribute();
+        self.current_attribute = Some((String::new(), String::new()));
+    }
+
+    /// Adds the attribute being built to the current tag. An attribute with
+    /// the same name as an earlier one is dropped, and so are those of end
+    /// tags, which can't have attributes.
+    fn finish_attribute(&mut self) {
+        let Some((name, value)) = self.current_attribute.take() else {
+            return;
+        };
+        match self.temporary_token {
+            Some(Token::StartTag {
+                ref mut attributes, ..
+            }) => {
+                if attributes.contains(&name) {
+                    self.error(ParseError::DuplicateAttribute);
                 } else {
-                    self.emit(Token::Character('<'));
-                    self.emit(Token::Character('/'));
-                    self.flush();
-                    self.unread(Some(c));
-                    self.switch_to(State::RcData);
+                    attributes.push(Attribute::new(name, value));
                 }
             }
-            Some(mut c) if c.is_ascii_alphabetic() => {
-                self.temporary_buffer.push(c);
-                c.make_ascii_lowercase();
-                let Some(Token::EndTag { ref mut name }) = self.temporary_token else {
-                    panic!();
-                };
-                name.push(c);
-            }
-            c => {
-                self.emit(Token::Character('<'));
-                self.emit(Token::Character('/'));
-                self.flush();
-                self.unread(c);
-                self.switch_to(State::RcData);
-            }
+            _ => self.end_tag_has_attributes = true,
         }
     }
 
-    fn step_rcdata_end_tag_open(&mut self) {
-        match self.read() {
-            Some(c) if c.is_ascii_alphabetic() => {
-                self.temporary_token = Some(Token::EndTag {
-                    name: String::new(),
-                });
-                self.unread(Some(c));
-                self.switch_to(State::RcDataEndTagName);
-            }
-            c => {
-                self.emit(Token::Character('<'));
-                self.emit(Token::Character('/'));
-                self.unread(c);
-                self.switch_to(State::RcData);
-            }
+    /// Emits the current tag token and goes back to the data state.
+    fn emit_tag(&mut self) {
+        self.finish_attribute();
+        if mem::take(&mut self.end_tag_has_attributes) {
+            self.error(ParseError::EndTagWithAttributes);
         }
+        self.switch_to(State::Data);
+        self.emit_temporary_token();
+    }
+
+    fn step_rcdata_end_tag_name(&mut self) {
+        self.step_text_end_tag_name(State::RcData);
+    }
+
+    fn step_rcdata_end_tag_open(&mut self) {
+        self.step_text_end_tag_open(State::RcDataEndTagName, State::RcData);
     }
 
     fn step_rcdata_less_than_sign(&mut self) {
@@ -526,51 +659,78 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn step_raw_text_end_tag_name(&mut self) {
+        self.step_text_end_tag_name(State::RawText);
+    }
+
+    fn step_raw_text_end_tag_open(&mut self) {
+        self.step_text_end_tag_open(State::RawTextEndTagName, State::RawText);
+    }
+
+    /// The end tag open states of RCDATA, RAWTEXT and script data, which
+    /// fall back to `text_state` when no tag name follows.
+    fn step_text_end_tag_open(&mut self, name_state: State, text_state: State) {
         match self.read() {
-            Some(c)
-                if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c)
-                    && Some(&self.temporary_buffer) == self.appropriate_end_tag_name.as_ref() =>
-            {
-                self.switch_to(State::BeforeAttributeName);
-            }
-            Some('/') if Some(&self.temporary_buffer) == self.appropriate_end_tag_name.as_ref() => {
-                self.switch_to(State::SelfClosingStartTag);
-            }
-            Some('>') if Some(&self.temporary_buffer) == self.appropriate_end_tag_name.as_ref() => {
-                self.switch_to(State::Data);
-                let name = mem::take(&mut self.temporary_buffer);
-                self.emit(Token::EndTag { name });
-                self.appropriate_end_tag_name = None;
-                self.switch_to(State::Data);
-            }
-            Some(mut c) if c.is_ascii_alphabetic() => {
-                c.make_ascii_lowercase();
-                self.temporary_buffer.push(c);
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.temporary_token = Some(Token::EndTag {
+                    name: String::new(),
+                });
+                self.unread(Some(c));
+                self.switch_to(name_state);
             }
             c => {
                 self.emit(Token::Character('<'));
                 self.emit(Token::Character('/'));
-                self.flush();
                 self.unread(c);
-                self.switch_to(State::RawText);
+                self.switch_to(text_state);
             }
         }
     }
 
-    fn step_raw_text_end_tag_open(&mut self) {
+    /// The end tag name states of RCDATA, RAWTEXT and script data. Only an
+    /// appropriate end tag ends the text; anything else goes back to
+    /// `text_state` as characters.
+    fn step_text_end_tag_name(&mut self, text_state: State) {
         match self.read() {
-            Some(c) if c.is_ascii_alphabetic() => {
-                self.temporary_token = Some(Token::EndTag {
-                    name: String::new(),
-                });
-                self.unread(Some(c));
-                self.switch_to(State::RawTextEndTagName);
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', ' ', '/', '>'].contains(&c) => {
+                let Token::EndTag { name, .. } = self.temporary_token.as_ref().unwrap() else {
+                    panic!();
+                };
+                if Some(name) == self.appropriate_end_tag_name.as_ref() {
+                    match c {
+                        '/' => {
+                            self.switch_to(State::SelfClosingStartTag);
+                        }
+                        '>' => {
+                            let token = self.temporary_token.take().unwrap();
+                            self.switch_to(State::Data);
+                            self.emit(token);
+                        }
+                        _ => {
+                            self.switch_to(State::BeforeAttributeName);
+                        }
+                    }
+                } else {
+                    self.emit(Token::Character('<'));
+                    self.emit(Token::Character('/'));
+                    self.flush();
+                    self.unread(Some(c));
+                    self.switch_to(text_state);
+                }
+            }
+            Some(mut c) if c.is_ascii_alphabetic() => {
+                self.temporary_buffer.push(c);
+                c.make_ascii_lowercase();
+                let Some(Token::EndTag { ref mut name }) = self.temporary_token else {
+                    panic!();
+                };
+                name.push(c);
             }
             c => {
                 self.emit(Token::Character('<'));
                 self.emit(Token::Character('/'));
+                self.flush();
                 self.unread(c);
-                self.switch_to(State::RawText);
+                self.switch_to(text_state);
             }
         }
     }
@@ -589,6 +749,21 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    fn step_plaintext(&mut self) {
+        match self.read() {
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => {
+                self.emit(Token::Eof);
+            }
+            Some(c) => {
+                self.emit(Token::Character(c));
+            }
+        }
+    }
+
     fn step_raw_text(&mut self) {
         match self.read() {
             Some('<') => {
@@ -607,56 +782,258 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
-    fn step_doctype_name(&mut self) {
-        match self.read() {
+    fn doctype_id(&mut self, id: DoctypeId) -> &mut Option<String> {
+        let Some(Token::Doctype {
+            ref mut public_id,
+            ref mut system_id,
+            ..
+        }) = self.temporary_token
+        else {
+            panic!();
+        };
+        match id {
+            DoctypeId::Public => public_id,
+            DoctypeId::System => system_id,
+        }
+    }
+
+    fn set_force_quirks(&mut self) {
+        let Some(Token::Doctype {
+            ref mut force_quirks,
+            ..
+        }) = self.temporary_token
+        else {
+            panic!();
+        };
+        *force_quirks = true;
+    }
+
+    fn emit_doctype_and_eof(&mut self) {
+        self.error(ParseError::EofInDoctype);
+        self.set_force_quirks();
+        self.emit_temporary_token();
+        self.emit(Token::Eof);
+    }
+
+    fn step_after_doctype_name(&mut self) {
+        const PUBLIC: &str = "PUBLIC";
+        const SYSTEM: &str = "SYSTEM";
+
+        match self.look() {
             Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => {
-                self.switch_to(State::AfterDoctypeName)
+                self.read();
             }
-            Some('\u{003e}') => {
+            Some('>') => {
+                self.read();
                 self.switch_to(State::Data);
                 self.emit_temporary_token();
             }
-            Some('\0') => {
-                self.error(ParseError::UnexpectedNullCharacter);
-                let Some(Token::Doctype { ref mut name, .. }) = self.temporary_token else {
-                    panic!();
-                };
-                if name.is_none() {
-                    *name = Some(String::new());
+            None => self.emit_doctype_and_eof(),
+            Some(_) => {
+                if let Some(s) = self.look_str(PUBLIC.len())
+                    && s.eq_ignore_ascii_case(PUBLIC)
+                {
+                    self.read_str(PUBLIC.len());
+                    self.switch_to(State::AfterDoctypePublicKeyword);
+                } else if let Some(s) = self.look_str(SYSTEM.len())
+                    && s.eq_ignore_ascii_case(SYSTEM)
+                {
+                    self.read_str(SYSTEM.len());
+                    self.switch_to(State::AfterDoctypeSystemKeyword);
+                } else {
+                    self.error(ParseError::InvalidCharacterSequenceAfterDoctypeName);
+                    self.set_force_quirks();
+                    self.switch_to(State::BogusDoctype);
                 }
-                name.as_mut().unwrap().push('\u{fffd}');
             }
-            None => {
-                self.error(ParseError::EofInDoctype);
-                let Some(Token::Doctype {
-                    ref mut force_quirks,
-                    ..
-                }) = self.temporary_token
-                else {
-                    panic!();
-                };
-                *force_quirks = true;
+        }
+    }
+
+    /// The after DOCTYPE public and system keyword states.
+    fn step_after_doctype_keyword(&mut self, id: DoctypeId) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => {
+                self.switch_to(id.before_identifier_state());
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.error(id.missing_whitespace_after_keyword());
+                *self.doctype_id(id) = Some(String::new());
+                self.switch_to(id.quoted_state(quote));
+            }
+            Some('>') => {
+                self.error(id.missing_identifier());
+                self.set_force_quirks();
+                self.switch_to(State::Data);
                 self.emit_temporary_token();
-                self.emit(Token::Eof);
             }
-            Some(mut c) => {
-                c.make_ascii_lowercase();
-                let Some(Token::Doctype { ref mut name, .. }) = self.temporary_token else {
-                    panic!();
-                };
-                if name.is_none() {
-                    *name = Some(String::new());
-                }
-                name.as_mut().unwrap().push(c);
+            None => self.emit_doctype_and_eof(),
+            c => {
+                self.error(id.missing_quote_before_identifier());
+                self.set_force_quirks();
+                self.unread(c);
+                self.switch_to(State::BogusDoctype);
             }
         }
     }
 
-    fn step_before_doctype_name(&mut self) {
+    /// The before DOCTYPE public and system identifier states.
+    fn step_before_doctype_identifier(&mut self, id: DoctypeId) {
         match self.read() {
             Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => (),
-            Some('\0') => {
-                self.error(ParseError::UnexpectedNullCharacter);
+            Some(quote @ ('"' | '\'')) => {
+                *self.doctype_id(id) = Some(String::new());
+                self.switch_to(id.quoted_state(quote));
+            }
+            Some('>') => {
+                self.error(id.missing_identifier());
+                self.set_force_quirks();
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            None => self.emit_doctype_and_eof(),
+            c => {
+                self.error(id.missing_quote_before_identifier());
+                self.set_force_quirks();
+                self.unread(c);
+                self.switch_to(State::BogusDoctype);
+            }
+        }
+    }
+
+    /// The DOCTYPE public and system identifier (double- and single-quoted)
+    /// states.
+    fn step_doctype_identifier_quoted(&mut self, id: DoctypeId, quote: char) {
+        match self.read() {
+            Some(c) if c == quote => self.switch_to(id.after_identifier_state()),
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.doctype_id(id).as_mut().unwrap().push('\u{fffd}');
+            }
+            Some('>') => {
+                self.error(id.abrupt_identifier());
+                self.set_force_quirks();
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            None => self.emit_doctype_and_eof(),
+            Some(c) => self.doctype_id(id).as_mut().unwrap().push(c),
+        }
+    }
+
+    fn step_after_doctype_public_identifier(&mut self) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => {
+                self.switch_to(State::BetweenDoctypePublicAndSystemIdentifiers);
+            }
+            Some('>') => {
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.error(ParseError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers);
+                *self.doctype_id(DoctypeId::System) = Some(String::new());
+                self.switch_to(DoctypeId::System.quoted_state(quote));
+            }
+            None => self.emit_doctype_and_eof(),
+            c => {
+                self.error(ParseError::MissingQuoteBeforeDoctypeSystemIdentifier);
+                self.set_force_quirks();
+                self.unread(c);
+                self.switch_to(State::BogusDoctype);
+            }
+        }
+    }
+
+    fn step_between_doctype_public_and_system_identifiers(&mut self) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => (),
+            Some('>') => {
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            Some(quote @ ('"' | '\'')) => {
+                *self.doctype_id(DoctypeId::System) = Some(String::new());
+                self.switch_to(DoctypeId::System.quoted_state(quote));
+            }
+            None => self.emit_doctype_and_eof(),
+            c => {
+                self.error(ParseError::MissingQuoteBeforeDoctypeSystemIdentifier);
+                self.set_force_quirks();
+                self.unread(c);
+                self.switch_to(State::BogusDoctype);
+            }
+        }
+    }
+
+    fn step_after_doctype_system_identifier(&mut self) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => (),
+            Some('>') => {
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            None => self.emit_doctype_and_eof(),
+            c => {
+                self.error(ParseError::UnexpectedCharacterAfterDoctypeSystemIdentifier);
+                self.unread(c);
+                self.switch_to(State::BogusDoctype);
+            }
+        }
+    }
+
+    fn step_bogus_doctype(&mut self) {
+        match self.read() {
+            Some('>') => {
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            Some('\0') => self.error(ParseError::UnexpectedNullCharacter),
+            None => {
+                self.emit_temporary_token();
+                self.emit(Token::Eof);
+            }
+            Some(_) => (),
+        }
+    }
+
+    fn step_doctype_name(&mut self) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => {
+                self.switch_to(State::AfterDoctypeName)
+            }
+            Some('\u{003e}') => {
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                let Some(Token::Doctype { ref mut name, .. }) = self.temporary_token else {
+                    panic!();
+                };
+                if name.is_none() {
+                    *name = Some(String::new());
+                }
+                name.as_mut().unwrap().push('\u{fffd}');
+            }
+            None => self.emit_doctype_and_eof(),
+            Some(mut c) => {
+                c.make_ascii_lowercase();
+                let Some(Token::Doctype { ref mut name, .. }) = self.temporary_token else {
+                    panic!();
+                };
+                if name.is_none() {
+                    *name = Some(String::new());
+                }
+                name.as_mut().unwrap().push(c);
+            }
+        }
+    }
+
+    fn step_before_doctype_name(&mut self) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => (),
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
                 self.temporary_token = Some(Token::Doctype {
                     name: Some("\u{fffd}".to_string()),
                     public_id: None,
@@ -666,61 +1043,510 @@ impl<'a> Tokenizer<'a> {
                 self.switch_to(State::DoctypeName);
             }
             Some('>') => {
-                self.error(ParseError::MissingDoctypeName);
-                self.emit(Token::Doctype {
-                    name: Some(String::new()),
-                    public_id: None,
-                    system_id: None,
-                    force_quirks: true,
-                });
+                self.error(ParseError::MissingDoctypeName);
+                self.switch_to(State::Data);
+                self.emit(Token::Doctype {
+                    name: None,
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                });
+            }
+            None => {
+                self.error(ParseError::EofInDoctype);
+                self.emit(Token::Doctype {
+                    name: None,
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                });
+                self.emit(Token::Eof);
+            }
+            Some(mut c) => {
+                c.make_ascii_lowercase();
+                self.temporary_token = Some(Token::Doctype {
+                    name: Some(format!("{}", c)),
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: false,
+                });
+                self.switch_to(State::DoctypeName);
+            }
+        }
+    }
+
+    fn step_doctype(&mut self) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => {
+                self.switch_to(State::BeforeDoctypeName)
+            }
+            Some('>') => {
+                self.unread(Some('>'));
+                self.switch_to(State::BeforeDoctypeName);
+            }
+            None => {
+                self.error(ParseError::EofInDoctype);
+                self.emit(Token::Doctype {
+                    name: None,
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                });
+                self.emit(Token::Eof);
+            }
+            Some(c) => {
+                self.error(ParseError::MissingWhitespaceBeforeDoctypeName);
+                self.unread(Some(c));
+                self.switch_to(State::BeforeDoctypeName);
+            }
+        }
+    }
+
+    fn step_script_data(&mut self) {
+        match self.read() {
+            Some('<') => self.switch_to(State::ScriptDataLessThanSign),
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.emit(Token::Eof),
+            Some(c) => self.emit(Token::Character(c)),
+        }
+    }
+
+    fn step_script_data_less_than_sign(&mut self) {
+        match self.read() {
+            Some('/') => {
+                self.temporary_buffer.clear();
+                self.switch_to(State::ScriptDataEndTagOpen);
+            }
+            Some('!') => {
+                self.switch_to(State::ScriptDataEscapeStart);
+                self.emit(Token::Character('<'));
+                self.emit(Token::Character('!'));
+            }
+            c => {
+                self.emit(Token::Character('<'));
+                self.unread(c);
+                self.switch_to(State::ScriptData);
+            }
+        }
+    }
+
+    fn step_script_data_escape_start(&mut self) {
+        match self.read() {
+            Some('-') => {
+                self.switch_to(State::ScriptDataEscapeStartDash);
+                self.emit(Token::Character('-'));
+            }
+            c => {
+                self.unread(c);
+                self.switch_to(State::ScriptData);
+            }
+        }
+    }
+
+    fn step_script_data_escape_start_dash(&mut self) {
+        match self.read() {
+            Some('-') => {
+                self.switch_to(State::ScriptDataEscapedDashDash);
+                self.emit(Token::Character('-'));
+            }
+            c => {
+                self.unread(c);
+                self.switch_to(State::ScriptData);
+            }
+        }
+    }
+
+    fn eof_in_script_comment(&mut self) {
+        self.error(ParseError::EofInScriptHtmlCommentLikeText);
+        self.emit(Token::Eof);
+    }
+
+    fn step_script_data_escaped(&mut self) {
+        match self.read() {
+            Some('-') => {
+                self.switch_to(State::ScriptDataEscapedDash);
+                self.emit(Token::Character('-'));
+            }
+            Some('<') => self.switch_to(State::ScriptDataEscapedLessThanSign),
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.eof_in_script_comment(),
+            Some(c) => self.emit(Token::Character(c)),
+        }
+    }
+
+    fn step_script_data_escaped_dash(&mut self) {
+        match self.read() {
+            Some('-') => {
+                self.switch_to(State::ScriptDataEscapedDashDash);
+                self.emit(Token::Character('-'));
+            }
+            Some('<') => self.switch_to(State::ScriptDataEscapedLessThanSign),
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.switch_to(State::ScriptDataEscaped);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.eof_in_script_comment(),
+            Some(c) => {
+                self.switch_to(State::ScriptDataEscaped);
+                self.emit(Token::Character(c));
+            }
+        }
+    }
+
+    fn step_script_data_escaped_dash_dash(&mut self) {
+        match self.read() {
+            Some('-') => self.emit(Token::Character('-')),
+            Some('<') => self.switch_to(State::ScriptDataEscapedLessThanSign),
+            Some('>') => {
+                self.switch_to(State::ScriptData);
+                self.emit(Token::Character('>'));
+            }
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.switch_to(State::ScriptDataEscaped);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.eof_in_script_comment(),
+            Some(c) => {
+                self.switch_to(State::ScriptDataEscaped);
+                self.emit(Token::Character(c));
+            }
+        }
+    }
+
+    fn step_script_data_escaped_less_than_sign(&mut self) {
+        match self.read() {
+            Some('/') => {
+                self.temporary_buffer.clear();
+                self.switch_to(State::ScriptDataEscapedEndTagOpen);
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.temporary_buffer.clear();
+                self.emit(Token::Character('<'));
+                self.unread(Some(c));
+                self.switch_to(State::ScriptDataDoubleEscapeStart);
+            }
+            c => {
+                self.emit(Token::Character('<'));
+                self.unread(c);
+                self.switch_to(State::ScriptDataEscaped);
+            }
+        }
+    }
+
+    /// The double escape start and end states, which look for `script` to
+    /// enter or leave the double escaped states.
+    fn step_script_data_double_escape(&mut self, script_state: State, other_state: State) {
+        match self.read() {
+            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', ' ', '/', '>'].contains(&c) => {
+                if self.temporary_buffer == "script" {
+                    self.switch_to(script_state);
+                } else {
+                    self.switch_to(other_state);
+                }
+                self.emit(Token::Character(c));
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.temporary_buffer.push(c.to_ascii_lowercase());
+                self.emit(Token::Character(c));
+            }
+            c => {
+                self.unread(c);
+                self.switch_to(other_state);
+            }
+        }
+    }
+
+    fn step_script_data_double_escape_start(&mut self) {
+        self.step_script_data_double_escape(
+            State::ScriptDataDoubleEscaped,
+            State::ScriptDataEscaped,
+        );
+    }
+
+    fn step_script_data_double_escaped(&mut self) {
+        match self.read() {
+            Some('-') => {
+                self.switch_to(State::ScriptDataDoubleEscapedDash);
+                self.emit(Token::Character('-'));
+            }
+            Some('<') => {
+                self.switch_to(State::ScriptDataDoubleEscapedLessThanSign);
+                self.emit(Token::Character('<'));
+            }
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.eof_in_script_comment(),
+            Some(c) => self.emit(Token::Character(c)),
+        }
+    }
+
+    fn step_script_data_double_escaped_dash(&mut self) {
+        match self.read() {
+            Some('-') => {
+                self.switch_to(State::ScriptDataDoubleEscapedDashDash);
+                self.emit(Token::Character('-'));
+            }
+            Some('<') => {
+                self.switch_to(State::ScriptDataDoubleEscapedLessThanSign);
+                self.emit(Token::Character('<'));
+            }
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.switch_to(State::ScriptDataDoubleEscaped);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.eof_in_script_comment(),
+            Some(c) => {
+                self.switch_to(State::ScriptDataDoubleEscaped);
+                self.emit(Token::Character(c));
+            }
+        }
+    }
+
+    fn step_script_data_double_escaped_dash_dash(&mut self) {
+        match self.read() {
+            Some('-') => self.emit(Token::Character('-')),
+            Some('<') => {
+                self.switch_to(State::ScriptDataDoubleEscapedLessThanSign);
+                self.emit(Token::Character('<'));
+            }
+            Some('>') => {
+                self.switch_to(State::ScriptData);
+                self.emit(Token::Character('>'));
+            }
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.switch_to(State::ScriptDataDoubleEscaped);
+                self.emit(Token::Character('\u{fffd}'));
+            }
+            None => self.eof_in_script_comment(),
+            Some(c) => {
+                self.switch_to(State::ScriptDataDoubleEscaped);
+                self.emit(Token::Character(c));
+            }
+        }
+    }
+
+    fn step_script_data_double_escaped_less_than_sign(&mut self) {
+        match self.read() {
+            Some('/') => {
+                self.temporary_buffer.clear();
+                self.switch_to(State::ScriptDataDoubleEscapeEnd);
+                self.emit(Token::Character('/'));
+            }
+            c => {
+                self.unread(c);
+                self.switch_to(State::ScriptDataDoubleEscaped);
+            }
+        }
+    }
+
+    fn step_script_data_double_escape_end(&mut self) {
+        self.step_script_data_double_escape(
+            State::ScriptDataEscaped,
+            State::ScriptDataDoubleEscaped,
+        );
+    }
+
+    fn step_bogus_comment(&mut self) {
+        match self.read() {
+            Some('>') => {
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            None => {
+                self.emit_temporary_token();
+                self.emit(Token::Eof);
+            }
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.comment().push('\u{fffd}');
+            }
+            Some(c) => self.comment().push(c),
+        }
+    }
+
+    fn step_comment_start(&mut self) {
+        match self.read() {
+            Some('-') => self.switch_to(State::CommentStartDash),
+            Some('>') => {
+                self.error(ParseError::AbruptClosingOfEmptyComment);
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            c => {
+                self.unread(c);
+                self.switch_to(State::Comment);
+            }
+        }
+    }
+
+    fn step_comment_start_dash(&mut self) {
+        match self.read() {
+            Some('-') => self.switch_to(State::CommentEnd),
+            Some('>') => {
+                self.error(ParseError::AbruptClosingOfEmptyComment);
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            None => self.emit_comment_and_eof(),
+            c => {
+                self.comment().push('-');
+                self.unread(c);
+                self.switch_to(State::Comment);
+            }
+        }
+    }
+
+    fn step_comment(&mut self) {
+        match self.read() {
+            Some('<') => {
+                self.comment().push('<');
+                self.switch_to(State::CommentLessThanSign);
+            }
+            Some('-') => self.switch_to(State::CommentEndDash),
+            Some('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.comment().push('\u{fffd}');
+            }
+            None => self.emit_comment_and_eof(),
+            Some(c) => self.comment().push(c),
+        }
+    }
+
+    fn step_comment_less_than_sign(&mut self) {
+        match self.read() {
+            Some('!') => {
+                self.comment().push('!');
+                self.switch_to(State::CommentLessThanSignBang);
+            }
+            Some('<') => self.comment().push('<'),
+            c => {
+                self.unread(c);
+                self.switch_to(State::Comment);
+            }
+        }
+    }
+
+    fn step_comment_less_than_sign_bang(&mut self) {
+        match self.read() {
+            Some('-') => self.switch_to(State::CommentLessThanSignBangDash),
+            c => {
+                self.unread(c);
+                self.switch_to(State::Comment);
+            }
+        }
+    }
+
+    fn step_comment_less_than_sign_bang_dash(&mut self) {
+        match self.read() {
+            Some('-') => self.switch_to(State::CommentLessThanSignBangDashDash),
+            c => {
+                self.unread(c);
+                self.switch_to(State::CommentEndDash);
+            }
+        }
+    }
+
+    fn step_comment_less_than_sign_bang_dash_dash(&mut self) {
+        let c = self.read();
+        if !matches!(c, Some('>') | None) {
+            self.error(ParseError::NestedComment);
+        }
+        self.unread(c);
+        self.switch_to(State::CommentEnd);
+    }
+
+    fn step_comment_end_dash(&mut self) {
+        match self.read() {
+            Some('-') => self.switch_to(State::CommentEnd),
+            None => self.emit_comment_and_eof(),
+            c => {
+                self.comment().push('-');
+                self.unread(c);
+                self.switch_to(State::Comment);
+            }
+        }
+    }
+
+    fn step_comment_end(&mut self) {
+        match self.read() {
+            Some('>') => {
                 self.switch_to(State::Data);
+                self.emit_temporary_token();
             }
-            None => {
-                self.error(ParseError::EofInDoctype);
-                self.emit(Token::Doctype {
-                    name: Some(String::new()),
-                    public_id: None,
-                    system_id: None,
-                    force_quirks: true,
-                });
-                self.emit(Token::Eof);
-            }
-            Some(mut c) => {
-                c.make_ascii_lowercase();
-                self.temporary_token = Some(Token::Doctype {
-                    name: Some(format!("{}", c)),
-                    public_id: None,
-                    system_id: None,
-                    force_quirks: false,
-                });
-                self.switch_to(State::DoctypeName);
+            Some('!') => self.switch_to(State::CommentEndBang),
+            Some('-') => self.comment().push('-'),
+            None => self.emit_comment_and_eof(),
+            c => {
+                self.comment().push_str("--");
+                self.unread(c);
+                self.switch_to(State::Comment);
             }
         }
     }
 
-    fn step_doctype(&mut self) {
+    fn step_comment_end_bang(&mut self) {
         match self.read() {
-            Some(c) if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{0020}'].contains(&c) => {
-                self.switch_to(State::BeforeDoctypeName)
+            Some('-') => {
+                self.comment().push_str("--!");
+                self.switch_to(State::CommentEndDash);
             }
             Some('>') => {
-                self.unread(Some('>'));
-                self.switch_to(State::BeforeDoctypeName);
+                self.error(ParseError::IncorrectlyClosedComment);
+                self.switch_to(State::Data);
+                self.emit_temporary_token();
+            }
+            None => self.emit_comment_and_eof(),
+            c => {
+                self.comment().push_str("--!");
+                self.unread(c);
+                self.switch_to(State::Comment);
             }
+        }
+    }
+
+    fn step_cdata_section(&mut self) {
+        match self.read() {
+            Some(']') => self.switch_to(State::CDataSectionBracket),
             None => {
-                self.error(ParseError::EofInDoctype);
-                self.emit(Token::Doctype {
-                    name: None,
-                    public_id: None,
-                    system_id: None,
-                    force_quirks: true,
-                });
+                self.error(ParseError::EofInCdata);
                 self.emit(Token::Eof);
             }
-            Some(c) => {
-                self.error(ParseError::MissingWhiteSpaceBeforeDoctypeName);
-                self.unread(Some(c));
-                self.switch_to(State::BeforeDoctypeName);
+            Some(c) => self.emit(Token::Character(c)),
+        }
+    }
+
+    fn step_cdata_section_bracket(&mut self) {
+        match self.read() {
+            Some(']') => self.switch_to(State::CDataSectionEnd),
+            c => {
+                self.emit(Token::Character(']'));
+                self.unread(c);
+                self.switch_to(State::CDataSection);
+            }
+        }
+    }
+
+    fn step_cdata_section_end(&mut self) {
+        match self.read() {
+            Some(']') => self.emit(Token::Character(']')),
+            Some('>') => self.switch_to(State::Data),
+            c => {
+                self.emit(Token::Character(']'));
+                self.emit(Token::Character(']'));
+                self.unread(c);
+                self.switch_to(State::CDataSection);
             }
         }
     }
@@ -742,7 +1568,7 @@ impl<'a> Tokenizer<'a> {
             self.switch_to(State::Doctype);
         } else if self.look_str(CDATA.len()) == Some(CDATA) {
             self.read_str(CDATA.len());
-            if self.adjusted_current_node_namespace() == Namespace::Html {
+            if self.adjusted_current_node_namespace() != Namespace::Html {
                 self.switch_to(State::CDataSection);
             } else {
                 self.error(ParseError::CDataInHtmlContent);
@@ -883,13 +1709,313 @@ impl<'a> Tokenizer<'a> {
             }
             c => {
                 self.unread(c);
+                self.flush();
+                self.return_state();
+            }
+        }
+    }
+
+    fn step_named_character_reference(&mut self) {
+        let Some((name, value)) = longest_character_reference(self.input.remaining()) else {
+            self.flush();
+            self.switch_to(State::AmbiguousAmpersand);
+            return;
+        };
+
+        self.input.advance(name.len());
+        self.temporary_buffer.push_str(name);
+        let next = self.look();
+        if self.is_consumed_as_part_of_an_attribute()
+            && !name.ends_with(';')
+            && next.is_some_and(|c| c == '=' || c.is_ascii_alphanumeric())
+        {
+            self.flush();
+            self.return_state();
+            return;
+        }
+
+        if !name.ends_with(';') {
+            self.error(ParseError::MissingSemicolonAfterCharacterReference);
+        }
+        self.temporary_buffer.clear();
+        self.temporary_buffer.push_str(value);
+        self.flush();
+        self.return_state();
+    }
+
+    fn step_ambiguous_ampersand(&mut self) {
+        match self.read() {
+            Some(c) if c.is_ascii_alphanumeric() => {
+                if self.is_consumed_as_part_of_an_attribute() {
+                    self.current_attribute.as_mut().unwrap().1.push(c);
+                } else {
+                    self.emit(Token::Character(c));
+                }
+            }
+            c => {
+                if c == Some(';') {
+                    self.error(ParseError::UnknownNamedCharacterReference);
+                }
+                self.unread(c);
+                self.return_state();
+            }
+        }
+    }
+
+    fn step_numeric_character_reference(&mut self) {
+        self.character_reference_code = 0;
+
+        match self.read() {
+            Some(c @ ('x' | 'X')) => {
+                self.temporary_buffer.push(c);
+                self.switch_to(State::HexadecimalCharacterReferenceStart);
+            }
+            c => {
+                self.unread(c);
+                self.switch_to(State::DecimalCharacterReferenceStart);
+            }
+        }
+    }
+
+    fn step_hexadecimal_character_reference_start(&mut self) {
+        match self.read() {
+            Some(c) if c.is_ascii_hexdigit() => {
+                self.unread(Some(c));
+                self.switch_to(State::HexadecimalCharacterReference);
+            }
+            c => {
+                self.error(ParseError::AbsenceOfDigitsInNumericCharacterReference);
+                self.unread(c);
+                self.flush();
                 self.return_state();
+            }
+        }
+    }
+
+    fn step_decimal_character_reference_start(&mut self) {
+        match self.read() {
+            Some(c) if c.is_ascii_digit() => {
+                self.unread(Some(c));
+                self.switch_to(State::DecimalCharacterReference);
+            }
+            c => {
+                self.error(ParseError::AbsenceOfDigitsInNumericCharacterReference);
+                self.unread(c);
                 self.flush();
+                self.return_state();
+            }
+        }
+    }
+
+    fn step_hexadecimal_character_reference(&mut self) {
+        match self.read() {
+            Some(c) if c.is_ascii_hexdigit() => {
+                self.push_character_reference_digit(16, c);
+            }
+            Some(';') => {
+                self.switch_to(State::NumericCharacterReferenceEnd);
+            }
+            c => {
+                self.error(ParseError::MissingSemicolonAfterCharacterReference);
+                self.unread(c);
+                self.switch_to(State::NumericCharacterReferenceEnd);
+            }
+        }
+    }
+
+    fn step_decimal_character_reference(&mut self) {
+        match self.read() {
+            Some(c) if c.is_ascii_digit() => {
+                self.push_character_reference_digit(10, c);
+            }
+            Some(';') => {
+                self.switch_to(State::NumericCharacterReferenceEnd);
+            }
+            c => {
+                self.error(ParseError::MissingSemicolonAfterCharacterReference);
+                self.unread(c);
+                self.switch_to(State::NumericCharacterReferenceEnd);
+            }
+        }
+    }
+
+    /// Saturates just above the Unicode range, which is all the end state
+    /// needs to know about an overlong reference.
+    fn push_character_reference_digit(&mut self, radix: u32, c: char) {
+        self.character_reference_code =
+            (self.character_reference_code * radix + c.to_digit(radix).unwrap()).min(0x110000);
+    }
+
+    fn step_numeric_character_reference_end(&mut self) {
+        let code = self.character_reference_code;
+        let c = match code {
+            0 => {
+                self.error(ParseError::NullCharacterReference);
+                '\u{fffd}'
+            }
+            0x110000.. => {
+                self.error(ParseError::CharacterReferenceOutsideUnicodeRange);
+                '\u{fffd}'
+            }
+            0xd800..=0xdfff => {
+                self.error(ParseError::SurrogateCharacterReference);
+                '\u{fffd}'
+            }
+            _ => {
+                if is_noncharacter(code) {
+                    self.error(ParseError::NoncharacterCharacterReference);
+                }
+                if code == 0x0d || (is_control(code) && !matches!(code, 0x09 | 0x0a | 0x0c | 0x20))
+                {
+                    self.error(ParseError::ControlCharacterReference);
+                }
+                match code {
+                    0x80..=0x9f => C1_REPLACEMENTS[code as usize - 0x80],
+                    _ => None,
+                }
+                .or(char::from_u32(code))
+                .unwrap()
             }
+        };
+
+        self.temporary_buffer.clear();
+        self.temporary_buffer.push(c);
+        self.flush();
+        self.return_state();
+    }
+}
+
+/// Which of a DOCTYPE's identifiers a state works on. The public and system
+/// identifier states mirror each other apart from their errors.
+#[derive(Clone, Copy)]
+enum DoctypeId {
+    Public,
+    System,
+}
+
+impl DoctypeId {
+    fn before_identifier_state(self) -> State {
+        match self {
+            Self::Public => State::BeforeDoctypePublicIdentifier,
+            Self::System => State::BeforeDoctypeSystemIdentifier,
+        }
+    }
+
+    fn quoted_state(self, quote: char) -> State {
+        match (self, quote) {
+            (Self::Public, '"') => State::DoctypePublicIdentifierDoubleQuoted,
+            (Self::Public, _) => State::DoctypePublicIdentifierSingleQuoted,
+            (Self::System, '"') => State::DoctypeSystemIdentifierDoubleQuoted,
+            (Self::System, _) => State::DoctypeSystemIdentifierSingleQuoted,
+        }
+    }
+
+    fn after_identifier_state(self) -> State {
+        match self {
+            Self::Public => State::AfterDoctypePublicIdentifier,
+            Self::System => State::AfterDoctypeSystemIdentifier,
+        }
+    }
+
+    fn missing_whitespace_after_keyword(self) -> ParseError {
+        match self {
+            Self::Public => ParseError::MissingWhitespaceAfterDoctypePublicKeyword,
+            Self::System => ParseError::MissingWhitespaceAfterDoctypeSystemKeyword,
+        }
+    }
+
+    fn missing_identifier(self) -> ParseError {
+        match self {
+            Self::Public => ParseError::MissingDoctypePublicIdentifier,
+            Self::System => ParseError::MissingDoctypeSystemIdentifier,
+        }
+    }
+
+    fn missing_quote_before_identifier(self) -> ParseError {
+        match self {
+            Self::Public => ParseError::MissingQuoteBeforeDoctypePublicIdentifier,
+            Self::System => ParseError::MissingQuoteBeforeDoctypeSystemIdentifier,
+        }
+    }
+
+    fn abrupt_identifier(self) -> ParseError {
+        match self {
+            Self::Public => ParseError::AbruptDoctypePublicIdentifier,
+            Self::System => ParseError::AbruptDoctypeSystemIdentifier,
+        }
+    }
+}
+
+const LONGEST_CHARACTER_REFERENCE_NAME: usize = "CounterClockwiseContourIntegral;".len();
+
+/// Finds the longest name in the table that `input` starts with.
+fn longest_character_reference(input: &str) -> Option<(&'static str, &'static str)> {
+    let mut longest = None;
+    let mut table = &NAMED_CHARACTER_REFERENCES[..];
+
+    for (len, c) in input.char_indices().map(|(i, c)| (i + c.len_utf8(), c)) {
+        if !c.is_ascii() || LONGEST_CHARACTER_REFERENCE_NAME < len {
+            break;
+        }
+        let prefix = &input[..len];
+        let start = table.partition_point(|(name, _)| *name < prefix);
+        let end = table.partition_point(|(name, _)| name.starts_with(prefix) || *name < prefix);
+        table = &table[start..end];
+        match table.first() {
+            Some(&(name, value)) if name == prefix => longest = Some((name, value)),
+            Some(_) => (),
+            None => break,
         }
     }
+
+    longest
+}
+
+fn is_noncharacter(code: u32) -> bool {
+    (0xfdd0..=0xfdef).contains(&code) || code & 0xfffe == 0xfffe
+}
+
+fn is_control(code: u32) -> bool {
+    code <= 0x1f || (0x7f..=0x9f).contains(&code)
 }
 
+/// What numeric references to the C1 controls decode to, following
+/// windows-1252. `None` leaves the code point as it is.
+const C1_REPLACEMENTS: [Option<char>; 32] = [
+    Some('\u{20ac}'),
+    None,
+    Some('\u{201a}'),
+    Some('\u{0192}'),
+    Some('\u{201e}'),
+    Some('\u{2026}'),
+    Some('\u{2020}'),
+    Some('\u{2021}'),
+    Some('\u{02c6}'),
+    Some('\u{2030}'),
+    Some('\u{0160}'),
+    Some('\u{2039}'),
+    Some('\u{0152}'),
+    None,
+    Some('\u{017d}'),
+    None,
+    None,
+    Some('\u{2018}'),
+    Some('\u{2019}'),
+    Some('\u{201c}'),
+    Some('\u{201d}'),
+    Some('\u{2022}'),
+    Some('\u{2013}'),
+    Some('\u{2014}'),
+    Some('\u{02dc}'),
+    Some('\u{2122}'),
+    Some('\u{0161}'),
+    Some('\u{203a}'),
+    Some('\u{0153}'),
+    None,
+    Some('\u{017e}'),
+    Some('\u{0178}'),
+];
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ParseError {
     UnexpectedNullCharacter,
@@ -905,15 +2031,23 @@ pub enum ParseError {
     IncorrectlyOpenedComment,
     EofInDoctype,
     EofInText,
-    MissingWhiteSpaceBeforeDoctypeName,
+    MissingWhitespaceBeforeDoctypeName,
     MissingDoctypeName,
     UnexpectedHeadTag,
     UnclosedElementAtEof,
     UnclosedElement,
     UnexpectedStartTag,
     ElementNotFoundInButtonScope,
+    ElementNotFoundInTableScope,
+    ElementNotFoundInScope,
+    UnclosedFormattingElement,
     HtmlEndTagInFragmentParse,
     UnexpectedTokenInAfterAfterBody,
+    UnexpectedTokenInTable,
+    UnexpectedHtmlElementInForeignContent,
+    UnexpectedTokenInNoscript,
+    UnexpectedTokenInSelect,
+    UnexpectedTokenInFrameset,
     UnexpectedEqualsSignBeforeAttributeName,
     MissingWhitespaceBetweenAttributes,
     UnexpectedCharacterInUnquotedAttributeValue,
@@ -921,6 +2055,35 @@ pub enum ParseError {
     UnexpectedCharacterInAttributeValue,
     UnexpectedCharacterInAttributeName,
     UnexpectedSolidusInTag,
+    EndTagWithAttributes,
+    DuplicateAttribute,
+    EndTagWithTrailingSolidus,
+    MissingSemicolonAfterCharacterReference,
+    UnknownNamedCharacterReference,
+    AbsenceOfDigitsInNumericCharacterReference,
+    NullCharacterReference,
+    CharacterReferenceOutsideUnicodeRange,
+    SurrogateCharacterReference,
+    NoncharacterCharacterReference,
+    ControlCharacterReference,
+    AbruptClosingOfEmptyComment,
+    EofInComment,
+    NestedComment,
+    IncorrectlyClosedComment,
+    EofInCdata,
+    EofInScriptHtmlCommentLikeText,
+    InvalidCharacterSequenceAfterDoctypeName,
+    MissingWhitespaceAfterDoctypePublicKeyword,
+    MissingWhitespaceAfterDoctypeSystemKeyword,
+    MissingDoctypePublicIdentifier,
+    MissingDoctypeSystemIdentifier,
+    MissingQuoteBeforeDoctypePublicIdentifier,
+    MissingQuoteBeforeDoctypeSystemIdentifier,
+    AbruptDoctypePublicIdentifier,
+    AbruptDoctypeSystemIdentifier,
+    MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+    UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    NonConformingDoctype,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -943,6 +2106,7 @@ pub enum TokenizerState {
     ScriptDataEndTagOpen,
     ScriptDataEndTagName,
     ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
     ScriptDataEscaped,
     ScriptDataEscapedDash,
     ScriptDataEscapedDashDash,
@@ -962,7 +2126,6 @@ pub enum TokenizerState {
     AttributeValueDoubleQuoted,
     AttributeValueSingleQuoted,
     AttributeValueUnquoted,
-    AttributeValue,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
     BogusComment,
@@ -986,6 +2149,7 @@ pub enum TokenizerState {
     DoctypePublicIdentifierDoubleQuoted,
     DoctypePublicIdentifierSingleQuoted,
     AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
     AfterDoctypeSystemKeyword,
     BeforeDoctypeSystemIdentifier,
     DoctypeSystemIdentifierDoubleQuoted,
@@ -1020,7 +2184,7 @@ pub enum Token {
     },
     StartTag {
         name: String,
-        attributes: HashMap<String, String>,
+        attributes: Attributes,
         self_closing_flag: bool,
     },
     EndTag {
@@ -1035,8 +2199,144 @@ impl Token {
     pub fn new_start_tag() -> Self {
         Self::StartTag {
             name: String::new(),
-            attributes: HashMap::new(),
+            attributes: Attributes::new(),
             self_closing_flag: false,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::html::ByteStreamDecoder;
+    use crate::html::DomNodeType;
+
+    #[derive(Default)]
+    struct Parsed {
+        text: String,
+        titles: Vec<String>,
+        comments: Vec<String>,
+        errors: Vec<ParseError>,
+    }
+
+    /// Parses `body` in a standards-mode document and collects what the tests
+    /// look at.
+    fn parse(body: &str) -> Parsed {
+        let html = format!("<!DOCTYPE html>{}", body);
+        let preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(html.as_bytes())).unwrap();
+        let mut tree_constructor = TreeConstructor::new();
+        Tokenizer::new(preprocessor, &mut tree_constructor).run();
+
+        let mut parsed = Parsed::default();
+        for node in &tree_constructor.dom()[..] {
+            match node.node_type() {
+                DomNodeType::Text(s) => parsed.text.push_str(s),
+                DomNodeType::Element { attributes, .. } => {
+                    parsed
+                        .titles
+                        .extend(attributes.get("title").map(str::to_string));
+                }
+                DomNodeType::Comment(data) => parsed.comments.push(data.clone()),
+                _ => (),
+            }
+        }
+        parsed.errors = tree_constructor.errors().to_vec();
+        parsed
+    }
+
+    const HTML: &str = "<!DOCTYPE html>\r\n<html><head><title>t</title>\
+                        <style>p { color: red; }</style></head>\r\n\
+                        <body><p class=\"a\">日本語\r\n</p></body></html>";
+
+    #[test]
+    fn test_streaming() {
+        let preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(HTML.as_bytes())).unwrap();
+        let mut tree_constructor = TreeConstructor::new();
+        Tokenizer::new(preprocessor, &mut tree_constructor).run();
+        let expected = format!("{}", **tree_constructor.dom());
+
+        let decoder = ByteStreamDecoder::streaming().with_transport_charset("utf-8");
+        let preprocessor = InputStreamPreprocessor::streaming(decoder);
+        let mut tree_constructor = TreeConstructor::new();
+        let mut tokenizer = Tokenizer::new(preprocessor, &mut tree_constructor);
+        for chunk in HTML.as_bytes().chunks(3) {
+            tokenizer.feed(chunk).unwrap();
+            assert!(!tokenizer.is_finished());
+        }
+        assert!(tokenizer.sink().dom().children(0).next().is_some());
+        tokenizer.finish();
+        assert!(tokenizer.is_finished());
+        assert_eq!(tokenizer.feed(b"<p>"), Err(ClosedStreamError));
+        assert_eq!(format!("{}", **tree_constructor.dom()), expected);
+    }
+
+    #[test]
+    fn test_character_references() {
+        let parsed = parse("&amp;&nbsp;&#x3042;&copy;&#12354;&notin;&notit;&#x80;&#0;");
+        assert_eq!(parsed.text, "&\u{a0}あ©あ∉¬it;€\u{fffd}");
+        assert_eq!(
+            parsed.errors,
+            [
+                ParseError::MissingSemicolonAfterCharacterReference,
+                ParseError::ControlCharacterReference,
+                ParseError::NullCharacterReference,
+            ]
+        );
+
+        let parsed = parse("&ampx &foo; &#x; &#65");
+        assert_eq!(parsed.text, "&x &foo; &#x; A");
+        assert_eq!(
+            parsed.errors,
+            [
+                ParseError::MissingSemicolonAfterCharacterReference,
+                ParseError::UnknownNamedCharacterReference,
+                ParseError::AbsenceOfDigitsInNumericCharacterReference,
+                ParseError::MissingSemicolonAfterCharacterReference,
+            ]
+        );
+
+        let parsed = parse("<p title='&copy=1&copy;&lt'></p><p title=&notin;x&amp>");
+        assert_eq!(parsed.titles, ["&copy=1©<", "∉x&"]);
+    }
+
+    #[test]
+    fn test_script_data() {
+        let parsed = parse(r#"<script>a</b><!--<script>"</script>"--></script><p>x"#);
+        assert_eq!(parsed.text, r#"a</b><!--<script>"</script>"-->x"#);
+        assert_eq!(parsed.errors, []);
+
+        let parsed = parse("<script><!--<script>");
+        assert_eq!(parsed.text, "<!--<script>");
+        assert_eq!(
+            parsed.errors,
+            [
+                ParseError::EofInScriptHtmlCommentLikeText,
+                ParseError::EofInText
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let parsed = parse("<!-- a -- b --><!a><!--><!---><!--x--!><!--<!--y-->z<![CDATA[w]]><!--");
+        assert_eq!(
+            parsed.comments,
+            [" a -- b ", "a", "", "", "x", "<!--y", "[CDATA[w]]", ""]
+        );
+        assert_eq!(parsed.text, "z");
+        assert_eq!(
+            parsed.errors,
+            [
+                ParseError::IncorrectlyOpenedComment,
+                ParseError::AbruptClosingOfEmptyComment,
+                ParseError::AbruptClosingOfEmptyComment,
+                ParseError::IncorrectlyClosedComment,
+                ParseError::NestedComment,
+                ParseError::CDataInHtmlContent,
+                ParseError::EofInComment,
+            ]
+        );
+    }
+}
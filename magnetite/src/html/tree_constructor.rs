@@ -1,6 +1,13 @@
+mod svg_names;
+
+use super::diagnostic::Diagnostic;
+use super::diagnostic::SourceSpan;
 use super::dom::*;
+use super::input_stream_preprocessor::InputStreamPreprocessor;
 use super::tokenizer::*;
-use std::collections::HashMap;
+use std::mem;
+use svg_names::SVG_ATTRIBUTES;
+use svg_names::SVG_TAG_NAMES;
 
 #[derive(Clone, Debug)]
 pub struct TreeConstructor {
@@ -11,15 +18,18 @@ pub struct TreeConstructor {
     active_formatting_elements: Vec<Option<NodeId>>,
     head_element: Option<NodeId>,
     form_element: Option<NodeId>,
-    quirks_mode: QuirksMode,
     pending_table_characters: String,
     arena: DomArena,
     document: NodeId,
+    context_element: Option<NodeId>,
     saw_doctype: bool,
     frameset_ok: bool,
     is_fragment: bool,
-    position: Option<NodeId>,
-    errors: Vec<ParseError>,
+    scripting: bool,
+    foster_parenting: bool,
+    skip_next_newline: bool,
+    token_span: SourceSpan,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Default for TreeConstructor {
@@ -28,6 +38,47 @@ impl Default for TreeConstructor {
     }
 }
 
+impl TokenSink for TreeConstructor {
+    fn handle_token(&mut self, token: Token, span: SourceSpan) -> Option<TokenizerState> {
+        self.token_span = span;
+        if mem::take(&mut self.skip_next_newline) && token == Token::Character('\n') {
+            return None;
+        }
+        if self.is_html_content(&token) {
+            self.reprocess(token)
+        } else {
+            self.handle_token_in_foreign_content(token)
+        }
+    }
+
+    fn handle_error(&mut self, error: ParseError, span: SourceSpan) {
+        self.diagnostics.push(Diagnostic { error, span });
+    }
+
+    fn adjusted_current_node_namespace(&self) -> Namespace {
+        self.arena[self.adjusted_current_node()].namespace()
+    }
+
+    /// The state the tokenizer starts in. For a fragment it depends on the
+    /// context element, so that the contents of a `title` are text and so on.
+    fn initial_tokenizer_state(&self) -> TokenizerState {
+        let Some(context) = self.context_element else {
+            return TokenizerState::INIT;
+        };
+        if self.arena[context].namespace() != Namespace::Html {
+            return TokenizerState::INIT;
+        }
+        match self.element_name(context).unwrap_or_default() {
+            "title" | "textarea" => TokenizerState::RcData,
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => TokenizerState::RawText,
+            "script" => TokenizerState::ScriptData,
+            "noscript" if self.scripting => TokenizerState::RawText,
+            "plaintext" => TokenizerState::PlainText,
+            _ => TokenizerState::INIT,
+        }
+    }
+}
+
 impl TreeConstructor {
     pub fn new() -> Self {
         Self {
@@ -38,16 +89,66 @@ impl TreeConstructor {
             active_formatting_elements: Vec::new(),
             head_element: None,
             form_element: None,
-            quirks_mode: QuirksMode::NoQuirks,
             pending_table_characters: String::new(),
             arena: DomArena::new(),
             document: DomArena::DOCUMENT_IDX,
+            context_element: None,
             saw_doctype: false,
             frameset_ok: true,
             is_fragment: false,
-            position: None,
-            errors: Vec::new(),
+            scripting: false,
+            foster_parenting: false,
+            skip_next_newline: false,
+            token_span: SourceSpan::default(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Starts parsing a fragment as if it were the contents of `context`, an
+    /// element of `document`, like setting `innerHTML` does.
+    pub fn for_fragment(document: &DomArena, context: NodeId) -> Self {
+        let mut this = Self::new();
+        this.is_fragment = true;
+        this.arena.set_quirks_mode(document.quirks_mode());
+
+        // The context element and its form don't become part of the
+        // fragment. New unlinked elements with the same name, namespace and
+        // attributes are enough for the parser to look at.
+        let detached =
+            |id: NodeId| DomNode::new(document[id].node_type.clone(), document[id].namespace());
+        this.context_element = Some(this.arena.push(detached(context)));
+        let mut ancestor = Some(context);
+        while let Some(id) = ancestor {
+            if document[id].namespace() == Namespace::Html
+                && matches!(document[id].node_type, DomNodeType::Element { ref name, .. } if name == "form")
+            {
+                this.form_element = Some(this.arena.push(detached(id)));
+                break;
+            }
+            ancestor = document[id].parent();
         }
+
+        let root = DomNode::new(
+            DomNodeType::Element {
+                name: "html".to_string(),
+                attributes: Attributes::new(),
+            },
+            Namespace::Html,
+        );
+        let root = this.arena.insert_child(this.document, root);
+        this.open_elements.push(root);
+        if this.is_html_element(this.context_element.unwrap(), &["template"]) {
+            this.template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        this.reset_the_insertion_mode_appropriately();
+        this
+    }
+
+    /// Whether scripts would run, which decides how `noscript` is parsed.
+    /// It's off by default since nothing here runs them.
+    pub fn set_scripting(&mut self, scripting: bool) {
+        self.scripting = scripting;
     }
 
     pub fn dom(&self) -> &DomArena {
@@ -58,8 +159,33 @@ impl TreeConstructor {
         self.arena
     }
 
-    pub fn errors(&self) -> &[ParseError] {
-        &self.errors
+    /// The nodes a fragment parsed into, as the children of a
+    /// `DocumentFragment` at the root of the arena.
+    pub fn take_fragment(mut self) -> DomArena {
+        let root = self.arena[self.document].child().unwrap();
+        let children: Vec<NodeId> = self.arena.children(root).collect();
+        for child in children {
+            self.arena.append_node(self.document, child);
+        }
+        self.arena.unlink(root);
+        self.arena[self.document].node_type = DomNodeType::DocumentFragment;
+        self.arena
+    }
+
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.error)
+            .collect()
+    }
+
+    /// The parse errors along with where in the input they happened.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.arena.quirks_mode()
     }
 
     pub fn mode(&self) -> InsertionMode {
@@ -71,15 +197,14 @@ impl TreeConstructor {
     }
 
     fn adjusted_current_node(&self) -> NodeId {
-        self.current_node()
-    }
-
-    pub fn handle_error(&mut self, error: ParseError) {
-        self.errors.push(error);
+        match self.context_element {
+            Some(context) if self.open_elements.len() == 2 => context,
+            _ => self.current_node(),
+        }
     }
 
     fn error(&mut self, error: ParseError) {
-        self.errors.push(error);
+        self.handle_error(error, self.token_span);
     }
 
     fn switch_to(&mut self, insertion_mode: InsertionMode) {
@@ -94,12 +219,164 @@ impl TreeConstructor {
         self.switch_to(original_insertion_mode);
     }
 
-    pub fn adjusted_current_node_namespace(&self) -> Namespace {
-        self.arena[self.adjusted_current_node()].namespace()
+    /// Whether `token` follows the rules of the current insertion mode rather
+    /// than those for foreign content.
+    fn is_html_content(&self, token: &Token) -> bool {
+        let node = self.adjusted_current_node();
+        let is_start_tag = |names: &[&str]| matches!(token, Token::StartTag { name, .. } if names.contains(&name.as_str()));
+        self.arena[node].namespace() == Namespace::Html
+            || (self.is_mathml_text_integration_point(node)
+                && (matches!(token, Token::Character(_))
+                    || (matches!(token, Token::StartTag { .. })
+                        && !is_start_tag(&["mglyph", "malignmark"]))))
+            || (self.arena[node].namespace() == Namespace::MathMl
+                && self.element_name(node) == Some("annotation-xml")
+                && is_start_tag(&["svg"]))
+            || (self.is_html_integration_point(node)
+                && matches!(token, Token::StartTag { .. } | Token::Character(_)))
+            || matches!(token, Token::Eof)
+    }
+
+    fn is_mathml_text_integration_point(&self, id: NodeId) -> bool {
+        self.arena[id].namespace() == Namespace::MathMl
+            && self
+                .element_name(id)
+                .is_some_and(|name| ["mi", "mo", "mn", "ms", "mtext"].contains(&name))
+    }
+
+    fn is_html_integration_point(&self, id: NodeId) -> bool {
+        let DomNodeType::Element {
+            ref name,
+            ref attributes,
+        } = self.arena[id].node_type
+        else {
+            return false;
+        };
+        match self.arena[id].namespace() {
+            Namespace::MathMl => {
+                name == "annotation-xml"
+                    && attributes.get("encoding").is_some_and(|encoding| {
+                        encoding.eq_ignore_ascii_case("text/html")
+                            || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                    })
+            }
+            Namespace::Svg => ["foreignObject", "desc", "title"].contains(&name.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Processes `token` again under the current insertion mode.
+    fn reprocess(&mut self, token: Token) -> Option<TokenizerState> {
+        self.handle_token_using_rules_for(self.insertion_mode, token)
+    }
+
+    fn handle_token_in_foreign_content(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character('\0') => {
+                self.error(ParseError::UnexpectedNullCharacter);
+                self.insert_character('\u{fffd}');
+            }
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Character(c) => {
+                self.insert_character(c);
+                self.frameset_ok = false;
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag {
+                ref name,
+                ref attributes,
+                ..
+            } if BREAKOUT_ELEMENTS.contains(&name.as_str())
+                || (name == "font"
+                    && ["color", "face", "size"]
+                        .iter()
+                        .any(|&attribute| attributes.contains(attribute))) =>
+            {
+                return self.break_out_of_foreign_content(token);
+            }
+            Token::EndTag { ref name } if ["br", "p"].contains(&name.as_str()) => {
+                return self.break_out_of_foreign_content(token);
+            }
+            Token::StartTag {
+                mut name,
+                mut attributes,
+                self_closing_flag,
+            } => {
+                let namespace = self.adjusted_current_node_namespace();
+                match namespace {
+                    Namespace::MathMl => adjust_mathml_attributes(&mut attributes),
+                    Namespace::Svg => {
+                        adjust_svg_tag_name(&mut name);
+                        adjust_svg_attributes(&mut attributes);
+                    }
+                    _ => (),
+                }
+                adjust_foreign_attributes(&mut attributes);
+                self.insert_foreign_element(name, attributes, namespace);
+                if self_closing_flag {
+                    self.open_elements.pop();
+                }
+            }
+            Token::EndTag { ref name }
+                if name == "script"
+                    && self.arena[self.current_node()].namespace() == Namespace::Svg
+                    && self.element_name(self.current_node()) == Some("script") =>
+            {
+                self.open_elements.pop();
+            }
+            Token::EndTag { ref name } => {
+                let mut i = self.open_elements.len() - 1;
+                if !self
+                    .element_name(self.open_elements[i])
+                    .is_some_and(|node_name| node_name.eq_ignore_ascii_case(name))
+                {
+                    self.error(ParseError::UnexpectedEndTag);
+                }
+                while i > 1 {
+                    let node = self.open_elements[i];
+                    if self
+                        .element_name(node)
+                        .is_some_and(|node_name| node_name.eq_ignore_ascii_case(name))
+                    {
+                        self.open_elements.truncate(i);
+                        return None;
+                    }
+                    i -= 1;
+                    if self.arena[self.open_elements[i]].namespace() == Namespace::Html {
+                        return self.reprocess(token);
+                    }
+                }
+            }
+            Token::Eof => unreachable!(),
+        }
+        None
+    }
+
+    /// Pops foreign elements until HTML can be parsed again, then reprocesses
+    /// `token` under the current insertion mode.
+    fn break_out_of_foreign_content(&mut self, token: Token) -> Option<TokenizerState> {
+        self.error(ParseError::UnexpectedHtmlElementInForeignContent);
+        while self.open_elements.len() > 1 {
+            let node = self.current_node();
+            if self.arena[node].namespace() == Namespace::Html
+                || self.is_mathml_text_integration_point(node)
+                || self.is_html_integration_point(node)
+            {
+                break;
+            }
+            self.open_elements.pop();
+        }
+        self.reprocess(token)
     }
 
-    pub fn handle_token(&mut self, token: Token) -> Option<TokenizerState> {
-        match self.insertion_mode {
+    /// Processes `token` as `insertion_mode` would, without switching to it.
+    fn handle_token_using_rules_for(
+        &mut self,
+        insertion_mode: InsertionMode,
+        token: Token,
+    ) -> Option<TokenizerState> {
+        match insertion_mode {
             InsertionMode::Initial => self.handle_token_initial(token),
             InsertionMode::BeforeHtml => self.handle_token_before_html(token),
             InsertionMode::BeforeHead => self.handle_token_before_head(token),
@@ -109,7 +386,20 @@ impl TreeConstructor {
             InsertionMode::AfterBody => self.handle_token_after_body(token),
             InsertionMode::AfterAfterBody => self.handle_token_after_after_token(token),
             InsertionMode::Text => self.handle_token_text(token),
-            mode => unimplemented!("{:?}", mode),
+            InsertionMode::InTable => self.handle_token_in_table(token),
+            InsertionMode::InTableText => self.handle_token_in_table_text(token),
+            InsertionMode::InCaption => self.handle_token_in_caption(token),
+            InsertionMode::InColumnGroup => self.handle_token_in_column_group(token),
+            InsertionMode::InTableBody => self.handle_token_in_table_body(token),
+            InsertionMode::InRow => self.handle_token_in_row(token),
+            InsertionMode::InCell => self.handle_token_in_cell(token),
+            InsertionMode::InTemplate => self.handle_token_in_template(token),
+            InsertionMode::InHeadNoScript => self.handle_token_in_head_noscript(token),
+            InsertionMode::InSelect => self.handle_token_in_select(token),
+            InsertionMode::InSelectInTable => self.handle_token_in_select_in_table(token),
+            InsertionMode::InFrameset => self.handle_token_in_frameset(token),
+            InsertionMode::AfterFrameset => self.handle_token_after_frameset(token),
+            InsertionMode::AfterAfterFrameset => self.handle_token_after_after_frameset(token),
         }
     }
 
@@ -120,9 +410,9 @@ impl TreeConstructor {
             }
             Token::Eof => {
                 self.error(ParseError::EofInText);
-            }
-            Token::EndTag { name, .. } if &name == "script" => {
-                unimplemented!();
+                self.open_elements.pop();
+                self.switch_to_original_insertion_mode();
+                return self.reprocess(token);
             }
             Token::EndTag { .. } => {
                 self.open_elements.pop();
@@ -136,28 +426,22 @@ impl TreeConstructor {
     fn handle_token_after_after_token(&mut self, token: Token) -> Option<TokenizerState> {
         match token {
             Token::Comment(text) => {
-                self.insert_comment(text);
+                self.insert_comment_at(text, InsertionLocation::LastChildOf(self.document));
             }
             Token::Doctype { .. } => {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
-
-            Token::Character(c)
-                if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{000d}', '\u{0020}'].contains(&c) =>
-            {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+            Token::Character(c) if is_whitespace(c) => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
             Token::StartTag { ref name, .. } if name == "html" => {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
             Token::Eof => (),
             _ => {
                 self.error(ParseError::UnexpectedTokenInAfterAfterBody);
                 self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.reprocess(token);
             }
         }
         None
@@ -165,21 +449,18 @@ impl TreeConstructor {
 
     fn handle_token_after_body(&mut self, token: Token) -> Option<TokenizerState> {
         match token {
-            Token::Character(c)
-                if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{000d}', '\u{0020}'].contains(&c) =>
-            {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+            Token::Character(c) if is_whitespace(c) => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
             Token::Comment(text) => {
-                self.insert_comment(text);
+                let html = self.open_elements[1];
+                self.insert_comment_at(text, InsertionLocation::LastChildOf(html));
             }
             Token::Doctype { .. } => {
                 self.error(ParseError::UnexpectedDoctype);
             }
             Token::StartTag { ref name, .. } if name == "html" => {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
             Token::EndTag { ref name, .. } if name == "html" => {
                 if self.is_fragment {
@@ -192,7 +473,7 @@ impl TreeConstructor {
             _ => {
                 self.error(ParseError::UnexpectedEndTag);
                 self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.reprocess(token);
             }
         }
         None
@@ -202,16 +483,38 @@ impl TreeConstructor {
         match token {
             Token::Character(c)
                 if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{000d}', '\u{0020}'].contains(&c) => {}
-            Token::Doctype { name, .. } => {
+            Token::Doctype {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => {
                 self.saw_doctype = true;
 
-                self.quirks_mode = if let Some(name) = &name
-                    && name.eq_ignore_ascii_case("html")
+                if name.as_deref() != Some("html")
+                    || public_id.is_some()
+                    || system_id
+                        .as_deref()
+                        .is_some_and(|id| id != "about:legacy-compat")
                 {
-                    QuirksMode::NoQuirks
-                } else {
+                    self.error(ParseError::NonConformingDoctype);
+                }
+                let quirks_mode = if force_quirks {
                     QuirksMode::Quirks
+                } else {
+                    doctype_quirks_mode(name.as_deref(), public_id.as_deref(), system_id.as_deref())
                 };
+                self.arena.set_quirks_mode(quirks_mode);
+
+                let node = DomNode::new(
+                    DomNodeType::DocType {
+                        name: name.unwrap_or_default(),
+                        public_id: public_id.unwrap_or_default(),
+                        system_id: system_id.unwrap_or_default(),
+                    },
+                    Namespace::Html,
+                );
+                self.arena.insert_child(self.document, node);
                 self.switch_to(InsertionMode::BeforeHtml);
             }
             Token::Comment(text) => {
@@ -219,94 +522,223 @@ impl TreeConstructor {
             }
             _ => {
                 self.error(ParseError::ExpectedDoctypeButGotSomethingElse);
-                self.quirks_mode = QuirksMode::Quirks;
+                self.arena.set_quirks_mode(QuirksMode::Quirks);
                 self.switch_to(InsertionMode::BeforeHtml);
-                self.handle_token(token);
+                return self.reprocess(token);
             }
         }
         None
     }
 
-    fn opened_element(&self, name: &str) -> bool {
-        for i in 1..self.open_elements.len() {
-            let idx = self.open_elements[i];
-            if let DomNodeType::Element { name: ref n, .. } = self.arena[idx].node_type
-                && n == name
-            {
-                return true;
-            }
+    fn element_name(&self, id: NodeId) -> Option<&str> {
+        if let DomNodeType::Element { ref name, .. } = self.arena[id].node_type {
+            Some(name)
+        } else {
+            None
         }
-        false
     }
 
-    fn close_element(&mut self, name: &str) {
-        for i in (1..self.open_elements.len()).rev() {
-            let idx = self.open_elements[i];
-            if let DomNodeType::Element { name: ref n, .. } = self.arena[idx].node_type
-                && n == name
-            {
-                self.open_elements.resize_with(i, || panic!());
-                break;
+    fn is_html_element(&self, id: NodeId, names: &[&str]) -> bool {
+        self.arena[id].namespace() == Namespace::Html
+            && self
+                .element_name(id)
+                .is_some_and(|name| names.contains(&name))
+    }
+
+    fn current_node_is(&self, names: &[&str]) -> bool {
+        self.is_html_element(self.current_node(), names)
+    }
+
+    fn is_special(&self, id: NodeId) -> bool {
+        let Some(name) = self.element_name(id) else {
+            return false;
+        };
+        match self.arena[id].namespace() {
+            Namespace::Html => SPECIAL_ELEMENTS.contains(&name),
+            Namespace::MathMl => {
+                ["mi", "mo", "mn", "ms", "mtext", "annotation-xml"].contains(&name)
             }
+            Namespace::Svg => ["foreignObject", "desc", "title"].contains(&name),
+            _ => false,
         }
     }
 
-    fn close_element_until(&mut self, close: &[&str], until: &str) {
-        for i in (1..self.open_elements.len()).rev() {
-            let idx = self.open_elements[i];
-            if let DomNodeType::Element { name: ref n, .. } = self.arena[idx].node_type {
-                if n == until {
-                    break;
-                }
-                if close.contains(&n.as_str()) {
-                    self.open_elements.remove(i);
+    fn is_scope_boundary(&self, id: NodeId, scope: Scope) -> bool {
+        let Some(name) = self.element_name(id) else {
+            return false;
+        };
+        let namespace = self.arena[id].namespace();
+        match scope {
+            Scope::Table => {
+                namespace == Namespace::Html && ["html", "table", "template"].contains(&name)
+            }
+            Scope::Select => {
+                namespace != Namespace::Html || !["optgroup", "option"].contains(&name)
+            }
+            Scope::Default | Scope::Button | Scope::ListItem => {
+                let extra: &[&str] = match scope {
+                    Scope::Button => &["button"],
+                    Scope::ListItem => &["ol", "ul"],
+                    _ => &[],
+                };
+                if namespace == Namespace::Html {
+                    SCOPE_ELEMENTS.contains(&name) || extra.contains(&name)
+                } else {
+                    self.is_special(id)
                 }
             }
         }
     }
 
-    fn has_an_element_in_scope(&self, name: &str) -> bool {
-        const SCOPE: &[&str] = &[
-            "applet", "caption", "html", "table", "td", "th", "marquee", "object", "select",
-            "template",
-        ];
-        for idx in &self.open_elements {
-            if let DomNodeType::Element { name: ref n, .. } = self.arena[*idx].node_type {
-                if n == name {
-                    return true;
-                }
-                if SCOPE.contains(&n.as_str()) {
-                    return false;
-                }
+    /// Whether an element matching `target` comes before any boundary of
+    /// `scope`, searching the stack of open elements from the current node.
+    fn has_in_specific_scope(&self, target: impl Fn(NodeId) -> bool, scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if target(id) {
+                return true;
+            }
+            if self.is_scope_boundary(id, scope) {
+                return false;
             }
         }
         false
     }
 
+    fn has_an_element_in_specific_scope(&self, names: &[&str], scope: Scope) -> bool {
+        self.has_in_specific_scope(|id| self.is_html_element(id, names), scope)
+    }
+
+    fn has_an_element_in_scope(&self, name: &str) -> bool {
+        self.has_an_element_in_specific_scope(&[name], Scope::Default)
+    }
+
     fn has_an_element_in_button_scope(&self, name: &str) -> bool {
-        if self.has_an_element_in_scope(name) {
-            true
-        } else {
-            for idx in &self.open_elements {
-                if let DomNodeType::Element { name: ref n, .. } = self.arena[*idx].node_type {
-                    if n == name && self.arena[*idx].namespace() == Namespace::Html {
-                        return true;
-                    }
-                    if n == "button" {
-                        return false;
-                    }
-                }
+        self.has_an_element_in_specific_scope(&[name], Scope::Button)
+    }
+
+    fn has_an_element_in_table_scope(&self, name: &str) -> bool {
+        self.has_an_element_in_specific_scope(&[name], Scope::Table)
+    }
+
+    /// Pops elements until an HTML element named one of `names` has been
+    /// popped.
+    fn pop_until(&mut self, names: &[&str]) {
+        while self.open_elements.len() > 1 {
+            let id = self.open_elements.pop().unwrap();
+            if self.is_html_element(id, names) {
+                break;
+            }
+        }
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while self.current_node_is(&IMPLIED_END_TAGS)
+            && except.is_none_or(|name| !self.current_node_is(&[name]))
+        {
+            self.open_elements.pop();
+        }
+    }
+
+    fn generate_all_implied_end_tags_thoroughly(&mut self) {
+        while self.current_node_is(&IMPLIED_END_TAGS)
+            || self.current_node_is(&[
+                "caption", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+            ])
+        {
+            self.open_elements.pop();
+        }
+    }
+
+    fn close_a_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        if !self.current_node_is(&["p"]) {
+            self.error(ParseError::UnclosedElement);
+        }
+        self.pop_until(&["p"]);
+    }
+
+    /// Pops elements until the current node is one of `names`, `template` or
+    /// `html`.
+    fn clear_the_stack_back_to(&mut self, names: &[&str]) {
+        while !self.current_node_is(names) && !self.current_node_is(&["template", "html"]) {
+            self.open_elements.pop();
+        }
+    }
+
+    fn clear_the_stack_back_to_a_table_context(&mut self) {
+        self.clear_the_stack_back_to(&["table"]);
+    }
+
+    fn clear_the_stack_back_to_a_table_body_context(&mut self) {
+        self.clear_the_stack_back_to(&["tbody", "tfoot", "thead"]);
+    }
+
+    fn clear_the_stack_back_to_a_table_row_context(&mut self) {
+        self.clear_the_stack_back_to(&["tr"]);
+    }
+
+    fn insert_a_marker(&mut self) {
+        self.active_formatting_elements.push(None);
+    }
+
+    fn clear_the_list_of_active_formatting_elements_up_to_the_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if entry.is_none() {
+                break;
+            }
+        }
+    }
+
+    fn reset_the_insertion_mode_appropriately(&mut self) {
+        for i in (1..self.open_elements.len()).rev() {
+            let last = i == 1;
+            let node = match self.context_element {
+                Some(context) if last => context,
+                _ => self.open_elements[i],
+            };
+            let insertion_mode = match self.element_name(node).unwrap_or_default() {
+                "select" => self.select_insertion_mode(i),
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "template" => match self.template_insertion_modes.last() {
+                    Some(&insertion_mode) => insertion_mode,
+                    None => continue,
+                },
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "frameset" => InsertionMode::InFrameset,
+                "html" if self.head_element.is_none() => InsertionMode::BeforeHead,
+                "html" => InsertionMode::AfterHead,
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            self.switch_to(insertion_mode);
+            return;
+        }
+        self.switch_to(InsertionMode::InBody);
+    }
+
+    /// The mode for the `select` at `index` on the stack, which depends on
+    /// whether it's inside a table.
+    fn select_insertion_mode(&self, index: usize) -> InsertionMode {
+        for &ancestor in self.open_elements[1..index].iter().rev() {
+            match self.element_name(ancestor) {
+                Some("template") => break,
+                Some("table") => return InsertionMode::InSelectInTable,
+                _ => (),
             }
-            false
         }
+        InsertionMode::InSelect
     }
 
     fn handle_token_in_body(&mut self, token: Token) -> Option<TokenizerState> {
         match token {
             Token::Character('\0') => self.error(ParseError::UnexpectedNullCharacter),
-            Token::Character(c)
-                if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{000d}', '\u{0020}'].contains(&c) =>
-            {
+            Token::Character(c) if is_whitespace(c) => {
                 self.reconstruct_the_active_formatting_elements();
                 self.insert_character(c);
             }
@@ -323,17 +755,8 @@ impl TreeConstructor {
                 name, attributes, ..
             } if &name == "html" => {
                 self.error(ParseError::UnexpectedStartTag);
-                if let Some(html_idx) = self.arena.get_child_element(DomArena::DOCUMENT_IDX, "html")
-                    && let DomNodeType::Element {
-                        attributes: ref mut real_attributes,
-                        ..
-                    } = self.arena[html_idx].node_type
-                {
-                    for attribute in attributes {
-                        if !real_attributes.contains_key(&attribute.0) {
-                            real_attributes.insert(attribute.0.clone(), attribute.1.clone());
-                        }
-                    }
+                if !self.has_template_on_the_stack() {
+                    self.add_missing_attributes(self.open_elements[1], attributes);
                 }
             }
             Token::StartTag { ref name, .. }
@@ -343,134 +766,1424 @@ impl TreeConstructor {
                 ]
                 .contains(&name.as_str()) =>
             {
-                self.switch_to(InsertionMode::InHead);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
             }
             Token::EndTag { ref name, .. } if name == "template" => {
-                self.switch_to(InsertionMode::InHead);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "body" => {
+                self.error(ParseError::UnexpectedStartTag);
+                if self.open_elements.len() > 2
+                    && self.is_html_element(self.open_elements[2], &["body"])
+                    && !self.has_template_on_the_stack()
+                {
+                    self.frameset_ok = false;
+                    self.add_missing_attributes(self.open_elements[2], attributes);
+                }
             }
-            Token::StartTag { ref name, .. } if ["body", "frameset"].contains(&name.as_str()) => {
-                unimplemented!();
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "frameset" => {
+                self.error(ParseError::UnexpectedStartTag);
+                if self.open_elements.len() > 2
+                    && self.is_html_element(self.open_elements[2], &["body"])
+                    && self.frameset_ok
+                {
+                    self.arena.unlink(self.open_elements[2]);
+                    self.open_elements.truncate(2);
+                    self.insert_element(name, attributes);
+                    self.switch_to(InsertionMode::InFrameset);
+                }
             }
             Token::Eof => {
                 if !self.template_insertion_modes.is_empty() {
-                    self.switch_to(InsertionMode::InTemplate);
-                    self.handle_token(token);
-                } else {
-                    for i in 1..self.open_elements.len() {
-                        let node_idx = self.open_elements[i];
-                        if let DomNodeType::Element { ref name, .. } =
-                            self.arena[node_idx].node_type
-                            && ![
-                                "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt",
-                                "rtc", "tbody", "td", "tfoot", "th", "thead", "tr", "body", "html",
-                            ]
-                            .contains(&name.as_str())
-                        {
-                            self.error(ParseError::UnclosedElementAtEof);
-                            break;
-                        }
-                    }
+                    return self.handle_token_using_rules_for(InsertionMode::InTemplate, token);
                 }
+                self.check_for_unclosed_elements(ParseError::UnclosedElementAtEof);
             }
-            Token::EndTag { ref name, .. } if name == "body" => {
-                if !self.opened_element("html") {
-                    self.error(ParseError::UnclosedElement);
-                } else {
-                    for i in 1..self.open_elements.len() {
-                        let node_idx = self.open_elements[i];
-                        if let DomNodeType::Element { ref name, .. } =
-                            self.arena[node_idx].node_type
-                            && ![
-                                "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt",
-                                "rtc", "tbody", "td", "tfoot", "th", "thead", "tr", "body", "html",
-                            ]
-                            .contains(&name.as_str())
-                        {
-                            self.error(ParseError::UnclosedElement);
-                            break;
-                        }
-                    }
-
-                    self.switch_to(InsertionMode::AfterBody);
+            Token::EndTag { ref name, .. } if ["body", "html"].contains(&name.as_str()) => {
+                if !self.has_an_element_in_scope("body") {
+                    self.error(ParseError::ElementNotFoundInScope);
+                    return None;
+                }
+                self.check_for_unclosed_elements(ParseError::UnclosedElement);
+                self.switch_to(InsertionMode::AfterBody);
+                if name == "html" {
+                    return self.reprocess(token);
                 }
             }
             Token::StartTag {
                 name, attributes, ..
-            } if ["h1", "h2", "h3", "h4", "h5", "h6"].contains(&name.as_str()) => {
-                if self.opened_element("p") {
-                    self.close_element("p");
-                }
-                if let DomNodeType::Element { ref name, .. } =
-                    self.arena[self.current_node()].node_type
-                    && ["h1", "h2", "h3", "h4", "h5", "h6"].contains(&name.as_str())
-                {
+            } if [
+                "address",
+                "article",
+                "aside",
+                "blockquote",
+                "center",
+                "details",
+                "dialog",
+                "dir",
+                "div",
+                "dl",
+                "fieldset",
+                "figcaption",
+                "figure",
+                "footer",
+                "header",
+                "hgroup",
+                "main",
+                "menu",
+                "nav",
+                "ol",
+                "p",
+                "search",
+                "section",
+                "summary",
+                "ul",
+            ]
+            .contains(&name.as_str()) =>
+            {
+                self.close_a_p_element_in_button_scope();
+                self.insert_element(name, attributes);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if HEADINGS.contains(&name.as_str()) => {
+                self.close_a_p_element_in_button_scope();
+                if self.current_node_is(&HEADINGS) {
+                    self.error(ParseError::UnexpectedStartTag);
                     self.open_elements.pop();
                 }
                 self.insert_element(name, attributes);
             }
             Token::StartTag {
                 name, attributes, ..
-            } => {
-                self.reconstruct_the_active_formatting_elements();
+            } if ["pre", "listing"].contains(&name.as_str()) => {
+                self.close_a_p_element_in_button_scope();
                 self.insert_element(name, attributes);
+                self.skip_next_newline = true;
+                self.frameset_ok = false;
             }
-            Token::EndTag { name }
-                if ["h1", "h2", "h3", "h4", "h5", "h6"].contains(&name.as_str()) =>
-            {
-                if !self.opened_element(&name) {
-                    self.error(ParseError::UnexpectedEndTag);
-                } else {
-                    self.close_element_until(
-                        &[
-                            "dd", "dt", "li", "option", "optgroup", "p", "rb", "rp", "rt", "rtc",
-                        ],
-                        "h1",
-                    );
-                    let current_node = self.current_node();
-                    let DomNodeType::Element { name: ref n, .. } =
-                        self.arena[current_node].node_type
-                    else {
-                        panic!();
-                    };
-                    if &name != n {
-                        self.error(ParseError::UnexpectedEndTag);
-                    }
-                    self.close_element(&name);
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "form" => {
+                let has_template = self.has_template_on_the_stack();
+                if self.form_element.is_some() && !has_template {
+                    self.error(ParseError::UnexpectedStartTag);
+                    return None;
+                }
+                self.close_a_p_element_in_button_scope();
+                let form = self.insert_element(name, attributes);
+                if !has_template {
+                    self.form_element = Some(form);
                 }
             }
-            Token::EndTag { name } if &name == "p" => {
-                if !self.has_an_element_in_button_scope("p") {
-                    self.error(ParseError::ElementNotFoundInButtonScope);
-                    self.insert_element("p".to_string(), HashMap::new());
+            Token::StartTag {
+                name, attributes, ..
+            } if ["li", "dd", "dt"].contains(&name.as_str()) => {
+                self.frameset_ok = false;
+                let closes: &[&str] = if name == "li" { &["li"] } else { &["dd", "dt"] };
+                for i in (1..self.open_elements.len()).rev() {
+                    let node = self.open_elements[i];
+                    if let Some(node_name) = self.element_name(node)
+                        && self.is_html_element(node, closes)
+                    {
+                        let node_name = node_name.to_string();
+                        self.generate_implied_end_tags(Some(&node_name));
+                        if !self.current_node_is(&[&node_name]) {
+                            self.error(ParseError::UnclosedElement);
+                        }
+                        self.pop_until(&[&node_name]);
+                        break;
+                    }
+                    if self.is_special(node)
+                        && !self.is_html_element(node, &["address", "div", "p"])
+                    {
+                        break;
+                    }
                 }
-                self.close_element("p");
+                self.close_a_p_element_in_button_scope();
+                self.insert_element(name, attributes);
             }
-            Token::EndTag { name } => {
-                if !self.opened_element("html") {
-                    self.error(ParseError::UnexpectedEndTag);
-                } else {
-                    self.close_element(&name);
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "plaintext" => {
+                self.close_a_p_element_in_button_scope();
+                self.insert_element(name, attributes);
+                return Some(TokenizerState::PlainText);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "button" => {
+                if self.has_an_element_in_scope("button") {
+                    self.error(ParseError::UnexpectedStartTag);
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"]);
                 }
+                self.reconstruct_the_active_formatting_elements();
+                self.insert_element(name, attributes);
+                self.frameset_ok = false;
             }
-        }
+            Token::EndTag { ref name }
+                if [
+                    "address",
+                    "article",
+                    "aside",
+                    "blockquote",
+                    "button",
+                    "center",
+                    "details",
+                    "dialog",
+                    "dir",
+                    "div",
+                    "dl",
+                    "fieldset",
+                    "figcaption",
+                    "figure",
+                    "footer",
+                    "header",
+                    "hgroup",
+                    "listing",
+                    "main",
+                    "menu",
+                    "nav",
+                    "ol",
+                    "pre",
+                    "search",
+                    "section",
+                    "summary",
+                    "ul",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                if !self.has_an_element_in_scope(name) {
+                    self.error(ParseError::ElementNotFoundInScope);
+                    return None;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[name]) {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.pop_until(&[name]);
+            }
+            Token::EndTag { ref name } if name == "form" => {
+                if self.has_template_on_the_stack() {
+                    if !self.has_an_element_in_scope("form") {
+                        self.error(ParseError::ElementNotFoundInScope);
+                        return None;
+                    }
+                    self.generate_implied_end_tags(None);
+                    if !self.current_node_is(&["form"]) {
+                        self.error(ParseError::UnclosedElement);
+                    }
+                    self.pop_until(&["form"]);
+                } else {
+                    let node = self.form_element.take();
+                    let Some(node) = node.filter(|&node| {
+                        self.has_in_specific_scope(|id| id == node, Scope::Default)
+                    }) else {
+                        self.error(ParseError::ElementNotFoundInScope);
+                        return None;
+                    };
+                    self.generate_implied_end_tags(None);
+                    if node != self.current_node() {
+                        self.error(ParseError::UnclosedElement);
+                    }
+                    self.open_elements.retain(|&id| id != node);
+                }
+            }
+            Token::EndTag { ref name } if name == "p" => {
+                if !self.has_an_element_in_button_scope("p") {
+                    self.error(ParseError::ElementNotFoundInButtonScope);
+                    self.insert_element("p".to_string(), Attributes::new());
+                }
+                self.close_a_p_element();
+            }
+            Token::EndTag { ref name } if ["li", "dd", "dt"].contains(&name.as_str()) => {
+                let scope = if name == "li" {
+                    Scope::ListItem
+                } else {
+                    Scope::Default
+                };
+                if !self.has_an_element_in_specific_scope(&[name], scope) {
+                    self.error(ParseError::ElementNotFoundInScope);
+                    return None;
+                }
+                self.generate_implied_end_tags(Some(name));
+                if !self.current_node_is(&[name]) {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.pop_until(&[name]);
+            }
+            Token::EndTag { ref name } if HEADINGS.contains(&name.as_str()) => {
+                if !self.has_an_element_in_specific_scope(&HEADINGS, Scope::Default) {
+                    self.error(ParseError::ElementNotFoundInScope);
+                    return None;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[name]) {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.pop_until(&HEADINGS);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "a" => {
+                if let Some(a) = self.active_formatting_element("a") {
+                    self.error(ParseError::UnexpectedStartTag);
+                    self.adoption_agency("a");
+                    self.remove_from_active_formatting_elements(a);
+                    self.open_elements.retain(|&id| id != a);
+                }
+                self.reconstruct_the_active_formatting_elements();
+                let element = self.insert_element(name, attributes);
+                self.push_onto_the_list_of_active_formatting_elements(element);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if FORMATTING_ELEMENTS.contains(&name.as_str()) => {
+                self.reconstruct_the_active_formatting_elements();
+                if name == "nobr" && self.has_an_element_in_scope("nobr") {
+                    self.error(ParseError::UnexpectedStartTag);
+                    self.adoption_agency("nobr");
+                    self.reconstruct_the_active_formatting_elements();
+                }
+                let element = self.insert_element(name, attributes);
+                self.push_onto_the_list_of_active_formatting_elements(element);
+            }
+            Token::EndTag { ref name }
+                if name == "a" || FORMATTING_ELEMENTS.contains(&name.as_str()) =>
+            {
+                self.adoption_agency(name);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["applet", "marquee", "object"].contains(&name.as_str()) => {
+                self.reconstruct_the_active_formatting_elements();
+                self.insert_element(name, attributes);
+                self.insert_a_marker();
+                self.frameset_ok = false;
+            }
+            Token::EndTag { ref name }
+                if ["applet", "marquee", "object"].contains(&name.as_str()) =>
+            {
+                if !self.has_an_element_in_scope(name) {
+                    self.error(ParseError::ElementNotFoundInScope);
+                    return None;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[name]) {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.pop_until(&[name]);
+                self.clear_the_list_of_active_formatting_elements_up_to_the_last_marker();
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "table" => {
+                if self.quirks_mode() != QuirksMode::Quirks {
+                    self.close_a_p_element_in_button_scope();
+                }
+                self.insert_element(name, attributes);
+                self.frameset_ok = false;
+                self.switch_to(InsertionMode::InTable);
+            }
+            Token::EndTag { ref name } if name == "br" => {
+                self.error(ParseError::UnexpectedEndTag);
+                return self.handle_token_in_body(Token::StartTag {
+                    name: "br".to_string(),
+                    attributes: Attributes::new(),
+                    self_closing_flag: false,
+                });
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["area", "br", "embed", "img", "keygen", "wbr"].contains(&name.as_str()) => {
+                self.reconstruct_the_active_formatting_elements();
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+                self.frameset_ok = false;
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "input" => {
+                self.reconstruct_the_active_formatting_elements();
+                let is_hidden = attributes
+                    .get("type")
+                    .is_some_and(|value| value.eq_ignore_ascii_case("hidden"));
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+                if !is_hidden {
+                    self.frameset_ok = false;
+                }
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["param", "source", "track"].contains(&name.as_str()) => {
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "hr" => {
+                self.close_a_p_element_in_button_scope();
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+                self.frameset_ok = false;
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing_flag,
+            } if name == "image" => {
+                self.error(ParseError::UnexpectedStartTag);
+                return self.reprocess(Token::StartTag {
+                    name: "img".to_string(),
+                    attributes,
+                    self_closing_flag,
+                });
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "textarea" => {
+                self.insert_element(name, attributes);
+                self.skip_next_newline = true;
+                self.frameset_ok = false;
+                self.set_original_insertion_mode();
+                self.switch_to(InsertionMode::Text);
+                return Some(TokenizerState::RcData);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "xmp" => {
+                self.close_a_p_element_in_button_scope();
+                self.reconstruct_the_active_formatting_elements();
+                self.frameset_ok = false;
+                return self.parse_generic_raw_text_element(name, attributes);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "iframe" => {
+                self.frameset_ok = false;
+                return self.parse_generic_raw_text_element(name, attributes);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "noembed" || (name == "noscript" && self.scripting) => {
+                return self.parse_generic_raw_text_element(name, attributes);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "select" => {
+                self.reconstruct_the_active_formatting_elements();
+                self.insert_element(name, attributes);
+                self.frameset_ok = false;
+                let insertion_mode = if [
+                    InsertionMode::InTable,
+                    InsertionMode::InCaption,
+                    InsertionMode::InTableBody,
+                    InsertionMode::InRow,
+                    InsertionMode::InCell,
+                ]
+                .contains(&self.insertion_mode)
+                {
+                    InsertionMode::InSelectInTable
+                } else {
+                    InsertionMode::InSelect
+                };
+                self.switch_to(insertion_mode);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["optgroup", "option"].contains(&name.as_str()) => {
+                if self.current_node_is(&["option"]) {
+                    self.open_elements.pop();
+                }
+                self.reconstruct_the_active_formatting_elements();
+                self.insert_element(name, attributes);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["rb", "rtc", "rp", "rt"].contains(&name.as_str()) => {
+                let (except, parents): (_, &[&str]) = if ["rb", "rtc"].contains(&name.as_str()) {
+                    (None, &["ruby"])
+                } else {
+                    (Some("rtc"), &["ruby", "rtc"])
+                };
+                if self.has_an_element_in_scope("ruby") {
+                    self.generate_implied_end_tags(except);
+                    if !self.current_node_is(parents) {
+                        self.error(ParseError::UnclosedElement);
+                    }
+                }
+                self.insert_element(name, attributes);
+            }
+            Token::StartTag {
+                name,
+                mut attributes,
+                self_closing_flag,
+            } if ["math", "svg"].contains(&name.as_str()) => {
+                self.reconstruct_the_active_formatting_elements();
+                let namespace = if name == "math" {
+                    adjust_mathml_attributes(&mut attributes);
+                    Namespace::MathMl
+                } else {
+                    adjust_svg_attributes(&mut attributes);
+                    Namespace::Svg
+                };
+                adjust_foreign_attributes(&mut attributes);
+                self.insert_foreign_element(name, attributes, namespace);
+                if self_closing_flag {
+                    self.open_elements.pop();
+                }
+            }
+            Token::StartTag { ref name, .. }
+                if [
+                    "caption", "col", "colgroup", "frame", "head", "tbody", "td", "tfoot", "th",
+                    "thead", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedStartTag);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } => {
+                self.reconstruct_the_active_formatting_elements();
+                self.insert_element(name, attributes);
+            }
+            Token::EndTag { name } => self.any_other_end_tag_in_body(&name),
+        }
+        None
+    }
+
+    /// Copies the attributes `element` doesn't have yet, as for a misplaced
+    /// `html` or `body` start tag.
+    fn add_missing_attributes(&mut self, element: NodeId, attributes: Attributes) {
+        if let DomNodeType::Element {
+            attributes: ref mut existing,
+            ..
+        } = self.arena[element].node_type
+        {
+            for attribute in attributes {
+                if !existing.contains(&attribute.local_name) {
+                    existing.push(attribute);
+                }
+            }
+        }
+    }
+
+    /// Reports `error` if an element other than those whose end tags may be
+    /// omitted is still open.
+    fn check_for_unclosed_elements(&mut self, error: ParseError) {
+        const OMITTABLE: [&str; 18] = [
+            "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody", "td",
+            "tfoot", "th", "thead", "tr", "body", "html",
+        ];
+        if self.open_elements[1..]
+            .iter()
+            .any(|&id| !self.is_html_element(id, &OMITTABLE))
+        {
+            self.error(error);
+        }
+    }
+
+    fn close_a_p_element_in_button_scope(&mut self) {
+        if self.has_an_element_in_button_scope("p") {
+            self.close_a_p_element();
+        }
+    }
+
+    fn any_other_end_tag_in_body(&mut self, name: &str) {
+        for i in (1..self.open_elements.len()).rev() {
+            let node = self.open_elements[i];
+            if self.is_html_element(node, &[name]) {
+                self.generate_implied_end_tags(Some(name));
+                if node != self.current_node() {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.open_elements.truncate(i);
+                return;
+            }
+            if self.is_special(node) {
+                self.error(ParseError::UnexpectedEndTag);
+                return;
+            }
+        }
+    }
+
+    fn handle_token_in_table(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character(_)
+                if self
+                    .current_node_is(&["table", "tbody", "template", "tfoot", "thead", "tr"]) =>
+            {
+                self.pending_table_characters.clear();
+                self.switch_to(InsertionMode::InTableText);
+                return self.reprocess(token);
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "caption" => {
+                self.clear_the_stack_back_to_a_table_context();
+                self.insert_a_marker();
+                self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InCaption);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "colgroup" => {
+                self.clear_the_stack_back_to_a_table_context();
+                self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InColumnGroup);
+            }
+            Token::StartTag { ref name, .. } if name == "col" => {
+                self.clear_the_stack_back_to_a_table_context();
+                self.insert_element("colgroup".to_string(), Attributes::new());
+                self.switch_to(InsertionMode::InColumnGroup);
+                return self.reprocess(token);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["tbody", "tfoot", "thead"].contains(&name.as_str()) => {
+                self.clear_the_stack_back_to_a_table_context();
+                self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InTableBody);
+            }
+            Token::StartTag { ref name, .. } if ["td", "th", "tr"].contains(&name.as_str()) => {
+                self.clear_the_stack_back_to_a_table_context();
+                self.insert_element("tbody".to_string(), Attributes::new());
+                self.switch_to(InsertionMode::InTableBody);
+                return self.reprocess(token);
+            }
+            Token::StartTag { ref name, .. } if name == "table" => {
+                self.error(ParseError::UnexpectedStartTag);
+                if self.has_an_element_in_table_scope("table") {
+                    self.pop_until(&["table"]);
+                    self.reset_the_insertion_mode_appropriately();
+                    return self.reprocess(token);
+                }
+            }
+            Token::EndTag { ref name } if name == "table" => {
+                if self.has_an_element_in_table_scope("table") {
+                    self.pop_until(&["table"]);
+                    self.reset_the_insertion_mode_appropriately();
+                } else {
+                    self.error(ParseError::ElementNotFoundInTableScope);
+                }
+            }
+            Token::EndTag { ref name }
+                if [
+                    "body", "caption", "col", "colgroup", "html", "tbody", "td", "tfoot", "th",
+                    "thead", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            Token::StartTag { ref name, .. }
+                if ["style", "script", "template"].contains(&name.as_str()) =>
+            {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::EndTag { ref name } if name == "template" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::StartTag {
+                ref name,
+                ref attributes,
+                ..
+            } if name == "input"
+                && attributes
+                    .get("type")
+                    .is_some_and(|value| value.eq_ignore_ascii_case("hidden")) =>
+            {
+                let Token::StartTag {
+                    name, attributes, ..
+                } = token
+                else {
+                    unreachable!();
+                };
+                self.error(ParseError::UnexpectedStartTag);
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "form" => {
+                self.error(ParseError::UnexpectedStartTag);
+                if !self.has_template_on_the_stack() && self.form_element.is_none() {
+                    let form = self.insert_element(name, attributes);
+                    self.form_element = Some(form);
+                    self.open_elements.pop();
+                }
+            }
+            Token::Eof => return self.handle_token_using_rules_for(InsertionMode::InBody, token),
+            _ => {
+                self.error(ParseError::UnexpectedTokenInTable);
+                return self.foster_parent(token);
+            }
+        }
+        None
+    }
+
+    /// Processes `token` using the rules for the "in body" insertion mode with
+    /// foster parenting enabled, so content misnested in a table ends up
+    /// before it.
+    fn foster_parent(&mut self, token: Token) -> Option<TokenizerState> {
+        self.foster_parenting = true;
+        let state = self.handle_token_using_rules_for(InsertionMode::InBody, token);
+        self.foster_parenting = false;
+        state
+    }
+
+    fn has_template_on_the_stack(&self) -> bool {
+        self.open_elements
+            .iter()
+            .any(|&id| self.is_html_element(id, &["template"]))
+    }
+
+    fn handle_token_in_table_text(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character('\0') => self.error(ParseError::UnexpectedNullCharacter),
+            Token::Character(c) => self.pending_table_characters.push(c),
+            _ => {
+                let characters = std::mem::take(&mut self.pending_table_characters);
+                if characters.chars().all(is_whitespace) {
+                    for c in characters.chars() {
+                        self.insert_character(c);
+                    }
+                } else {
+                    self.error(ParseError::UnexpectedTokenInTable);
+                    for c in characters.chars() {
+                        self.foster_parent(Token::Character(c));
+                    }
+                }
+                self.switch_to_original_insertion_mode();
+                return self.reprocess(token);
+            }
+        }
+        None
+    }
+
+    /// Closes the caption if there is one in table scope, returning whether it
+    /// did.
+    fn close_the_caption(&mut self) -> bool {
+        if !self.has_an_element_in_table_scope("caption") {
+            self.error(ParseError::ElementNotFoundInTableScope);
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        if !self.current_node_is(&["caption"]) {
+            self.error(ParseError::UnclosedElement);
+        }
+        self.pop_until(&["caption"]);
+        self.clear_the_list_of_active_formatting_elements_up_to_the_last_marker();
+        self.switch_to(InsertionMode::InTable);
+        true
+    }
+
+    fn handle_token_in_caption(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::EndTag { ref name } if name == "caption" => {
+                self.close_the_caption();
+            }
+            Token::StartTag { ref name, .. }
+                if [
+                    "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                if self.close_the_caption() {
+                    return self.reprocess(token);
+                }
+            }
+            Token::EndTag { ref name } if name == "table" => {
+                if self.close_the_caption() {
+                    return self.reprocess(token);
+                }
+            }
+            Token::EndTag { ref name }
+                if [
+                    "body", "col", "colgroup", "html", "tbody", "td", "tfoot", "th", "thead", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            _ => return self.handle_token_using_rules_for(InsertionMode::InBody, token),
+        }
+        None
+    }
+
+    fn handle_token_in_column_group(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag { ref name, .. } if name == "html" => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "col" => {
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+            }
+            Token::EndTag { ref name } if name == "colgroup" => {
+                if self.current_node_is(&["colgroup"]) {
+                    self.open_elements.pop();
+                    self.switch_to(InsertionMode::InTable);
+                } else {
+                    self.error(ParseError::UnexpectedEndTag);
+                }
+            }
+            Token::EndTag { ref name } if name == "col" => {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            Token::StartTag { ref name, .. } | Token::EndTag { ref name } if name == "template" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::Eof => return self.handle_token_using_rules_for(InsertionMode::InBody, token),
+            _ => {
+                if self.current_node_is(&["colgroup"]) {
+                    self.open_elements.pop();
+                    self.switch_to(InsertionMode::InTable);
+                    return self.reprocess(token);
+                }
+                self.error(ParseError::UnexpectedTokenInTable);
+            }
+        }
+        None
+    }
+
+    fn handle_token_in_table_body(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "tr" => {
+                self.clear_the_stack_back_to_a_table_body_context();
+                self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InRow);
+            }
+            Token::StartTag { ref name, .. } if ["td", "th"].contains(&name.as_str()) => {
+                self.error(ParseError::UnexpectedStartTag);
+                self.clear_the_stack_back_to_a_table_body_context();
+                self.insert_element("tr".to_string(), Attributes::new());
+                self.switch_to(InsertionMode::InRow);
+                return self.reprocess(token);
+            }
+            Token::EndTag { ref name } if ["tbody", "tfoot", "thead"].contains(&name.as_str()) => {
+                if self.has_an_element_in_table_scope(name) {
+                    self.clear_the_stack_back_to_a_table_body_context();
+                    self.open_elements.pop();
+                    self.switch_to(InsertionMode::InTable);
+                } else {
+                    self.error(ParseError::ElementNotFoundInTableScope);
+                }
+            }
+            Token::StartTag { ref name, .. }
+                if ["caption", "col", "colgroup", "tbody", "tfoot", "thead"]
+                    .contains(&name.as_str()) =>
+            {
+                return self.close_the_table_body(token);
+            }
+            Token::EndTag { ref name } if name == "table" => {
+                return self.close_the_table_body(token);
+            }
+            Token::EndTag { ref name }
+                if [
+                    "body", "caption", "col", "colgroup", "html", "td", "th", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            _ => return self.handle_token_using_rules_for(InsertionMode::InTable, token),
+        }
+        None
+    }
+
+    /// Closes the open `tbody`, `thead` or `tfoot` and reprocesses `token` in
+    /// the table.
+    fn close_the_table_body(&mut self, token: Token) -> Option<TokenizerState> {
+        if !self.has_an_element_in_specific_scope(&["tbody", "thead", "tfoot"], Scope::Table) {
+            self.error(ParseError::ElementNotFoundInTableScope);
+            return None;
+        }
+        self.clear_the_stack_back_to_a_table_body_context();
+        self.open_elements.pop();
+        self.switch_to(InsertionMode::InTable);
+        self.reprocess(token)
+    }
+
+    /// Closes the open `tr`, returning whether there was one in table scope.
+    fn close_the_row(&mut self) -> bool {
+        if !self.has_an_element_in_table_scope("tr") {
+            self.error(ParseError::ElementNotFoundInTableScope);
+            return false;
+        }
+        self.clear_the_stack_back_to_a_table_row_context();
+        self.open_elements.pop();
+        self.switch_to(InsertionMode::InTableBody);
+        true
+    }
+
+    fn handle_token_in_row(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::StartTag {
+                name, attributes, ..
+            } if ["td", "th"].contains(&name.as_str()) => {
+                self.clear_the_stack_back_to_a_table_row_context();
+                self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InCell);
+                self.insert_a_marker();
+            }
+            Token::EndTag { ref name } if name == "tr" => {
+                self.close_the_row();
+            }
+            Token::StartTag { ref name, .. }
+                if [
+                    "caption", "col", "colgroup", "tbody", "tfoot", "thead", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                if self.close_the_row() {
+                    return self.reprocess(token);
+                }
+            }
+            Token::EndTag { ref name } if name == "table" => {
+                if self.close_the_row() {
+                    return self.reprocess(token);
+                }
+            }
+            Token::EndTag { ref name } if ["tbody", "tfoot", "thead"].contains(&name.as_str()) => {
+                if !self.has_an_element_in_table_scope(name) {
+                    self.error(ParseError::ElementNotFoundInTableScope);
+                } else if self.close_the_row() {
+                    return self.reprocess(token);
+                }
+            }
+            Token::EndTag { ref name }
+                if ["body", "caption", "col", "colgroup", "html", "td", "th"]
+                    .contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            _ => return self.handle_token_using_rules_for(InsertionMode::InTable, token),
+        }
+        None
+    }
+
+    /// Switches the current template insertion mode to `insertion_mode` and
+    /// reprocesses `token` in it.
+    fn switch_the_template_insertion_mode(
+        &mut self,
+        insertion_mode: InsertionMode,
+        token: Token,
+    ) -> Option<TokenizerState> {
+        self.template_insertion_modes.pop();
+        self.template_insertion_modes.push(insertion_mode);
+        self.switch_to(insertion_mode);
+        self.reprocess(token)
+    }
+
+    fn handle_token_in_template(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character(_) | Token::Comment(_) | Token::Doctype { .. } => {
+                self.handle_token_using_rules_for(InsertionMode::InBody, token)
+            }
+            Token::StartTag { ref name, .. }
+                if [
+                    "base", "basefont", "bgsound", "link", "meta", "noframes", "script", "style",
+                    "template", "title",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                self.handle_token_using_rules_for(InsertionMode::InHead, token)
+            }
+            Token::EndTag { ref name } if name == "template" => {
+                self.handle_token_using_rules_for(InsertionMode::InHead, token)
+            }
+            Token::StartTag { ref name, .. }
+                if ["caption", "colgroup", "tbody", "tfoot", "thead"].contains(&name.as_str()) =>
+            {
+                self.switch_the_template_insertion_mode(InsertionMode::InTable, token)
+            }
+            Token::StartTag { ref name, .. } if name == "col" => {
+                self.switch_the_template_insertion_mode(InsertionMode::InColumnGroup, token)
+            }
+            Token::StartTag { ref name, .. } if name == "tr" => {
+                self.switch_the_template_insertion_mode(InsertionMode::InTableBody, token)
+            }
+            Token::StartTag { ref name, .. } if ["td", "th"].contains(&name.as_str()) => {
+                self.switch_the_template_insertion_mode(InsertionMode::InRow, token)
+            }
+            Token::StartTag { .. } => {
+                self.switch_the_template_insertion_mode(InsertionMode::InBody, token)
+            }
+            Token::EndTag { .. } => {
+                self.error(ParseError::UnexpectedEndTag);
+                None
+            }
+            Token::Eof => {
+                if !self.has_template_on_the_stack() {
+                    return None;
+                }
+                self.error(ParseError::UnclosedElementAtEof);
+                self.pop_until(&["template"]);
+                self.clear_the_list_of_active_formatting_elements_up_to_the_last_marker();
+                self.template_insertion_modes.pop();
+                self.reset_the_insertion_mode_appropriately();
+                self.reprocess(token)
+            }
+        }
+    }
+
+    fn handle_token_in_select(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character('\0') => self.error(ParseError::UnexpectedNullCharacter),
+            Token::Character(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag { ref name, .. } if name == "html" => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "option" => {
+                if self.current_node_is(&["option"]) {
+                    self.open_elements.pop();
+                }
+                self.insert_element(name, attributes);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["optgroup", "hr"].contains(&name.as_str()) => {
+                if self.current_node_is(&["option"]) {
+                    self.open_elements.pop();
+                }
+                if self.current_node_is(&["optgroup"]) {
+                    self.open_elements.pop();
+                }
+                let is_hr = name == "hr";
+                self.insert_element(name, attributes);
+                if is_hr {
+                    self.open_elements.pop();
+                }
+            }
+            Token::EndTag { ref name } if name == "optgroup" => {
+                let len = self.open_elements.len();
+                if self.current_node_is(&["option"])
+                    && self.is_html_element(self.open_elements[len - 2], &["optgroup"])
+                {
+                    self.open_elements.pop();
+                }
+                if self.current_node_is(&["optgroup"]) {
+                    self.open_elements.pop();
+                } else {
+                    self.error(ParseError::UnexpectedEndTag);
+                }
+            }
+            Token::EndTag { ref name } if name == "option" => {
+                if self.current_node_is(&["option"]) {
+                    self.open_elements.pop();
+                } else {
+                    self.error(ParseError::UnexpectedEndTag);
+                }
+            }
+            Token::EndTag { ref name } if name == "select" => {
+                if !self.close_the_select() {
+                    self.error(ParseError::ElementNotFoundInScope);
+                }
+            }
+            Token::StartTag { ref name, .. } if name == "select" => {
+                self.error(ParseError::UnexpectedStartTag);
+                self.close_the_select();
+            }
+            Token::StartTag { ref name, .. }
+                if ["input", "keygen", "textarea"].contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedStartTag);
+                if self.close_the_select() {
+                    return self.reprocess(token);
+                }
+            }
+            Token::StartTag { ref name, .. } if ["script", "template"].contains(&name.as_str()) => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::EndTag { ref name } if name == "template" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::Eof => return self.handle_token_using_rules_for(InsertionMode::InBody, token),
+            _ => self.error(ParseError::UnexpectedTokenInSelect),
+        }
+        None
+    }
+
+    /// Closes the select if there is one in select scope, returning whether it
+    /// did. Without one, which only happens when parsing a fragment, the
+    /// callers report their own error and ignore the token.
+    fn close_the_select(&mut self) -> bool {
+        if !self.has_an_element_in_specific_scope(&["select"], Scope::Select) {
+            return false;
+        }
+        self.pop_until(&["select"]);
+        self.reset_the_insertion_mode_appropriately();
+        true
+    }
+
+    fn handle_token_in_select_in_table(&mut self, token: Token) -> Option<TokenizerState> {
+        const TABLE_ELEMENTS: [&str; 8] = [
+            "caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th",
+        ];
+        match token {
+            Token::StartTag { ref name, .. } if TABLE_ELEMENTS.contains(&name.as_str()) => {
+                self.error(ParseError::UnexpectedStartTag);
+                self.pop_until(&["select"]);
+                self.reset_the_insertion_mode_appropriately();
+                self.reprocess(token)
+            }
+            Token::EndTag { ref name } if TABLE_ELEMENTS.contains(&name.as_str()) => {
+                self.error(ParseError::UnexpectedEndTag);
+                if !self.has_an_element_in_table_scope(name) {
+                    return None;
+                }
+                self.pop_until(&["select"]);
+                self.reset_the_insertion_mode_appropriately();
+                self.reprocess(token)
+            }
+            _ => self.handle_token_using_rules_for(InsertionMode::InSelect, token),
+        }
+    }
+
+    fn handle_token_in_frameset(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag { ref name, .. } if name == "html" => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "frameset" => {
+                self.insert_element(name, attributes);
+            }
+            Token::EndTag { ref name } if name == "frameset" => {
+                if self.open_elements.len() <= 2 {
+                    self.error(ParseError::UnexpectedEndTag);
+                    return None;
+                }
+                self.open_elements.pop();
+                if !self.is_fragment && !self.current_node_is(&["frameset"]) {
+                    self.switch_to(InsertionMode::AfterFrameset);
+                }
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "frame" => {
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
+            }
+            Token::StartTag { ref name, .. } if name == "noframes" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::Eof => {
+                if self.open_elements.len() > 2 {
+                    self.error(ParseError::UnclosedElementAtEof);
+                }
+            }
+            _ => self.error(ParseError::UnexpectedTokenInFrameset),
+        }
+        None
+    }
+
+    fn handle_token_after_frameset(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag { ref name, .. } if name == "html" => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::EndTag { ref name } if name == "html" => {
+                self.switch_to(InsertionMode::AfterAfterFrameset);
+            }
+            Token::StartTag { ref name, .. } if name == "noframes" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::Eof => (),
+            _ => self.error(ParseError::UnexpectedTokenInFrameset),
+        }
+        None
+    }
+
+    fn handle_token_after_after_frameset(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Comment(text) => {
+                self.insert_comment_at(text, InsertionLocation::LastChildOf(self.document));
+            }
+            Token::Doctype { .. } => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::Character(c) if is_whitespace(c) => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::StartTag { ref name, .. } if name == "html" => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::StartTag { ref name, .. } if name == "noframes" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::Eof => (),
+            _ => self.error(ParseError::UnexpectedTokenInFrameset),
+        }
+        None
+    }
+
+    fn close_the_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        if !self.current_node_is(&["td", "th"]) {
+            self.error(ParseError::UnclosedElement);
+        }
+        self.pop_until(&["td", "th"]);
+        self.clear_the_list_of_active_formatting_elements_up_to_the_last_marker();
+        self.switch_to(InsertionMode::InRow);
+    }
+
+    fn handle_token_in_cell(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::EndTag { ref name } if ["td", "th"].contains(&name.as_str()) => {
+                if !self.has_an_element_in_table_scope(name) {
+                    self.error(ParseError::ElementNotFoundInTableScope);
+                    return None;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&[name]) {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.pop_until(&[name]);
+                self.clear_the_list_of_active_formatting_elements_up_to_the_last_marker();
+                self.switch_to(InsertionMode::InRow);
+            }
+            Token::StartTag { ref name, .. }
+                if [
+                    "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                if self.has_an_element_in_specific_scope(&["td", "th"], Scope::Table) {
+                    self.close_the_cell();
+                    return self.reprocess(token);
+                }
+                self.error(ParseError::ElementNotFoundInTableScope);
+            }
+            Token::EndTag { ref name }
+                if ["body", "caption", "col", "colgroup", "html"].contains(&name.as_str()) =>
+            {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            Token::EndTag { ref name }
+                if ["table", "tbody", "tfoot", "thead", "tr"].contains(&name.as_str()) =>
+            {
+                if self.has_an_element_in_table_scope(name) {
+                    self.close_the_cell();
+                    return self.reprocess(token);
+                }
+                self.error(ParseError::ElementNotFoundInTableScope);
+            }
+            _ => return self.handle_token_using_rules_for(InsertionMode::InBody, token),
+        }
         None
     }
 
-    fn reconstruct_the_active_formatting_elements(&mut self) {
-        if self.active_formatting_elements.is_empty() {
-            return;
-        }
+    fn reconstruct_the_active_formatting_elements(&mut self) {
+        let Some(&Some(last)) = self.active_formatting_elements.last() else {
+            return;
+        };
+        if self.open_elements.contains(&last) {
+            return;
+        }
+
+        let mut first = self.active_formatting_elements.len() - 1;
+        while first > 0 {
+            match self.active_formatting_elements[first - 1] {
+                Some(entry) if !self.open_elements.contains(&entry) => first -= 1,
+                _ => break,
+            }
+        }
+        for i in first..self.active_formatting_elements.len() {
+            let Some(entry) = self.active_formatting_elements[i] else {
+                unreachable!();
+            };
+            let node = self.clone_element(entry);
+            let element = self.insert(node, false);
+            self.active_formatting_elements[i] = Some(element);
+        }
+    }
+
+    /// A fresh HTML element with the same name and attributes as `element`,
+    /// standing in for the token `element` was created for.
+    fn clone_element(&self, element: NodeId) -> DomNode {
+        DomNode::new(self.arena[element].node_type.clone(), Namespace::Html)
+    }
+
+    /// The last formatting element named `name` after the last marker.
+    fn active_formatting_element(&self, name: &str) -> Option<NodeId> {
+        self.active_formatting_elements
+            .iter()
+            .rev()
+            .map_while(|&entry| entry)
+            .find(|&id| self.is_html_element(id, &[name]))
+    }
+
+    fn remove_from_active_formatting_elements(&mut self, element: NodeId) {
+        self.active_formatting_elements
+            .retain(|&entry| entry != Some(element));
+    }
+
+    fn push_onto_the_list_of_active_formatting_elements(&mut self, element: NodeId) {
+        let identical: Vec<NodeId> = self
+            .active_formatting_elements
+            .iter()
+            .rev()
+            .map_while(|&entry| entry)
+            .filter(|&id| *self.arena[id] == *self.arena[element])
+            .collect();
+        // Noah's Ark clause: keep at most three identical elements.
+        if identical.len() >= 3 {
+            self.remove_from_active_formatting_elements(*identical.last().unwrap());
+        }
+        self.active_formatting_elements.push(Some(element));
+    }
+
+    fn adoption_agency(&mut self, subject: &str) {
+        let current_node = self.current_node();
+        if self.is_html_element(current_node, &[subject])
+            && !self
+                .active_formatting_elements
+                .contains(&Some(current_node))
+        {
+            self.open_elements.pop();
+            return;
+        }
+
+        for _ in 0..8 {
+            let Some(formatting_element) = self.active_formatting_element(subject) else {
+                self.any_other_end_tag_in_body(subject);
+                return;
+            };
+            let Some(formatting_element_index) = self
+                .open_elements
+                .iter()
+                .position(|&id| id == formatting_element)
+            else {
+                self.error(ParseError::UnclosedFormattingElement);
+                self.remove_from_active_formatting_elements(formatting_element);
+                return;
+            };
+            if !self.has_in_specific_scope(|id| id == formatting_element, Scope::Default) {
+                self.error(ParseError::ElementNotFoundInScope);
+                return;
+            }
+            if formatting_element != self.current_node() {
+                self.error(ParseError::UnclosedFormattingElement);
+            }
+
+            let Some(furthest_block_index) = (formatting_element_index + 1
+                ..self.open_elements.len())
+                .find(|&i| self.is_special(self.open_elements[i]))
+            else {
+                self.open_elements.truncate(formatting_element_index);
+                self.remove_from_active_formatting_elements(formatting_element);
+                return;
+            };
+            let furthest_block = self.open_elements[furthest_block_index];
+            let common_ancestor = self.open_elements[formatting_element_index - 1];
+
+            // Where the formatting element's replacement goes in the list.
+            enum Bookmark {
+                Replace(NodeId),
+                After(NodeId),
+            }
+            let mut bookmark = Bookmark::Replace(formatting_element);
+
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.open_elements[node_index];
+                if node == formatting_element {
+                    break;
+                }
+                if inner_loop_counter > 3 {
+                    self.remove_from_active_formatting_elements(node);
+                }
+                let Some(entry) = self
+                    .active_formatting_elements
+                    .iter()
+                    .position(|&entry| entry == Some(node))
+                else {
+                    self.open_elements.remove(node_index);
+                    continue;
+                };
+
+                let element = self.clone_element(node);
+                let element = self.arena.push(element);
+                self.active_formatting_elements[entry] = Some(element);
+                self.open_elements[node_index] = element;
+                if last_node == furthest_block {
+                    bookmark = Bookmark::After(element);
+                }
+                self.arena.append_node(element, last_node);
+                last_node = element;
+            }
 
-        let Some(Some(entry)) = self.active_formatting_elements.last() else {
-            return;
-        };
-        if self.open_elements.contains(entry) {
-            return;
-        }
+            let location = self.appropriate_place_for_inserting_a_node(Some(common_ancestor));
+            self.move_to(location, last_node);
+
+            let element = self.clone_element(formatting_element);
+            let element = self.arena.push(element);
+            while let Some(child) = self.arena[furthest_block].child() {
+                self.arena.append_node(element, child);
+            }
+            self.arena.append_node(furthest_block, element);
+
+            match bookmark {
+                Bookmark::Replace(entry) => {
+                    let i = self
+                        .active_formatting_elements
+                        .iter()
+                        .position(|&e| e == Some(entry))
+                        .unwrap();
+                    self.active_formatting_elements[i] = Some(element);
+                }
+                Bookmark::After(entry) => {
+                    self.remove_from_active_formatting_elements(formatting_element);
+                    let i = self
+                        .active_formatting_elements
+                        .iter()
+                        .position(|&e| e == Some(entry))
+                        .unwrap();
+                    self.active_formatting_elements.insert(i + 1, Some(element));
+                }
+            }
 
-        unimplemented!("{:?}", entry);
+            self.open_elements.retain(|&id| id != formatting_element);
+            let i = self
+                .open_elements
+                .iter()
+                .position(|&id| id == furthest_block)
+                .unwrap();
+            self.open_elements.insert(i + 1, element);
+        }
     }
 
     fn handle_token_after_head(&mut self, token: Token) -> Option<TokenizerState> {
@@ -483,8 +2196,7 @@ impl TreeConstructor {
             Token::Comment(text) => self.insert_comment(text),
             Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
             Token::StartTag { ref name, .. } if name == "html" => {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
             Token::StartTag {
                 name, attributes, ..
@@ -495,14 +2207,27 @@ impl TreeConstructor {
             }
             Token::StartTag { ref name, .. }
                 if [
-                    "frameset", "base", "basefont", "bgsound", "link", "meta", "noframes",
-                    "script", "style", "template", "title",
+                    "base", "basefont", "bgsound", "link", "meta", "noframes", "script", "style",
+                    "template", "title",
                 ]
                 .contains(&name.as_str()) =>
             {
-                unimplemented!("{:?}", name);
+                self.error(ParseError::UnexpectedStartTag);
+                let head = self.head_element.unwrap();
+                self.open_elements.push(head);
+                let state = self.handle_token_using_rules_for(InsertionMode::InHead, token);
+                self.open_elements.retain(|&id| id != head);
+                return state;
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if name == "frameset" => {
+                self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InFrameset);
+            }
+            Token::EndTag { ref name, .. } if name == "template" => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
             }
-            Token::EndTag { ref name, .. } if name == "template" => unimplemented!("{:?}", name),
             Token::StartTag { ref name, .. } if name == "head" => {
                 self.error(ParseError::UnexpectedHeadTag);
             }
@@ -510,9 +2235,9 @@ impl TreeConstructor {
                 self.error(ParseError::UnexpectedEndTag);
             }
             _ => {
-                self.insert_element("body".to_string(), HashMap::new());
+                self.insert_element("body".to_string(), Attributes::new());
                 self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.reprocess(token);
             }
         }
         None
@@ -525,7 +2250,7 @@ impl TreeConstructor {
     fn parse_generic_raw_text_element(
         &mut self,
         name: String,
-        attributes: HashMap<String, String>,
+        attributes: Attributes,
     ) -> Option<TokenizerState> {
         self.insert_element(name, attributes);
         self.set_original_insertion_mode();
@@ -537,7 +2262,7 @@ impl TreeConstructor {
     fn parse_generic_rcdata_element(
         &mut self,
         name: String,
-        attributes: HashMap<String, String>,
+        attributes: Attributes,
     ) -> Option<TokenizerState> {
         self.insert_element(name, attributes);
         self.set_original_insertion_mode();
@@ -548,9 +2273,7 @@ impl TreeConstructor {
 
     fn handle_token_in_head(&mut self, token: Token) -> Option<TokenizerState> {
         match token {
-            Token::Character(c)
-                if ['\u{0009}', '\u{000a}', '\u{000c}', '\u{000d}', '\u{0020}'].contains(&c) =>
-            {
+            Token::Character(c) if is_whitespace(c) => {
                 self.insert_character(c);
                 None
             }
@@ -563,96 +2286,234 @@ impl TreeConstructor {
                 None
             }
             Token::StartTag { ref name, .. } if name == "html" => {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                self.handle_token_using_rules_for(InsertionMode::InBody, token)
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } if ["base", "basefont", "bgsound", "link", "meta"].contains(&name.as_str()) => {
+                self.insert_element(name, attributes);
+                self.open_elements.pop();
                 None
             }
             Token::StartTag {
                 name, attributes, ..
-            } if ["noframes", "style"].contains(&name.as_str()) => {
+            } if name == "title" => self.parse_generic_rcdata_element(name, attributes),
+            Token::StartTag {
+                name, attributes, ..
+            } if ["noframes", "style"].contains(&name.as_str())
+                || (name == "noscript" && self.scripting) =>
+            {
                 self.parse_generic_raw_text_element(name, attributes)
             }
             Token::StartTag {
                 name, attributes, ..
-            } if ["base", "basefont", "bgsound", "link"].contains(&name.as_str()) => {
+            } if name == "noscript" => {
                 self.insert_element(name, attributes);
+                self.switch_to(InsertionMode::InHeadNoScript);
                 None
             }
             Token::StartTag {
                 name, attributes, ..
-            } if name == "meta" => {
+            } if name == "script" => {
                 self.insert_element(name, attributes);
+                self.set_original_insertion_mode();
+                self.switch_to(InsertionMode::Text);
+                Some(TokenizerState::ScriptData)
+            }
+            Token::EndTag { ref name, .. } if name == "head" => {
                 self.open_elements.pop();
-                // TODO
+                self.switch_to(InsertionMode::AfterHead);
                 None
             }
             Token::StartTag {
                 name, attributes, ..
-            } if name == "title" => self.parse_generic_rcdata_element(name, attributes),
-            Token::StartTag { name, .. }
-                if ["noscript", "script", "head", "template"].contains(&name.as_str()) =>
-            {
-                unimplemented!("{:?}", name);
+            } if name == "template" => {
+                self.insert_element(name, attributes);
+                self.insert_a_marker();
+                self.frameset_ok = false;
+                self.switch_to(InsertionMode::InTemplate);
+                self.template_insertion_modes
+                    .push(InsertionMode::InTemplate);
+                None
             }
-            Token::StartTag { name, .. } if &name == "head" => {
-                self.error(ParseError::UnexpectedHeadTag);
+            Token::EndTag { ref name } if name == "template" => {
+                if !self.has_template_on_the_stack() {
+                    self.error(ParseError::UnexpectedEndTag);
+                    return None;
+                }
+                self.generate_all_implied_end_tags_thoroughly();
+                if !self.current_node_is(&["template"]) {
+                    self.error(ParseError::UnclosedElement);
+                }
+                self.pop_until(&["template"]);
+                self.clear_the_list_of_active_formatting_elements_up_to_the_last_marker();
+                self.template_insertion_modes.pop();
+                self.reset_the_insertion_mode_appropriately();
                 None
             }
-            Token::EndTag { ref name, .. } if name == "head" => {
-                self.open_elements.pop();
-                self.switch_to(InsertionMode::AfterHead);
+            Token::StartTag { ref name, .. } if name == "head" => {
+                self.error(ParseError::UnexpectedHeadTag);
                 None
             }
-            Token::EndTag { .. } => {
+            Token::EndTag { ref name } if !["body", "html", "br"].contains(&name.as_str()) => {
                 self.error(ParseError::UnexpectedEndTag);
                 None
             }
             _ => {
                 self.open_elements.pop();
                 self.switch_to(InsertionMode::AfterHead);
-                self.handle_token(token);
-                None
+                self.reprocess(token)
             }
         }
     }
 
-    fn appropriate_place_for_inserting_a_node(&self) -> NodeId {
-        self.current_node()
+    fn handle_token_in_head_noscript(&mut self, token: Token) -> Option<TokenizerState> {
+        match token {
+            Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
+            Token::StartTag { ref name, .. } if name == "html" => {
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
+            }
+            Token::EndTag { ref name } if name == "noscript" => {
+                self.open_elements.pop();
+                self.switch_to(InsertionMode::InHead);
+            }
+            Token::Character(c) if is_whitespace(c) => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::Comment(_) => {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::StartTag { ref name, .. }
+                if ["basefont", "bgsound", "link", "meta", "noframes", "style"]
+                    .contains(&name.as_str()) =>
+            {
+                return self.handle_token_using_rules_for(InsertionMode::InHead, token);
+            }
+            Token::StartTag { ref name, .. } if ["head", "noscript"].contains(&name.as_str()) => {
+                self.error(ParseError::UnexpectedStartTag);
+            }
+            Token::EndTag { ref name } if name != "br" => {
+                self.error(ParseError::UnexpectedEndTag);
+            }
+            _ => {
+                self.error(ParseError::UnexpectedTokenInNoscript);
+                self.open_elements.pop();
+                self.switch_to(InsertionMode::InHead);
+                return self.reprocess(token);
+            }
+        }
+        None
     }
 
-    fn insert_position(&self) -> NodeId {
-        if let Some(position) = self.position {
-            position
+    fn appropriate_place_for_inserting_a_node(
+        &self,
+        override_target: Option<NodeId>,
+    ) -> InsertionLocation {
+        let target = override_target.unwrap_or(self.current_node());
+        let location = if !self.foster_parenting
+            || !self.is_html_element(target, &["table", "tbody", "tfoot", "thead", "tr"])
+        {
+            InsertionLocation::LastChildOf(target)
         } else {
-            self.appropriate_place_for_inserting_a_node()
+            self.foster_parenting_location()
+        };
+
+        match location {
+            InsertionLocation::LastChildOf(parent) => InsertionLocation::LastChildOf(
+                self.arena.template_contents(parent).unwrap_or(parent),
+            ),
+            location => location,
+        }
+    }
+
+    fn foster_parenting_location(&self) -> InsertionLocation {
+        let last_of = |name: &str| {
+            self.open_elements
+                .iter()
+                .rposition(|&id| self.is_html_element(id, &[name]))
+        };
+        let last_template = last_of("template");
+        match last_of("table") {
+            Some(table) if last_template.is_none_or(|template| template < table) => {
+                let table_id = self.open_elements[table];
+                if self.arena[table_id].parent().is_some() {
+                    InsertionLocation::Before(table_id)
+                } else {
+                    InsertionLocation::LastChildOf(self.open_elements[table - 1])
+                }
+            }
+            _ => match last_template {
+                Some(template) => InsertionLocation::LastChildOf(self.open_elements[template]),
+                None => InsertionLocation::LastChildOf(self.open_elements[1]),
+            },
+        }
+    }
+
+    fn insert_at(&mut self, location: InsertionLocation, node: DomNode) -> NodeId {
+        match location {
+            InsertionLocation::LastChildOf(parent) => self.arena.insert_child(parent, node),
+            InsertionLocation::Before(sibling) => self.arena.insert_before(sibling, node),
+        }
+    }
+
+    /// Moves an existing node, along with its children, to `location`.
+    fn move_to(&mut self, location: InsertionLocation, id: NodeId) {
+        match location {
+            InsertionLocation::LastChildOf(parent) => self.arena.append_node(parent, id),
+            InsertionLocation::Before(sibling) => self.arena.insert_before_node(id, sibling),
         }
     }
 
     fn insert_comment(&mut self, text: String) {
-        let insert_position = self.insert_position();
-        let domnode = DomNode::new(
-            DomNodeType::Comment(text),
-            self.arena[insert_position].namespace(),
-        );
-        self.arena.insert_child(insert_position, domnode);
+        let location = self.appropriate_place_for_inserting_a_node(None);
+        self.insert_comment_at(text, location);
     }
 
+    fn insert_comment_at(&mut self, text: String, location: InsertionLocation) {
+        let (InsertionLocation::LastChildOf(parent) | InsertionLocation::Before(parent)) = location;
+        let domnode = DomNode::new(DomNodeType::Comment(text), self.arena[parent].namespace());
+        self.insert_at(location, domnode);
+    }
+
+    /// Appends `c` to the text node right before the insertion location, or
+    /// inserts a new text node if there isn't one.
     fn insert_character(&mut self, c: char) {
-        self.insert(
-            DomNode::new(
-                DomNodeType::Character(c),
-                self.adjusted_current_node_namespace(),
+        let location = self.appropriate_place_for_inserting_a_node(None);
+        let (parent, previous) = match location {
+            InsertionLocation::LastChildOf(parent) => (parent, self.arena.children(parent).last()),
+            InsertionLocation::Before(sibling) => (
+                self.arena[sibling].parent().unwrap(),
+                self.arena[sibling].prev(),
             ),
-            false,
-        );
+        };
+        if parent == DomArena::DOCUMENT_IDX {
+            return;
+        }
+        if let Some(previous) = previous
+            && let DomNodeType::Text(ref mut text) = self.arena[previous].node_type
+        {
+            text.push(c);
+        } else {
+            let node = DomNode::new(
+                DomNodeType::Text(c.to_string()),
+                self.adjusted_current_node_namespace(),
+            );
+            self.insert_at(location, node);
+        }
     }
 
-    fn insert_element(&mut self, name: String, attributes: HashMap<String, String>) -> NodeId {
+    fn insert_element(&mut self, name: String, attributes: Attributes) -> NodeId {
+        self.insert_foreign_element(name, attributes, Namespace::Html)
+    }
+
+    fn insert_foreign_element(
+        &mut self,
+        name: String,
+        attributes: Attributes,
+        namespace: Namespace,
+    ) -> NodeId {
         self.insert(
-            DomNode::new(
-                DomNodeType::Element { name, attributes },
-                self.adjusted_current_node_namespace(),
-            ),
+            DomNode::new(DomNodeType::Element { name, attributes }, namespace),
             false,
         )
     }
@@ -660,7 +2521,7 @@ impl TreeConstructor {
         fn insert_element_with_only_add_to_element_stack(
             &mut self,
             name: String,
-            attributes: HashMap<String, String>,
+            attributes: Attributes,
         ) -> NodeId {
             self.insert(
                 DomNode::new(
@@ -673,14 +2534,20 @@ impl TreeConstructor {
     */
     fn insert(&mut self, node: DomNode, only_add_to_element_stack: bool) -> NodeId {
         let is_element = matches!(node.node_type, DomNodeType::Element { .. });
-        let adjusted_insertion_location = self.appropriate_place_for_inserting_a_node();
+        let is_template = node.namespace() == Namespace::Html
+            && matches!(node.node_type, DomNodeType::Element { ref name, .. } if name == "template");
+        let adjusted_insertion_location = self.appropriate_place_for_inserting_a_node(None);
         let nodeidx = if !only_add_to_element_stack {
-            self.arena.insert_child(adjusted_insertion_location, node)
+            self.insert_at(adjusted_insertion_location, node)
         } else {
             self.arena.push(node)
         };
         if is_element {
             self.open_elements.push(nodeidx);
+            self.arena.set_source_span(nodeidx, self.token_span);
+        }
+        if is_template {
+            self.arena.create_template_contents(nodeidx);
         }
         nodeidx
     }
@@ -694,8 +2561,7 @@ impl TreeConstructor {
             }
             Token::Doctype { .. } => self.error(ParseError::UnexpectedDoctype),
             Token::StartTag { ref name, .. } if name == "html" => {
-                self.switch_to(InsertionMode::InBody);
-                self.handle_token(token);
+                return self.handle_token_using_rules_for(InsertionMode::InBody, token);
             }
             Token::StartTag {
                 name, attributes, ..
@@ -710,10 +2576,10 @@ impl TreeConstructor {
                 self.error(ParseError::UnexpectedEndTag);
             }
             _ => {
-                let head_idx = self.insert_element("head".to_string(), HashMap::new());
+                let head_idx = self.insert_element("head".to_string(), Attributes::new());
                 self.head_element = Some(head_idx);
                 self.switch_to(InsertionMode::InHead);
-                self.handle_token(token);
+                return self.reprocess(token);
             }
         }
         None
@@ -738,15 +2604,355 @@ impl TreeConstructor {
                 self.error(ParseError::UnexpectedEndTag);
             }
             _ => {
-                self.insert_element("html".to_string(), HashMap::new());
+                self.insert_element("html".to_string(), Attributes::new());
                 self.switch_to(InsertionMode::BeforeHead);
-                self.handle_token(token);
+                return self.reprocess(token);
             }
         }
         None
     }
 }
 
+/// Public identifiers that put a document in quirks mode when they start its
+/// DOCTYPE's public identifier.
+const QUIRKS_PUBLIC_ID_PREFIXES: [&str; 55] = [
+    "+//Silmaril//dtd html Pro v0r11 19970101//",
+    "-//AS//DTD HTML 3.0 asWedit + extensions//",
+    "-//AdvaSoft Ltd//DTD HTML 3.0 asWedit + extensions//",
+    "-//IETF//DTD HTML 2.0 Level 1//",
+    "-//IETF//DTD HTML 2.0 Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict Level 1//",
+    "-//IETF//DTD HTML 2.0 Strict Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict//",
+    "-//IETF//DTD HTML 2.0//",
+    "-//IETF//DTD HTML 2.1E//",
+    "-//IETF//DTD HTML 3.0//",
+    "-//IETF//DTD HTML 3.2 Final//",
+    "-//IETF//DTD HTML 3.2//",
+    "-//IETF//DTD HTML 3//",
+    "-//IETF//DTD HTML Level 0//",
+    "-//IETF//DTD HTML Level 1//",
+    "-//IETF//DTD HTML Level 2//",
+    "-//IETF//DTD HTML Level 3//",
+    "-//IETF//DTD HTML Strict Level 0//",
+    "-//IETF//DTD HTML Strict Level 1//",
+    "-//IETF//DTD HTML Strict Level 2//",
+    "-//IETF//DTD HTML Strict Level 3//",
+    "-//IETF//DTD HTML Strict//",
+    "-//IETF//DTD HTML//",
+    "-//Metrius//DTD Metrius Presentational//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 2.0 Tables//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 3.0 Tables//",
+    "-//Netscape Comm. Corp.//DTD HTML//",
+    "-//Netscape Comm. Corp.//DTD Strict HTML//",
+    "-//O'Reilly and Associates//DTD HTML 2.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended 1.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended Relaxed 1.0//",
+    "-//SQ//DTD HTML 2.0 HoTMetaL + extensions//",
+    "-//SoftQuad Software//DTD HoTMetaL PRO 6.0::19990601::extensions to HTML 4.0//",
+    "-//SoftQuad//DTD HoTMetaL PRO 4.0::19971010::extensions to HTML 4.0//",
+    "-//Spyglass//DTD HTML 2.0 Extended//",
+    "-//Sun Microsystems Corp.//DTD HotJava HTML//",
+    "-//Sun Microsystems Corp.//DTD HotJava Strict HTML//",
+    "-//W3C//DTD HTML 3 1995-03-24//",
+    "-//W3C//DTD HTML 3.2 Draft//",
+    "-//W3C//DTD HTML 3.2 Final//",
+    "-//W3C//DTD HTML 3.2//",
+    "-//W3C//DTD HTML 3.2S Draft//",
+    "-//W3C//DTD HTML 4.0 Frameset//",
+    "-//W3C//DTD HTML 4.0 Transitional//",
+    "-//W3C//DTD HTML Experimental 19960712//",
+    "-//W3C//DTD HTML Experimental 970421//",
+    "-//W3C//DTD W3 HTML//",
+    "-//W3O//DTD W3 HTML 3.0//",
+    "-//WebTechs//DTD Mozilla HTML 2.0//",
+    "-//WebTechs//DTD Mozilla HTML//",
+];
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len())
+        .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+}
+
+/// The mode a DOCTYPE without the force-quirks flag puts the document in.
+fn doctype_quirks_mode(
+    name: Option<&str>,
+    public_id: Option<&str>,
+    system_id: Option<&str>,
+) -> QuirksMode {
+    let public = public_id.unwrap_or_default();
+    let is_html_401 = |public: &str| {
+        starts_with_ignore_case(public, "-//W3C//DTD HTML 4.01 Frameset//")
+            || starts_with_ignore_case(public, "-//W3C//DTD HTML 4.01 Transitional//")
+    };
+
+    if name != Some("html")
+        || [
+            "-//W3O//DTD W3 HTML Strict 3.0//EN//",
+            "-/W3C/DTD HTML 4.0 Transitional/EN",
+            "HTML",
+        ]
+        .iter()
+        .any(|id| public.eq_ignore_ascii_case(id))
+        || system_id.is_some_and(|id| {
+            id.eq_ignore_ascii_case("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        })
+        || QUIRKS_PUBLIC_ID_PREFIXES
+            .iter()
+            .any(|prefix| starts_with_ignore_case(public, prefix))
+        || (system_id.is_none() && is_html_401(public))
+    {
+        QuirksMode::Quirks
+    } else if starts_with_ignore_case(public, "-//W3C//DTD XHTML 1.0 Frameset//")
+        || starts_with_ignore_case(public, "-//W3C//DTD XHTML 1.0 Transitional//")
+        || (system_id.is_some() && is_html_401(public))
+    {
+        QuirksMode::LimitedQuirks
+    } else {
+        QuirksMode::NoQuirks
+    }
+}
+
+/// Parses `input` as the contents of `context`, an element of `document`,
+/// the way `innerHTML` does. The returned arena's root is a
+/// `DocumentFragment` holding the parsed nodes.
+pub fn parse_fragment(document: &DomArena, context: NodeId, input: &str) -> DomArena {
+    let preprocessor = InputStreamPreprocessor::from_text(input);
+    let mut tree_constructor = TreeConstructor::for_fragment(document, context);
+    Tokenizer::new(preprocessor, &mut tree_constructor).run();
+    tree_constructor.take_fragment()
+}
+
+/// Where a new node goes in the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InsertionLocation {
+    LastChildOf(NodeId),
+    Before(NodeId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scope {
+    Default,
+    Button,
+    ListItem,
+    Table,
+    Select,
+}
+
+/// HTML elements that bound the default scope. The MathML and SVG elements
+/// that are special do too.
+const SCOPE_ELEMENTS: [&str; 9] = [
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+/// Elements kept on the list of active formatting elements, other than `a`.
+const FORMATTING_ELEMENTS: [&str; 13] = [
+    "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+
+/// HTML elements in the special category.
+const SPECIAL_ELEMENTS: [&str; 83] = [
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "bgsound",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "iframe",
+    "img",
+    "input",
+    "keygen",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "meta",
+    "nav",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "param",
+    "plaintext",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "select",
+    "source",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "wbr",
+    "xmp",
+];
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Elements whose end tags are implied by the next end tag.
+const IMPLIED_END_TAGS: [&str; 10] = [
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+/// HTML start tags that end foreign content.
+const BREAKOUT_ELEMENTS: [&str; 44] = [
+    "b",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "center",
+    "code",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "listing",
+    "menu",
+    "meta",
+    "nobr",
+    "ol",
+    "p",
+    "pre",
+    "ruby",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "strike",
+    "sub",
+    "sup",
+    "table",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+
+fn adjust_mathml_attributes(attributes: &mut Attributes) {
+    for attribute in attributes.iter_mut() {
+        if attribute.local_name == "definitionurl" {
+            attribute.local_name = "definitionURL".to_string();
+        }
+    }
+}
+
+fn adjust_svg_attributes(attributes: &mut Attributes) {
+    for attribute in attributes.iter_mut() {
+        if let Ok(i) =
+            SVG_ATTRIBUTES.binary_search_by_key(&attribute.local_name.as_str(), |&(from, _)| from)
+        {
+            attribute.local_name = SVG_ATTRIBUTES[i].1.to_string();
+        }
+    }
+}
+
+/// Attributes of foreign elements that are in a namespace, with their
+/// prefix and local name.
+const FOREIGN_ATTRIBUTES: [(&str, Option<&str>, &str, Namespace); 11] = [
+    ("xlink:actuate", Some("xlink"), "actuate", Namespace::XLink),
+    ("xlink:arcrole", Some("xlink"), "arcrole", Namespace::XLink),
+    ("xlink:href", Some("xlink"), "href", Namespace::XLink),
+    ("xlink:role", Some("xlink"), "role", Namespace::XLink),
+    ("xlink:show", Some("xlink"), "show", Namespace::XLink),
+    ("xlink:title", Some("xlink"), "title", Namespace::XLink),
+    ("xlink:type", Some("xlink"), "type", Namespace::XLink),
+    ("xml:lang", Some("xml"), "lang", Namespace::Xml),
+    ("xml:space", Some("xml"), "space", Namespace::Xml),
+    ("xmlns", None, "xmlns", Namespace::Xmlns),
+    ("xmlns:xlink", Some("xmlns"), "xlink", Namespace::Xmlns),
+];
+
+fn adjust_foreign_attributes(attributes: &mut Attributes) {
+    for attribute in attributes.iter_mut() {
+        if let Some(&(_, prefix, local_name, namespace)) = FOREIGN_ATTRIBUTES
+            .iter()
+            .find(|&&(name, ..)| name == attribute.local_name)
+        {
+            attribute.namespace = Some(namespace);
+            attribute.prefix = prefix.map(str::to_string);
+            attribute.local_name = local_name.to_string();
+        }
+    }
+}
+
+fn adjust_svg_tag_name(name: &mut String) {
+    if let Ok(i) = SVG_TAG_NAMES.binary_search_by_key(&name.as_str(), |&(from, _)| from) {
+        *name = SVG_TAG_NAMES[i].1.to_string();
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    ['\u{0009}', '\u{000a}', '\u{000c}', '\u{000d}', '\u{0020}'].contains(&c)
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InsertionMode {
     Initial,
@@ -764,6 +2970,8 @@ pub enum InsertionMode {
     InTableBody,
     InRow,
     InCell,
+    InSelect,
+    InSelectInTable,
     InTemplate,
     AfterBody,
     InFrameset,
@@ -772,9 +2980,705 @@ pub enum InsertionMode {
     AfterAfterFrameset,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum QuirksMode {
-    NoQuirks,
-    LimitedQuirks,
-    Quirks,
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::html::ByteStreamDecoder;
+    use crate::html::InputStreamPreprocessor;
+
+    fn parse(html: &str) -> TreeConstructor {
+        let preprocessor =
+            InputStreamPreprocessor::new(ByteStreamDecoder::new(html.as_bytes())).unwrap();
+        let mut tree_constructor = TreeConstructor::new();
+        Tokenizer::new(preprocessor, &mut tree_constructor).run();
+        tree_constructor
+    }
+
+    /// Dumps the tree in the format of html5lib-tests' `#document` sections.
+    fn dump(dom: &DomArena) -> String {
+        fn dump_children(dom: &DomArena, parent: NodeId, depth: usize, out: &mut String) {
+            let indent = "  ".repeat(depth);
+            for child in dom.children(parent) {
+                match dom[child].node_type {
+                    DomNodeType::Document | DomNodeType::DocumentFragment => unreachable!(),
+                    DomNodeType::DocType {
+                        ref name,
+                        ref public_id,
+                        ref system_id,
+                    } => {
+                        if public_id.is_empty() && system_id.is_empty() {
+                            out.push_str(&format!("| {indent}<!DOCTYPE {name}>\n"));
+                        } else {
+                            out.push_str(&format!(
+                                "| {indent}<!DOCTYPE {name} \"{public_id}\" \"{system_id}\">\n"
+                            ));
+                        }
+                    }
+                    DomNodeType::Element {
+                        ref name,
+                        ref attributes,
+                    } => {
+                        let prefix = match dom[child].namespace() {
+                            Namespace::MathMl => "math ",
+                            Namespace::Svg => "svg ",
+                            _ => "",
+                        };
+                        out.push_str(&format!("| {indent}<{prefix}{name}>\n"));
+                        let mut attributes: Vec<String> = attributes
+                            .iter()
+                            .map(|attribute| {
+                                let name = match attribute.namespace {
+                                    Some(_) => format!(
+                                        "{} {}",
+                                        attribute.prefix.as_deref().unwrap_or("xmlns"),
+                                        attribute.local_name
+                                    ),
+                                    None => attribute.local_name.clone(),
+                                };
+                                format!("{name}=\"{}\"", attribute.value)
+                            })
+                            .collect();
+                        attributes.sort();
+                        for attribute in attributes {
+                            out.push_str(&format!("| {indent}  {attribute}\n"));
+                        }
+                    }
+                    DomNodeType::Comment(ref text) => {
+                        out.push_str(&format!("| {indent}<!-- {text} -->\n"));
+                    }
+                    DomNodeType::Text(ref text) => {
+                        out.push_str(&format!("| {indent}\"{text}\"\n"));
+                    }
+                }
+                if let Some(contents) = dom.template_contents(child) {
+                    out.push_str(&format!("| {indent}  content\n"));
+                    dump_children(dom, contents, depth + 2, out);
+                }
+                dump_children(dom, child, depth + 1, out);
+            }
+        }
+
+        let mut out = String::new();
+        dump_children(dom, DomArena::DOCUMENT_IDX, 0, &mut out);
+        out
+    }
+
+    fn assert_tree(html: &str, expected: &str) {
+        assert_eq!(dump(parse(html).dom()), expected.trim_start());
+    }
+
+    #[test]
+    fn test_doctype() {
+        let tree_constructor = parse(
+            "<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\"\n\
+             'http://www.w3.org/TR/html4/strict.dtd'><p>",
+        );
+        let dom = tree_constructor.dom();
+        let doctype = dom.children(DomArena::DOCUMENT_IDX).next().unwrap();
+        assert_eq!(
+            dom[doctype].node_type,
+            DomNodeType::DocType {
+                name: "html".to_string(),
+                public_id: "-//W3C//DTD HTML 4.01//EN".to_string(),
+                system_id: "http://www.w3.org/TR/html4/strict.dtd".to_string(),
+            }
+        );
+        assert_eq!(dom.quirks_mode(), QuirksMode::NoQuirks);
+        assert_eq!(
+            tree_constructor.errors(),
+            [ParseError::NonConformingDoctype]
+        );
+
+        let tree_constructor = parse("<!doctype html system>");
+        assert_eq!(tree_constructor.quirks_mode(), QuirksMode::Quirks);
+        assert_eq!(
+            tree_constructor.errors(),
+            [ParseError::MissingDoctypeSystemIdentifier]
+        );
+    }
+
+    #[test]
+    fn test_quirks_mode() {
+        let quirks_mode = |html: &str| parse(html).quirks_mode();
+        assert_eq!(quirks_mode("<!DOCTYPE html>"), QuirksMode::NoQuirks);
+        assert_eq!(quirks_mode("<p>"), QuirksMode::Quirks);
+        assert_eq!(quirks_mode("<!DOCTYPE svg>"), QuirksMode::Quirks);
+        assert_eq!(
+            quirks_mode("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">"),
+            QuirksMode::Quirks
+        );
+        assert_eq!(
+            quirks_mode(
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \
+                 \"http://www.w3.org/TR/html4/loose.dtd\">"
+            ),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(
+            quirks_mode("<!DOCTYPE html PUBLIC '-//w3c//dtd xhtml 1.0 transitional//en'>"),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(
+            quirks_mode("<!DOCTYPE html PUBLIC \"-//IETF//DTD HTML 2.0//EN\">"),
+            QuirksMode::Quirks
+        );
+    }
+
+    #[test]
+    fn test_implied_table_elements() {
+        assert_tree(
+            "<!DOCTYPE html><table><td>a<td>b<tr><th>c",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <table>
+|       <tbody>
+|         <tr>
+|           <td>
+|             "a"
+|           <td>
+|             "b"
+|         <tr>
+|           <th>
+|             "c"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><table><col><caption>a<td>b</table>c",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <table>
+|       <colgroup>
+|         <col>
+|       <caption>
+|         "a"
+|       <tbody>
+|         <tr>
+|           <td>
+|             "b"
+|     "c"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><table> <tr> </tr> <table>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <table>
+|       " "
+|       <tbody>
+|         <tr>
+|           " "
+|         " "
+|     <table>
+"#,
+        );
+    }
+
+    #[test]
+    fn test_foster_parenting() {
+        let tree_constructor = parse("<!DOCTYPE html><table>a<tr>b</table>c");
+        assert_eq!(
+            dump(tree_constructor.dom()),
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     "ab"
+|     <table>
+|       <tbody>
+|         <tr>
+|     "c"
+"#
+            .trim_start()
+        );
+        assert_eq!(
+            tree_constructor.errors(),
+            [
+                ParseError::UnexpectedTokenInTable,
+                ParseError::UnexpectedTokenInTable
+            ]
+        );
+
+        assert_tree(
+            "<!DOCTYPE html><table><div>a</div><input type=hidden><input><tr><td>b</table>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <div>
+|       "a"
+|     <input>
+|     <table>
+|       <input>
+|         type="hidden"
+|       <tbody>
+|         <tr>
+|           <td>
+|             "b"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_table_in_paragraph() {
+        assert_tree(
+            "<!DOCTYPE html><p><table></table>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <p>
+|     <table>
+"#,
+        );
+        assert_tree(
+            "<p><table></table>",
+            r#"
+| <html>
+|   <head>
+|   <body>
+|     <p>
+|       <table>
+"#,
+        );
+    }
+
+    #[test]
+    fn test_adoption_agency() {
+        assert_tree(
+            "<!DOCTYPE html><b><i>x</b>y</i>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <b>
+|       <i>
+|         "x"
+|     <i>
+|       "y"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><b>1<p>2</b>3</p>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <b>
+|       "1"
+|     <p>
+|       <b>
+|         "2"
+|       "3"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><a>1<div>2<div>3</a>4</div>5</div>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <a>
+|       "1"
+|     <div>
+|       <a>
+|         "2"
+|       <div>
+|         <a>
+|           "3"
+|         "4"
+|       "5"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><a><p>X<a>Y</a>Z</p></a>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <a>
+|     <p>
+|       <a>
+|         "X"
+|       <a>
+|         "Y"
+|       "Z"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_the_active_formatting_elements() {
+        // Only three of the identical `b` elements are reopened.
+        assert_tree(
+            "<!DOCTYPE html><div><b><b><b><b></div>x",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <div>
+|       <b>
+|         <b>
+|           <b>
+|             <b>
+|     <b>
+|       <b>
+|         <b>
+|           "x"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><table><tr><td><i>a</td><td>b</table>c",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <table>
+|       <tbody>
+|         <tr>
+|           <td>
+|             <i>
+|               "a"
+|           <td>
+|             "b"
+|     "c"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_foreign_content() {
+        assert_tree(
+            "<!DOCTYPE html><svg viewbox='0 0 10 10' xlink:href=#a><foreignobject><div>x</div>\
+             </foreignobject><lineargradient/><path/></svg><math definitionurl=u><mi>y</mi>\
+             <annotation-xml encoding=text/html><p>z</p></annotation-xml></math><p>done",
+            r##"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <svg svg>
+|       viewBox="0 0 10 10"
+|       xlink href="#a"
+|       <svg foreignObject>
+|         <div>
+|           "x"
+|       <svg linearGradient>
+|       <svg path>
+|     <math math>
+|       definitionURL="u"
+|       <math mi>
+|         "y"
+|       <math annotation-xml>
+|         encoding="text/html"
+|         <p>
+|           "z"
+|     <p>
+|       "done"
+"##,
+        );
+    }
+
+    #[test]
+    fn test_break_out_of_foreign_content() {
+        let tree_constructor = parse("<!DOCTYPE html><svg><g><![CDATA[a<b]]><p>x");
+        assert_eq!(
+            dump(tree_constructor.dom()),
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <svg svg>
+|       <svg g>
+|         "a<b"
+|     <p>
+|       "x"
+"#
+            .trim_start()
+        );
+        assert_eq!(
+            tree_constructor.errors(),
+            [ParseError::UnexpectedHtmlElementInForeignContent]
+        );
+    }
+
+    #[test]
+    fn test_template() {
+        assert_tree(
+            "<!DOCTYPE html><template><div>a</div></template><p>b",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|     <template>
+|       content
+|         <div>
+|           "a"
+|   <body>
+|     <p>
+|       "b"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><table><template><tr><td>a</template><template><col>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <table>
+|       <template>
+|         content
+|           <tr>
+|             <td>
+|               "a"
+|       <template>
+|         content
+|           <col>
+"#,
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_tree(
+            "<!DOCTYPE html><ul><li>a<li>b<ol><li>c</ul>d<dl><dt>e<dd>f<dt>g</dl>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <ul>
+|       <li>
+|         "a"
+|       <li>
+|         "b"
+|         <ol>
+|           <li>
+|             "c"
+|     "d"
+|     <dl>
+|       <dt>
+|         "e"
+|       <dd>
+|         "f"
+|       <dt>
+|         "g"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_leading_newline() {
+        assert_tree(
+            "<!DOCTYPE html><pre>\na</pre><textarea>\nb</textarea><listing>\n\nc</listing>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <pre>
+|       "a"
+|     <textarea>
+|       "b"
+|     <listing>
+|       "
+c"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        assert_tree(
+            "<!DOCTYPE html><select><option>a<option>b<optgroup><option>c</select>d",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <select>
+|       <option>
+|         "a"
+|       <option>
+|         "b"
+|       <optgroup>
+|         <option>
+|           "c"
+|     "d"
+"#,
+        );
+        assert_tree(
+            "<!DOCTYPE html><table><tr><td><select><td>a",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <table>
+|       <tbody>
+|         <tr>
+|           <td>
+|             <select>
+|           <td>
+|             "a"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_frameset() {
+        assert_tree(
+            "<!DOCTYPE html><div><frameset><frame></frameset><noframes>a</noframes><!--b-->",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <frameset>
+|     <frame>
+|   <noframes>
+|     "a"
+|   <!-- b -->
+"#,
+        );
+    }
+
+    #[test]
+    fn test_text_coalescing() {
+        let tree_constructor =
+            parse("<!DOCTYPE html><p>  a\n b  <!--c-->d</p><table>e<tr>f</table>");
+        assert_eq!(
+            dump(tree_constructor.dom()),
+            r#"| <!DOCTYPE html>
+| <html>
+|   <head>
+|   <body>
+|     <p>
+|       "  a
+ b  "
+|       <!-- c -->
+|       "d"
+|     "ef"
+|     <table>
+|       <tbody>
+|         <tr>
+"#
+        );
+        let dom = tree_constructor.dom();
+        let p = dom
+            .query_selector(DomArena::DOCUMENT_IDX, "p")
+            .unwrap()
+            .unwrap();
+        assert_eq!(dom.children(p).count(), 3);
+    }
+
+    #[test]
+    fn test_noscript() {
+        assert_tree(
+            "<!DOCTYPE html><noscript><link><p>a</noscript><image src=b>",
+            r#"
+| <!DOCTYPE html>
+| <html>
+|   <head>
+|     <noscript>
+|       <link>
+|   <body>
+|     <p>
+|       "a"
+|       <img>
+|         src="b"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        let document =
+            parse("<!DOCTYPE html><form><div></div></form><title></title><table><tr></table><svg>");
+        let dom = document.dom();
+        let assert_fragment = |context: &str, html: &str, expected: &str| {
+            let context = dom[..]
+                .iter()
+                .position(|node| {
+                    matches!(node.node_type(), DomNodeType::Element { name, .. } if name == context)
+                })
+                .unwrap();
+            let fragment = parse_fragment(dom, context, html);
+            assert_eq!(fragment[0].node_type(), &DomNodeType::DocumentFragment);
+            assert_eq!(dump(&fragment), expected.trim_start());
+        };
+
+        let div = dom[..]
+            .iter()
+            .position(|node| matches!(node.node_type(), DomNodeType::Element { name, .. } if name == "div"))
+            .unwrap();
+        let tree_constructor = TreeConstructor::for_fragment(dom, div);
+        for id in [
+            tree_constructor.context_element,
+            tree_constructor.form_element,
+        ] {
+            let node = &tree_constructor.arena[id.unwrap()];
+            assert_eq!(
+                (node.parent(), node.child(), node.next(), node.prev()),
+                (None, None, None, None)
+            );
+        }
+
+        assert_fragment(
+            "div",
+            "<td>a</td><p>b<form><input>",
+            r#"
+| "a"
+| <p>
+|   "b"
+|   <input>
+"#,
+        );
+        assert_fragment(
+            "title",
+            "<b>a</b>&amp;",
+            r#"
+| "<b>a</b>&"
+"#,
+        );
+        assert_fragment(
+            "tr",
+            "<td>a<td>b",
+            r#"
+| <td>
+|   "a"
+| <td>
+|   "b"
+"#,
+        );
+        assert_fragment(
+            "svg",
+            "<circle/><foreignObject><p>a</foreignObject><p>",
+            r#"
+| <svg circle>
+| <svg foreignObject>
+|   <p>
+|     "a"
+|   <p>
+"#,
+        );
+    }
 }
@@ -7,9 +7,10 @@ use crate::css::ComputedValue;
 use crate::css::CssStyle;
 use crate::css::CssomArena;
 use crate::css::Display;
+use crate::css::SelectorTarget;
+use crate::html::Attributes;
 use crate::html::DomArena;
 use crate::html::DomNodeType;
-use std::collections::HashMap;
 use std::ops::Deref;
 use std::ops::DerefMut;
 
@@ -107,6 +108,8 @@ impl RenderArena {
     ) {
         for dom_child_id in dom.children(dom_parent_id) {
             match dom[dom_child_id].node_type {
+                // Templates hold inert contents that are never rendered.
+                DomNodeType::Element { ref name, .. } if name == "template" => {}
                 DomNodeType::Element {
                     ref name,
                     ref attributes,
@@ -122,20 +125,13 @@ impl RenderArena {
                     self.apply_css_style_for(arena_child_id, cssom);
                     self.build_body(dom, dom_child_id, arena_child_id, cssom);
                 }
-                DomNodeType::String(ref s) => {
-                    let mut text_chars = s.trim().chars().collect::<Vec<char>>();
-                    text_chars.dedup_by(|c1, c2| c1.is_whitespace() && c2.is_whitespace());
-                    let text = text_chars
-                        .iter()
-                        .collect::<String>()
-                        .replace(|c: char| c.is_whitespace(), " ");
+                DomNodeType::Text(ref s) => {
+                    let css_style = self[arena_parent_id].css_style.inherit_for_text();
+                    let text = css_style.white_space.unwrap().process(s);
                     if !text.is_empty() {
                         let arena_child_id = self.arena.insert_child(
                             arena_parent_id,
-                            RenderNode::new(
-                                RenderNodeType::Text(text),
-                                self[arena_parent_id].css_style.inherit_for_text(),
-                            ),
+                            RenderNode::new(RenderNodeType::Text(text), css_style),
                         );
                         self.apply_css_style_for(arena_child_id, cssom);
                     }
@@ -146,6 +142,26 @@ impl RenderArena {
     }
 }
 
+impl SelectorTarget for RenderArena {
+    fn parent_element(&self, id: NodeId) -> Option<NodeId> {
+        self[id].parent()
+    }
+
+    fn local_name(&self, id: NodeId) -> Option<&str> {
+        match self[id].node_type() {
+            RenderNodeType::Element { name, .. } => Some(name),
+            RenderNodeType::Text(_) => None,
+        }
+    }
+
+    fn attribute(&self, id: NodeId, name: &str) -> Option<&str> {
+        match self[id].node_type() {
+            RenderNodeType::Element { attributes, .. } => attributes.get(name),
+            RenderNodeType::Text(_) => None,
+        }
+    }
+}
+
 impl Deref for RenderArena {
     type Target = Arena<Node>;
 
@@ -176,7 +192,7 @@ impl RenderNode {
         }
     }
 
-    pub fn body(attributes: HashMap<String, String>) -> Self {
+    pub fn body(attributes: Attributes) -> Self {
         Self {
             node_type: NodeType::Element {
                 name: "body".into(),
@@ -200,7 +216,7 @@ impl RenderNode {
 pub enum RenderNodeType {
     Element {
         name: String,
-        attributes: HashMap<String, String>,
+        attributes: Attributes,
     },
     Text(String),
 }
@@ -0,0 +1,683 @@
+//! Runs the tokenizer and tree construction tests in the formats of
+//! html5lib-tests: `.test` JSON files under `html5lib-tests/tokenizer` and
+//! `.dat` files under `html5lib-tests/tree-construction`.
+//!
+//! Tests known to fail are listed in `html5lib-tests/expected-failures.txt`.
+//! A suite fails when a test fails that isn't listed, or when a listed test
+//! starts passing. Run with `UPDATE_EXPECTED_FAILURES=1` to rewrite the list.
+
+use magnetite::html::*;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/html5lib-tests");
+
+#[test]
+fn tokenizer() {
+    run_suite("tokenizer", "test", run_tokenizer_file);
+}
+
+#[test]
+fn tree_construction() {
+    run_suite("tree-construction", "dat", run_tree_construction_file);
+}
+
+/// The outcome of one test: its id, and the reason it failed if it did.
+type Outcome = (String, Option<String>);
+
+fn run_suite(directory: &str, extension: &str, run_file: fn(&str, &str) -> Vec<Outcome>) {
+    let mut paths: Vec<PathBuf> = fs::read_dir(Path::new(FIXTURES).join(directory))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == extension))
+        .collect();
+    paths.sort();
+
+    let mut failures = BTreeSet::new();
+    for path in paths {
+        let file = path.file_name().unwrap().to_str().unwrap();
+        let outcomes = run_file(
+            &format!("{}/{}", directory, file),
+            &fs::read_to_string(&path).unwrap(),
+        );
+        let passed = outcomes.iter().filter(|(_, f)| f.is_none()).count();
+        println!(
+            "{}/{}: {}/{} passed",
+            directory,
+            file,
+            passed,
+            outcomes.len()
+        );
+        for (id, failure) in outcomes {
+            if let Some(failure) = failure {
+                println!("  FAIL {}\n{}", id, indent(&failure));
+                failures.insert(id);
+            }
+        }
+    }
+
+    let list_path = Path::new(FIXTURES).join("expected-failures.txt");
+    let list = fs::read_to_string(&list_path).unwrap_or_default();
+    let expected: BTreeSet<String> = list
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|line| line.starts_with(&format!("{}/", directory)))
+        .map(str::to_string)
+        .collect();
+
+    if env::var_os("UPDATE_EXPECTED_FAILURES").is_some() {
+        let mut lines: Vec<&str> = list
+            .lines()
+            .filter(|line| !line.starts_with(&format!("{}/", directory)))
+            .collect();
+        lines.extend(failures.iter().map(String::as_str));
+        fs::write(&list_path, lines.join("\n") + "\n").unwrap();
+        return;
+    }
+
+    let unexpected: Vec<&String> = failures.difference(&expected).collect();
+    let fixed: Vec<&String> = expected.difference(&failures).collect();
+    assert!(
+        unexpected.is_empty() && fixed.is_empty(),
+        "unexpected failures: {:#?}\nno longer failing: {:#?}",
+        unexpected,
+        fixed
+    );
+}
+
+fn indent(text: &str) -> String {
+    text.lines().map(|line| format!("    {}\n", line)).collect()
+}
+
+fn preprocessor(input: &str) -> InputStreamPreprocessor {
+    let decoder = ByteStreamDecoder::new(input.as_bytes()).with_transport_charset("utf-8");
+    InputStreamPreprocessor::new(decoder).unwrap()
+}
+
+/// Collects tokens in the output format of the tokenizer tests, without
+/// building a tree.
+struct TokenCollector {
+    initial_state: TokenizerState,
+    output: Vec<Json>,
+    errors: Vec<String>,
+}
+
+impl TokenSink for TokenCollector {
+    fn handle_token(&mut self, token: Token, _: SourceSpan) -> Option<TokenizerState> {
+        let string = |s: &str| Json::String(s.to_string());
+        let optional = |s: Option<String>| s.map_or(Json::Null, Json::String);
+        let token = match token {
+            Token::Doctype {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => vec![
+                string("DOCTYPE"),
+                optional(name),
+                optional(public_id),
+                optional(system_id),
+                Json::Bool(!force_quirks),
+            ],
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing_flag,
+            } => {
+                let mut token = vec![
+                    string("StartTag"),
+                    Json::String(name),
+                    Json::Object(
+                        attributes
+                            .into_iter()
+                            .map(|attribute| (attribute.local_name, Json::String(attribute.value)))
+                            .collect(),
+                    ),
+                ];
+                if self_closing_flag {
+                    token.push(Json::Bool(true));
+                }
+                token
+            }
+            Token::EndTag { name } => vec![string("EndTag"), Json::String(name)],
+            Token::Comment(data) => vec![string("Comment"), Json::String(data)],
+            Token::Character(c) => {
+                if let Some(Json::Array(last)) = self.output.last_mut()
+                    && last[0] == string("Character")
+                    && let Json::String(ref mut data) = last[1]
+                {
+                    data.push(c);
+                    return None;
+                }
+                vec![string("Character"), Json::String(c.to_string())]
+            }
+            Token::Eof => return None,
+        };
+        self.output.push(Json::Array(token));
+        None
+    }
+
+    fn handle_error(&mut self, error: ParseError, _: SourceSpan) {
+        self.errors.push(error.to_string());
+    }
+
+    fn initial_tokenizer_state(&self) -> TokenizerState {
+        self.initial_state
+    }
+}
+
+fn run_tokenizer_file(file: &str, source: &str) -> Vec<Outcome> {
+    let json = Json::parse(source).unwrap_or_else(|e| panic!("{}: {}", file, e));
+    let mut outcomes = Vec::new();
+    for (i, test) in json.get("tests").unwrap().as_array().iter().enumerate() {
+        let double_escaped = test.get("doubleEscaped") == Some(&Json::Bool(true));
+        let unescape = |s: &str| {
+            if double_escaped {
+                Json::parse(&format!("\"{}\"", s.replace('"', "\\\"")))
+                    .ok()
+                    .and_then(|json| json.as_str().map(str::to_string))
+                    .unwrap_or_default()
+            } else {
+                s.to_string()
+            }
+        };
+        let input = unescape(test.get("input").unwrap().as_str().unwrap());
+        let mut expected = test.get("output").unwrap().clone();
+        expected.unescape_strings(&unescape);
+        let expected_errors: Vec<&str> = test
+            .get("errors")
+            .map(|errors| errors.as_array())
+            .unwrap_or_default()
+            .iter()
+            .map(|error| error.get("code").unwrap().as_str().unwrap())
+            .collect();
+
+        let initial_states = match test.get("initialStates") {
+            Some(states) => states
+                .as_array()
+                .iter()
+                .map(|state| state.as_str().unwrap())
+                .collect(),
+            None => vec!["Data state"],
+        };
+        for state_name in initial_states {
+            let id = format!("{}:{}:{}", file, i + 1, state_name);
+            let Some(initial_state) = tokenizer_state(state_name) else {
+                outcomes.push((id, Some(format!("unknown state {}", state_name))));
+                continue;
+            };
+            let mut collector = TokenCollector {
+                initial_state,
+                output: Vec::new(),
+                errors: Vec::new(),
+            };
+            let mut tokenizer = Tokenizer::new(preprocessor(&input), &mut collector);
+            if let Some(Json::String(name)) = test.get("lastStartTag") {
+                tokenizer = tokenizer.with_last_start_tag(name);
+            }
+            tokenizer.run();
+
+            let output = Json::Array(collector.output);
+            let failure = if output != expected {
+                Some(format!(
+                    "{}\ninput:    {:?}\nexpected: {}\nactual:   {}",
+                    test.get("description").unwrap().as_str().unwrap(),
+                    input,
+                    expected,
+                    output
+                ))
+            } else if collector.errors != expected_errors {
+                Some(format!(
+                    "{}\ninput:    {:?}\nexpected errors: {:?}\nactual errors:   {:?}",
+                    test.get("description").unwrap().as_str().unwrap(),
+                    input,
+                    expected_errors,
+                    collector.errors
+                ))
+            } else {
+                None
+            };
+            outcomes.push((id, failure));
+        }
+    }
+    outcomes
+}
+
+fn tokenizer_state(name: &str) -> Option<TokenizerState> {
+    match name {
+        "Data state" => Some(TokenizerState::Data),
+        "PLAINTEXT state" => Some(TokenizerState::PlainText),
+        "RCDATA state" => Some(TokenizerState::RcData),
+        "RAWTEXT state" => Some(TokenizerState::RawText),
+        "Script data state" => Some(TokenizerState::ScriptData),
+        "CDATA section state" => Some(TokenizerState::CDataSection),
+        _ => None,
+    }
+}
+
+/// One test of a `.dat` file, split into its sections.
+struct TreeTest {
+    data: String,
+    /// The lines of the `#errors` and `#new-errors` sections.
+    errors: Vec<String>,
+    fragment_context: Option<String>,
+    scripting: bool,
+    document: String,
+}
+
+fn parse_dat(source: &str) -> Vec<TreeTest> {
+    let source = format!("\n{}", source);
+    let mut tests = Vec::new();
+    for chunk in source.split("\n#data\n").skip(1) {
+        let mut sections: Vec<(&str, Vec<&str>)> = vec![("#data", Vec::new())];
+        for line in chunk.split('\n') {
+            let is_header = [
+                "#errors",
+                "#new-errors",
+                "#document-fragment",
+                "#script-on",
+                "#script-off",
+                "#document",
+            ]
+            .contains(&line);
+            if is_header {
+                sections.push((line, Vec::new()));
+            } else {
+                sections.last_mut().unwrap().1.push(line);
+            }
+        }
+        let section = |name: &str| {
+            sections
+                .iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, lines)| lines.join("\n"))
+        };
+        let errors = ["#errors", "#new-errors"]
+            .into_iter()
+            .filter_map(section)
+            .flat_map(|lines| {
+                lines
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        tests.push(TreeTest {
+            data: section("#data").unwrap(),
+            errors,
+            fragment_context: section("#document-fragment").map(|s| s.trim().to_string()),
+            scripting: section("#script-on").is_some(),
+            document: section("#document")
+                .unwrap_or_default()
+                .trim_end()
+                .to_string(),
+        });
+    }
+    tests
+}
+
+fn run_tree_construction_file(file: &str, source: &str) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for (i, test) in parse_dat(source).into_iter().enumerate() {
+        let id = format!("{}:{}", file, i + 1);
+        let (dom, errors) = match &test.fragment_context {
+            Some(context) => {
+                let (namespace, name) = match context.split_once(' ') {
+                    Some(("svg", name)) => (Namespace::Svg, name),
+                    Some(("math", name)) => (Namespace::MathMl, name),
+                    _ => (Namespace::Html, context.as_str()),
+                };
+                let mut document = DomArena::new();
+                let element = DomNode::new(
+                    DomNodeType::Element {
+                        name: name.to_string(),
+                        attributes: Default::default(),
+                    },
+                    namespace,
+                );
+                let context = document.insert_child(DomArena::DOCUMENT_IDX, element);
+                let mut tree_constructor = TreeConstructor::for_fragment(&document, context);
+                tree_constructor.set_scripting(test.scripting);
+                Tokenizer::new(preprocessor(&test.data), &mut tree_constructor).run();
+                let errors = tree_constructor.diagnostics().to_vec();
+                (tree_constructor.take_fragment(), errors)
+            }
+            None => {
+                let mut tree_constructor = TreeConstructor::new();
+                tree_constructor.set_scripting(test.scripting);
+                Tokenizer::new(preprocessor(&test.data), &mut tree_constructor).run();
+                let errors = tree_constructor.diagnostics().to_vec();
+                (tree_constructor.take_dom(), errors)
+            }
+        };
+        let actual = dump(&dom);
+        let failure = if actual.trim_end() != test.document {
+            Some(format!(
+                "input: {:?}\nexpected:\n{}\nactual:\n{}",
+                test.data, test.document, actual
+            ))
+        } else if !errors_match(&test.errors, &errors) {
+            let errors: Vec<String> = errors.iter().map(Diagnostic::to_string).collect();
+            Some(format!(
+                "input: {:?}\nexpected errors:\n{}\nactual errors:\n{}",
+                test.data,
+                indent(&test.errors.join("\n")),
+                indent(&errors.join("\n"))
+            ))
+        } else {
+            None
+        };
+        outcomes.push((id, failure));
+    }
+    outcomes
+}
+
+/// Compares the lines of the `#errors` sections with the diagnostics, in
+/// order. A line is a code, optionally preceded by the position it was
+/// reported at as `(line,col):` or `(line:col)`; the position is only
+/// compared when it is given.
+fn errors_match(expected: &[String], actual: &[Diagnostic]) -> bool {
+    expected.len() == actual.len()
+        && expected.iter().zip(actual).all(|(line, diagnostic)| {
+            let (position, code) = parse_error_line(line);
+            let start = diagnostic.span.start;
+            code == diagnostic.error.to_string()
+                && position.is_none_or(|position| position == (start.line, start.column))
+        })
+}
+
+fn parse_error_line(line: &str) -> (Option<(usize, usize)>, &str) {
+    let line = line.trim();
+    let Some((position, code)) = line.strip_prefix('(').and_then(|rest| rest.split_once(')'))
+    else {
+        return (None, line);
+    };
+    let position = position
+        .split_once([',', ':'])
+        .and_then(|(line, column)| Some((line.trim().parse().ok()?, column.trim().parse().ok()?)));
+    let code = code.trim_start_matches(':').trim();
+    (position, code)
+}
+
+/// Dumps the tree in the format of the `#document` sections.
+fn dump(dom: &DomArena) -> String {
+    fn dump_children(dom: &DomArena, parent: NodeId, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        for child in dom.children(parent) {
+            match dom[child].node_type() {
+                DomNodeType::Document | DomNodeType::DocumentFragment => unreachable!(),
+                DomNodeType::DocType {
+                    name,
+                    public_id,
+                    system_id,
+                } => {
+                    if public_id.is_empty() && system_id.is_empty() {
+                        out.push_str(&format!("| {indent}<!DOCTYPE {name}>\n"));
+                    } else {
+                        out.push_str(&format!(
+                            "| {indent}<!DOCTYPE {name} \"{public_id}\" \"{system_id}\">\n"
+                        ));
+                    }
+                }
+                DomNodeType::Element { name, attributes } => {
+                    let prefix = match dom[child].namespace() {
+                        Namespace::MathMl => "math ",
+                        Namespace::Svg => "svg ",
+                        _ => "",
+                    };
+                    out.push_str(&format!("| {indent}<{prefix}{name}>\n"));
+                    let mut attributes: Vec<String> = attributes
+                        .iter()
+                        .map(|attribute| {
+                            let name = match attribute.namespace {
+                                Some(_) => format!(
+                                    "{} {}",
+                                    attribute.prefix.as_deref().unwrap_or("xmlns"),
+                                    attribute.local_name
+                                ),
+                                None => attribute.local_name.clone(),
+                            };
+                            format!("{name}=\"{}\"", attribute.value)
+                        })
+                        .collect();
+                    attributes.sort();
+                    for attribute in attributes {
+                        out.push_str(&format!("| {indent}  {attribute}\n"));
+                    }
+                }
+                DomNodeType::Comment(text) => {
+                    out.push_str(&format!("| {indent}<!-- {text} -->\n"));
+                }
+                DomNodeType::Text(text) => out.push_str(&format!("| {indent}\"{text}\"\n")),
+            }
+            if let Some(contents) = dom.template_contents(child) {
+                out.push_str(&format!("| {indent}  content\n"));
+                dump_children(dom, contents, depth + 2, out);
+            }
+            dump_children(dom, child, depth + 1, out);
+        }
+    }
+
+    let mut out = String::new();
+    dump_children(dom, DomArena::DOCUMENT_IDX, 0, &mut out);
+    out
+}
+
+/// Just enough JSON for the tokenizer tests. Objects compare regardless of
+/// the order of their members.
+#[derive(Clone, Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl PartialEq for Json {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Json::Null, Json::Null) => true,
+            (Json::Bool(a), Json::Bool(b)) => a == b,
+            (Json::Number(a), Json::Number(b)) => a == b,
+            (Json::String(a), Json::String(b)) => a == b,
+            (Json::Array(a), Json::Array(b)) => a == b,
+            (Json::Object(a), Json::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(key, value)| b.iter().any(|(k, v)| k == key && v == value))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write!(f, "{:?}", s),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { ", " }, value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    write!(f, "{}{:?}: {}", if i == 0 { "" } else { ", " }, key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl Json {
+    fn parse(source: &str) -> Result<Json, String> {
+        let mut chars = source.chars().peekable();
+        let value = Self::parse_value(&mut chars)?;
+        Self::skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("trailing {:?}", c)),
+        }
+    }
+
+    fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Json, String> {
+        Self::skip_whitespace(chars);
+        match chars.next() {
+            Some('n') => Self::expect(chars, "ull").map(|_| Json::Null),
+            Some('t') => Self::expect(chars, "rue").map(|_| Json::Bool(true)),
+            Some('f') => Self::expect(chars, "alse").map(|_| Json::Bool(false)),
+            Some('"') => Self::parse_string(chars).map(Json::String),
+            Some('[') => {
+                let mut values = Vec::new();
+                Self::skip_whitespace(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(Self::parse_value(chars)?);
+                    Self::skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => (),
+                        Some(']') => return Ok(Json::Array(values)),
+                        c => return Err(format!("expected , or ] but got {:?}", c)),
+                    }
+                }
+            }
+            Some('{') => {
+                let mut members = Vec::new();
+                Self::skip_whitespace(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    Self::skip_whitespace(chars);
+                    if chars.next() != Some('"') {
+                        return Err("expected a key".to_string());
+                    }
+                    let key = Self::parse_string(chars)?;
+                    Self::skip_whitespace(chars);
+                    if chars.next() != Some(':') {
+                        return Err("expected :".to_string());
+                    }
+                    members.push((key, Self::parse_value(chars)?));
+                    Self::skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => (),
+                        Some('}') => return Ok(Json::Object(members)),
+                        c => return Err(format!("expected , or }} but got {:?}", c)),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some(c) = chars.next_if(|c| "+-.eE".contains(*c) || c.is_ascii_digit()) {
+                    number.push(c);
+                }
+                number.parse().map(Json::Number).map_err(|e| e.to_string())
+            }
+            c => Err(format!("unexpected {:?}", c)),
+        }
+    }
+
+    fn expect(chars: &mut std::iter::Peekable<std::str::Chars>, rest: &str) -> Result<(), String> {
+        for expected in rest.chars() {
+            if chars.next() != Some(expected) {
+                return Err(format!("expected {:?}", rest));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the rest of a string after its opening quote. Lone surrogates
+    /// can't be held in a `String` and become U+FFFD.
+    fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+        let mut string = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match chars.next() {
+                    Some('u') => {
+                        let mut code = Self::parse_hex4(chars)?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            let mut lookahead = chars.clone();
+                            if lookahead.next() == Some('\\') && lookahead.next() == Some('u') {
+                                let low = Self::parse_hex4(&mut lookahead)?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                    *chars = lookahead;
+                                }
+                            }
+                        }
+                        string.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some(c) => string.push(c),
+                    None => return Err("unterminated string".to_string()),
+                },
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn parse_hex4(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<u32, String> {
+        let hex: String = chars.take(4).collect();
+        u32::from_str_radix(&hex, 16).map_err(|e| e.to_string())
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    fn unescape_strings(&mut self, unescape: &impl Fn(&str) -> String) {
+        match self {
+            Json::String(s) => *s = unescape(s),
+            Json::Array(values) => values.iter_mut().for_each(|v| v.unescape_strings(unescape)),
+            Json::Object(members) => {
+                for (key, value) in members {
+                    *key = unescape(key);
+                    value.unescape_strings(unescape);
+                }
+            }
+            _ => (),
+        }
+    }
+}